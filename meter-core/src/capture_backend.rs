@@ -0,0 +1,303 @@
+//! 抓包后端：Windows使用WinDivert，其他平台使用libpcap，另有pcap文件回放后端
+
+use crate::pcap::PcapPacket;
+use crate::{MeterError, Result};
+use std::time::Duration;
+
+/// 抓包后端，向捕获循环提供IP数据包
+pub trait CaptureBackend: Send {
+    /// 接收下一个IP数据包；超时或暂时没有数据时返回 `Ok(None)`
+    fn recv(&mut self) -> Result<Option<Vec<u8>>>;
+
+    /// 重新注入最近一次接收的数据包（数据可以被修改）；不拦截流量的后端直接忽略
+    fn send(&mut self, _data: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    /// 使用新的过滤器，失败时保留旧过滤器
+    fn set_filter(&mut self, filter: &str) -> Result<()>;
+
+    /// 交付刚接收的数据包前需要等待的时间（只有按原始间隔回放时才有），由捕获循环异步等待
+    fn take_pacing_delay(&mut self) -> Option<Duration> {
+        None
+    }
+
+    /// 数据源是否已经结束（只有回放后端会结束）
+    fn is_finished(&self) -> bool {
+        false
+    }
+
+    /// 关闭底层句柄
+    fn close(&mut self) {}
+}
+
+/// 按平台打开实时抓包后端（编译时选择）
+pub fn open_live_backend(filter: &str) -> Result<Box<dyn CaptureBackend>> {
+    #[cfg(windows)]
+    {
+        Ok(Box::new(WinDivertBackend::open(filter)?))
+    }
+
+    #[cfg(not(windows))]
+    {
+        Ok(Box::new(PcapBackend::open(filter)?))
+    }
+}
+
+#[cfg(windows)]
+pub use self::windivert_backend::WinDivertBackend;
+
+#[cfg(windows)]
+mod windivert_backend {
+    use super::CaptureBackend;
+    use crate::{MeterError, Result};
+    use std::borrow::Cow;
+    use windivert::address::WinDivertAddress;
+    use windivert::prelude::*;
+
+    // 单个网络层数据包的最大长度
+    const RECV_BUF_SIZE: usize = 64 * 1024;
+    // 接收超时，保证捕获循环能及时检查停止标志和过滤器更新
+    const RECV_TIMEOUT_MS: u32 = 100;
+
+    /// WinDivert网络层后端：拦截数据包，处理后需要重新注入
+    pub struct WinDivertBackend {
+        handle: WinDivert<NetworkLayer>,
+        buffer: Vec<u8>,
+        last_address: Option<WinDivertAddress<NetworkLayer>>,
+    }
+
+    impl WinDivertBackend {
+        pub fn open(filter: &str) -> Result<Self> {
+            // 检查WinDivert是否可用
+            if !crate::utils::is_windivert_installed() {
+                return Err(MeterError::WinDivertError(
+                    "未找到WinDivert驱动。请确保WinDivert64.sys已安装到应用程序目录。".to_string(),
+                ));
+            }
+
+            // 检查管理员权限
+            if !crate::utils::is_admin() {
+                log::warn!("WinDivert需要管理员权限，但当前进程没有管理员权限");
+                return Err(MeterError::WinDivertError(
+                    "WinDivert需要管理员权限。请以管理员身份运行应用程序。".to_string(),
+                ));
+            }
+
+            let handle = Self::open_handle(filter)?;
+            log::info!("WinDivert句柄创建成功，过滤器: {}", filter);
+
+            Ok(Self {
+                handle,
+                buffer: vec![0u8; RECV_BUF_SIZE],
+                last_address: None,
+            })
+        }
+
+        fn open_handle(filter: &str) -> Result<WinDivert<NetworkLayer>> {
+            WinDivert::<NetworkLayer>::network(filter, 0, WinDivertFlags::new())
+                .map_err(|e| MeterError::WinDivertError(format!("创建WinDivert句柄失败: {}", e)))
+        }
+    }
+
+    impl CaptureBackend for WinDivertBackend {
+        fn recv(&mut self) -> Result<Option<Vec<u8>>> {
+            let Some(packet) = self
+                .handle
+                .recv_wait(Some(&mut self.buffer[..]), RECV_TIMEOUT_MS)
+                .map_err(|e| MeterError::WinDivertError(format!("接收数据包失败: {:?}", e)))?
+            else {
+                return Ok(None);
+            };
+            let data = packet.data.to_vec();
+            self.last_address = Some(packet.address);
+            Ok(Some(data))
+        }
+
+        fn send(&mut self, data: &[u8]) -> Result<()> {
+            let Some(address) = self.last_address.take() else {
+                return Ok(());
+            };
+            let packet = WinDivertPacket {
+                address,
+                data: Cow::Borrowed(data),
+            };
+            self.handle
+                .send(&packet)
+                .map(|_| ())
+                .map_err(|e| MeterError::WinDivertError(format!("重新注入数据包失败: {:?}", e)))
+        }
+
+        fn set_filter(&mut self, filter: &str) -> Result<()> {
+            // 接收带超时，捕获循环不必等到匹配旧过滤器的数据包就能走到这里
+            let handle = Self::open_handle(filter)?;
+            let mut old = std::mem::replace(&mut self.handle, handle);
+            if let Err(e) = old.close(CloseAction::Nothing) {
+                log::warn!("关闭旧的WinDivert句柄失败: {:?}", e);
+            }
+            self.last_address = None;
+            Ok(())
+        }
+
+        fn close(&mut self) {
+            if let Err(e) = self.handle.close(CloseAction::Nothing) {
+                log::warn!("关闭WinDivert句柄失败: {:?}", e);
+            }
+        }
+    }
+}
+
+#[cfg(not(windows))]
+pub use self::pcap_backend::PcapBackend;
+
+#[cfg(not(windows))]
+mod pcap_backend {
+    use super::CaptureBackend;
+    use crate::{MeterError, Result};
+
+    // 读超时，保证捕获循环能及时检查停止标志
+    const READ_TIMEOUT_MS: i32 = 100;
+    // 部分平台上 DLT_RAW 的取值
+    const DLT_RAW_VALUES: [i32; 2] = [12, 14];
+
+    /// libpcap后端：只旁路监听，不需要重新注入
+    pub struct PcapBackend {
+        capture: pcap::Capture<pcap::Active>,
+        link_type: u32,
+    }
+
+    impl PcapBackend {
+        /// 打开网卡：过滤器中的 `ifIdx` 子句用于选择网卡，其余部分作为BPF过滤器
+        pub fn open(filter: &str) -> Result<Self> {
+            let if_idx = crate::packet_capture::interface_from_filter(filter);
+            let filter = crate::packet_capture::filter_for_interface(filter, None);
+
+            let device = match if_idx.and_then(interface_name) {
+                Some(name) => pcap::Device::from(name.as_str()),
+                None => pcap::Device::lookup()
+                    .map_err(|e| MeterError::PacketCapture(format!("查找默认网卡失败: {}", e)))?
+                    .ok_or_else(|| MeterError::PacketCapture("没有可用的网卡".to_string()))?,
+            };
+            let device_name = device.name.clone();
+
+            let mut capture = pcap::Capture::from_device(device)
+                .and_then(|capture| {
+                    capture
+                        .promisc(false)
+                        .snaplen(65535)
+                        .timeout(READ_TIMEOUT_MS)
+                        .immediate_mode(true)
+                        .open()
+                })
+                .map_err(|e| MeterError::PacketCapture(format!("打开网卡 {} 失败: {}", device_name, e)))?;
+
+            let datalink = capture.get_datalink().0;
+            let link_type = if DLT_RAW_VALUES.contains(&datalink) {
+                crate::pcap::LINKTYPE_RAW
+            } else {
+                datalink as u32
+            };
+
+            // WinDivert专用的过滤语法无法编译为BPF，此时退回到只过滤TCP
+            if let Err(e) = capture.filter(&filter, true) {
+                log::warn!("过滤器无法用于libpcap（{}），改用 \"tcp\": {}", e, filter);
+                capture
+                    .filter("tcp", true)
+                    .map_err(|e| MeterError::PacketCapture(format!("设置过滤器失败: {}", e)))?;
+            }
+
+            log::info!("libpcap已打开网卡: {}，过滤器: {}", device_name, filter);
+            Ok(Self { capture, link_type })
+        }
+    }
+
+    impl CaptureBackend for PcapBackend {
+        fn recv(&mut self) -> Result<Option<Vec<u8>>> {
+            match self.capture.next_packet() {
+                Ok(packet) => Ok(crate::pcap::strip_link_layer(packet.data, self.link_type).map(|ip| ip.to_vec())),
+                Err(pcap::Error::TimeoutExpired) => Ok(None),
+                Err(e) => Err(MeterError::PacketCapture(format!("接收数据包失败: {}", e))),
+            }
+        }
+
+        fn set_filter(&mut self, filter: &str) -> Result<()> {
+            // 过滤器可能切换了网卡，直接重新打开
+            *self = Self::open(filter)?;
+            Ok(())
+        }
+    }
+
+    fn interface_name(index: u32) -> Option<String> {
+        crate::network::list_network_interfaces()
+            .ok()?
+            .into_iter()
+            .find(|iface| iface.index == index)
+            .map(|iface| iface.name)
+    }
+}
+
+// 按原始间隔回放时单个包间隔的上限，录制中的长时间空闲不会让回放停住
+const MAX_REPLAY_DELAY: Duration = Duration::from_secs(60);
+
+/// pcap文件回放后端，可选按原始包间隔回放
+pub struct ReplayBackend {
+    packets: std::vec::IntoIter<PcapPacket>,
+    realtime: bool,
+    previous: Option<Duration>,
+    pending_delay: Option<Duration>,
+    finished: bool,
+}
+
+impl ReplayBackend {
+    pub fn open(path: &std::path::Path, realtime: bool) -> Result<Self> {
+        let packets = crate::pcap::read_packets(path)?;
+        log::info!(
+            "开始回放pcap文件: {:?}，共{}个数据包{}",
+            path,
+            packets.len(),
+            if realtime { "（按原始间隔）" } else { "" }
+        );
+
+        Ok(Self {
+            packets: packets.into_iter(),
+            realtime,
+            previous: None,
+            pending_delay: None,
+            finished: false,
+        })
+    }
+}
+
+impl CaptureBackend for ReplayBackend {
+    fn recv(&mut self) -> Result<Option<Vec<u8>>> {
+        let Some(packet) = self.packets.next() else {
+            if !self.finished {
+                log::info!("pcap回放结束");
+            }
+            self.finished = true;
+            return Ok(None);
+        };
+
+        if self.realtime {
+            self.pending_delay = self
+                .previous
+                .and_then(|prev| packet.timestamp.checked_sub(prev))
+                .map(|delay| delay.min(MAX_REPLAY_DELAY));
+            self.previous = Some(packet.timestamp);
+        }
+
+        Ok(Some(packet.data))
+    }
+
+    fn set_filter(&mut self, _filter: &str) -> Result<()> {
+        Err(MeterError::PacketCapture("回放模式不支持修改过滤器".to_string()))
+    }
+
+    fn take_pacing_delay(&mut self) -> Option<Duration> {
+        self.pending_delay.take()
+    }
+
+    fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
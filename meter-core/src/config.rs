@@ -1,3 +1,4 @@
+use crate::models::Language;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -9,6 +10,23 @@ pub enum ConfigMode {
     Tauri,
 }
 
+// Config file format, picked from the file extension
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+}
+
+impl ConfigFormat {
+    /// `.toml` files are TOML, anything else is treated as JSON
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => ConfigFormat::Toml,
+            _ => ConfigFormat::Json,
+        }
+    }
+}
+
 // Main application configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppConfig {
@@ -16,6 +34,8 @@ pub struct AppConfig {
     pub web_server: WebServerConfig,
     pub data_manager: DataManagerConfig,
     pub logging: LoggingConfig,
+    #[serde(default)]
+    pub discord: DiscordConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,6 +46,43 @@ pub struct PacketCaptureConfig {
     pub enable_tcp_reassembly: bool,
     pub max_connections: usize,
     pub connection_timeout: u64, // seconds
+    /// Write every captured packet to this pcap file when set
+    #[serde(default)]
+    pub record_pcap: Option<String>,
+    /// Bytes identifying the game server in small notify packets
+    #[serde(default = "default_server_signature")]
+    pub server_signature: Vec<u8>,
+    /// Offset of `server_signature` within each framed packet
+    #[serde(default = "default_signature_offset")]
+    pub signature_offset: usize,
+    /// Exact payload size of the login response used to identify the server
+    #[serde(default = "default_login_response_len")]
+    pub login_response_len: usize,
+    /// Notify service ids accepted by the parser; other services are dropped
+    #[serde(default = "default_service_uuids")]
+    pub service_uuids: Vec<u64>,
+    /// Out-of-order TCP segments kept per connection before the stream is reset
+    #[serde(default = "default_max_tcp_cache_segments")]
+    pub max_tcp_cache_segments: usize,
+    /// Total bytes of out-of-order TCP segments kept per connection
+    #[serde(default = "default_max_tcp_cache_bytes")]
+    pub max_tcp_cache_bytes: usize,
+    /// Bytes of reassembled but not yet framed data kept per connection
+    #[serde(default = "default_max_data_buffer_bytes")]
+    pub max_data_buffer_bytes: usize,
+    /// Largest length prefix accepted when framing game packets; larger ones are treated as corrupt and resynced past
+    #[serde(default = "default_max_packet_size")]
+    pub max_packet_size: usize,
+    /// Recent game packets kept in memory for `/api/debug/packets`; 0 (default) disables recording.
+    /// Payloads may contain account-related data, so only enable this while collecting a bug report.
+    #[serde(default)]
+    pub debug_packet_capacity: usize,
+    /// IP packets awaiting missing fragments; the oldest is evicted beyond this
+    #[serde(default = "default_max_fragment_entries")]
+    pub max_fragment_entries: usize,
+    /// Fragments accepted for a single IP packet before it is dropped
+    #[serde(default = "default_max_fragments_per_packet")]
+    pub max_fragments_per_packet: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -33,9 +90,32 @@ pub struct WebServerConfig {
     pub host: String,
     pub port: u16,
     pub enable_cors: bool,
+    /// Origins allowed by CORS (e.g. "http://localhost:5173"); any origin is allowed when empty
+    #[serde(default)]
+    pub cors_allowed_origins: Vec<String>,
     pub enable_websocket: bool,
     pub static_files_path: Option<String>,
     pub request_timeout: u64, // seconds
+    #[serde(default = "default_true")]
+    pub enable_compression: bool,
+    /// Bearer token required on `/api/*`, `/ws` and `/metrics` when set.
+    /// WebSocket clients that can't set headers may pass it (URL-encoded) as `?token=`.
+    pub api_token: Option<String>,
+    /// Seconds between WebSocket pings; 0 disables the keepalive
+    #[serde(default = "default_ws_ping_interval")]
+    pub ws_ping_interval: u64,
+    /// Seconds to wait for a pong before closing the WebSocket
+    #[serde(default = "default_ws_pong_timeout")]
+    pub ws_pong_timeout: u64,
+    /// Concurrent WebSocket clients allowed; further upgrades get a 503. 0 means no limit
+    #[serde(default = "default_max_ws_connections")]
+    pub max_ws_connections: usize,
+    /// PEM certificate chain; serve HTTPS and wss:// when set together with `tls_key_path`
+    #[serde(default)]
+    pub tls_cert_path: Option<String>,
+    /// PEM private key for `tls_cert_path`
+    #[serde(default)]
+    pub tls_key_path: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -46,6 +126,38 @@ pub struct DataManagerConfig {
     pub auto_save_interval: u64, // seconds
     pub max_cache_age: u64, // days
     pub enable_persistence: bool,
+    /// Also save complete user stats (damage, healing, skills) so a session survives a restart
+    #[serde(default)]
+    pub enable_full_session: bool,
+    #[serde(default = "default_session_file_path")]
+    pub session_file_path: String,
+    /// Language for profession and element names ("zh" or "en")
+    #[serde(default)]
+    pub language: Language,
+    /// Optional JSON file overriding profession/element names, same layout as the skill name table
+    #[serde(default)]
+    pub name_table_path: Option<String>,
+    /// Also record saved snapshots into this SQLite database, queryable via `/api/history/query`
+    #[serde(default)]
+    pub sqlite_path: Option<String>,
+    /// Append every damage/healing record as a JSON line to this file (rotated like the app log)
+    #[serde(default)]
+    pub combat_log_file: Option<String>,
+    #[serde(default = "default_combat_log_capacity")]
+    pub combat_log_capacity: usize,
+    /// Previous copies of the user cache kept as `<cache_file_path>.1`..`.N`; 0 disables backups
+    #[serde(default = "default_user_cache_backups")]
+    pub user_cache_backups: usize,
+}
+
+/// Encounter summaries posted to a Discord webhook when a history snapshot is saved
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscordConfig {
+    pub enabled: bool,
+    pub webhook_url: Option<String>,
+    /// Minimum time between two posts, so rapid clears don't spam the channel
+    #[serde(default = "default_discord_min_interval")]
+    pub min_interval_secs: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -58,6 +170,88 @@ pub struct LoggingConfig {
     pub enable_console_logging: bool,
 }
 
+// Upper bound for ws_ping_interval and ws_pong_timeout (seconds)
+pub const MAX_WS_KEEPALIVE_SECS: u64 = 3600;
+
+// Upper bound for logging.max_log_size (MB)
+pub const MAX_LOG_SIZE_MB: u64 = 10 * 1024;
+
+// Upper bound for debug_packet_capacity; each entry holds a full packet payload
+pub const MAX_DEBUG_PACKET_CAPACITY: usize = 10_000;
+
+// Serde default for boolean options added after the initial config format
+fn default_true() -> bool {
+    true
+}
+
+fn default_ws_ping_interval() -> u64 {
+    30
+}
+
+fn default_ws_pong_timeout() -> u64 {
+    10
+}
+
+fn default_max_ws_connections() -> usize {
+    32
+}
+
+fn default_discord_min_interval() -> u64 {
+    60
+}
+
+fn default_session_file_path() -> String {
+    "session.json".to_string()
+}
+
+fn default_combat_log_capacity() -> usize {
+    5000
+}
+
+fn default_user_cache_backups() -> usize {
+    3
+}
+
+fn default_server_signature() -> Vec<u8> {
+    vec![0x00, 0x63, 0x33, 0x53, 0x42, 0x00]
+}
+
+fn default_signature_offset() -> usize {
+    5
+}
+
+fn default_login_response_len() -> usize {
+    0x62
+}
+
+fn default_service_uuids() -> Vec<u64> {
+    vec![0x0000000063335342]
+}
+
+fn default_max_tcp_cache_segments() -> usize {
+    2048
+}
+
+fn default_max_tcp_cache_bytes() -> usize {
+    8 * 1024 * 1024 // 8MB
+}
+
+fn default_max_data_buffer_bytes() -> usize {
+    16 * 1024 * 1024 // 16MB
+}
+
+fn default_max_packet_size() -> usize {
+    0x0fffff // ~1MB
+}
+
+fn default_max_fragment_entries() -> usize {
+    1024
+}
+
+fn default_max_fragments_per_packet() -> usize {
+    64
+}
+
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
@@ -65,6 +259,17 @@ impl Default for AppConfig {
             web_server: WebServerConfig::default(),
             data_manager: DataManagerConfig::default(),
             logging: LoggingConfig::default(),
+            discord: DiscordConfig::default(),
+        }
+    }
+}
+
+impl Default for DiscordConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            webhook_url: None,
+            min_interval_secs: default_discord_min_interval(),
         }
     }
 }
@@ -72,12 +277,24 @@ impl Default for AppConfig {
 impl Default for PacketCaptureConfig {
     fn default() -> Self {
         Self {
-            filter: "ip and tcp".to_string(),
+            filter: "(ip or ipv6) and tcp".to_string(),
             buffer_size: 10 * 1024 * 1024, // 10MB
             mtu: 1500,
             enable_tcp_reassembly: true,
             max_connections: 10000,
             connection_timeout: 300, // 5 minutes
+            record_pcap: None,
+            server_signature: default_server_signature(),
+            signature_offset: default_signature_offset(),
+            login_response_len: default_login_response_len(),
+            service_uuids: default_service_uuids(),
+            max_tcp_cache_segments: default_max_tcp_cache_segments(),
+            max_tcp_cache_bytes: default_max_tcp_cache_bytes(),
+            max_data_buffer_bytes: default_max_data_buffer_bytes(),
+            max_packet_size: default_max_packet_size(),
+            debug_packet_capacity: 0,
+            max_fragment_entries: default_max_fragment_entries(),
+            max_fragments_per_packet: default_max_fragments_per_packet(),
         }
     }
 }
@@ -88,9 +305,17 @@ impl Default for WebServerConfig {
             host: "127.0.0.1".to_string(),
             port: 8989,
             enable_cors: true,
+            cors_allowed_origins: Vec::new(),
             enable_websocket: true,
             static_files_path: Some("public".to_string()),
             request_timeout: 30,
+            enable_compression: true,
+            api_token: None,
+            ws_ping_interval: default_ws_ping_interval(),
+            ws_pong_timeout: default_ws_pong_timeout(),
+            max_ws_connections: default_max_ws_connections(),
+            tls_cert_path: None,
+            tls_key_path: None,
         }
     }
 }
@@ -104,6 +329,14 @@ impl Default for DataManagerConfig {
             auto_save_interval: 300, // 5 minutes
             max_cache_age: 30, // 30 days
             enable_persistence: true,
+            enable_full_session: false,
+            session_file_path: default_session_file_path(),
+            language: Language::default(),
+            name_table_path: None,
+            sqlite_path: None,
+            combat_log_file: None,
+            combat_log_capacity: default_combat_log_capacity(),
+            user_cache_backups: default_user_cache_backups(),
         }
     }
 }
@@ -132,27 +365,32 @@ impl AppConfig {
         Self::load_with_mode(ConfigMode::Tauri)
     }
 
-    /// Internal method to load configuration based on mode
-    fn load_with_mode(mode: ConfigMode) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        let config_paths = match mode {
-            ConfigMode::Standalone => vec![
-                PathBuf::from("config.json"),
-                std::env::current_exe()?
-                    .parent()
-                    .unwrap_or(&PathBuf::from("."))
-                    .join("config.json"),
-            ],
-            ConfigMode::Tauri => vec![
-                std::env::current_exe()?
-                    .parent()
-                    .unwrap_or(&PathBuf::from("."))
-                    .join("config.json"),
-                PathBuf::from("../meter-core/config.json"),
-            ],
+    /// Config file that `load_with_mode` would pick, if any exists
+    pub fn find_config_file(mode: &ConfigMode) -> Option<PathBuf> {
+        Self::config_paths(mode).ok()?.into_iter().find(|path| path.exists())
+    }
+
+    fn config_paths(mode: &ConfigMode) -> Result<Vec<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
+        let exe_dir = std::env::current_exe()?
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from("."));
+        let dirs = match mode {
+            ConfigMode::Standalone => vec![PathBuf::from("."), exe_dir],
+            ConfigMode::Tauri => vec![exe_dir, PathBuf::from("../meter-core")],
         };
 
+        // In each directory config.toml takes precedence over config.json
+        Ok(dirs
+            .into_iter()
+            .flat_map(|dir| [dir.join("config.toml"), dir.join("config.json")])
+            .collect())
+    }
+
+    /// Internal method to load configuration based on mode
+    fn load_with_mode(mode: ConfigMode) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
         // Try to load from each path
-        for path in config_paths {
+        for path in Self::config_paths(&mode)? {
             if let Ok(config) = Self::load_from_file(&path) {
                 let mut config = config;
 
@@ -190,13 +428,19 @@ impl AppConfig {
         }
 
         let content = fs::read_to_string(&path)?;
-        let config: Self = serde_json::from_str(&content)?;
+        let config: Self = match ConfigFormat::from_path(path.as_ref()) {
+            ConfigFormat::Json => serde_json::from_str(&content)?,
+            ConfigFormat::Toml => toml::from_str(&content)?,
+        };
         Ok(config)
     }
 
-    /// Save configuration to a file
+    /// Save configuration to a file, in the format given by its extension
     pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let content = serde_json::to_string_pretty(self)?;
+        let content = match ConfigFormat::from_path(path.as_ref()) {
+            ConfigFormat::Json => serde_json::to_string_pretty(self)?,
+            ConfigFormat::Toml => toml::to_string_pretty(self)?,
+        };
 
         // Create directory if it doesn't exist
         if let Some(parent) = path.as_ref().parent() {
@@ -209,130 +453,64 @@ impl AppConfig {
     }
 }
 
+const ARGS_AFTER_HELP: &str = "\
+CONFIGURATION:
+    Create a config.json file to customize settings. Copy from config.example.json
+    Log level can be set in config file under 'logging.level'
+    Priority: Command line > Config file > Environment variables > Defaults
+
+EXAMPLES:
+    meter-core --port 8080 --log-level debug
+    meter-core --config my-config.json
+    cp config.example.json config.json && meter-core";
+
 // Command line arguments structure
-#[derive(Debug)]
+#[derive(Debug, clap::Parser)]
+#[command(
+    name = "meter-core",
+    version,
+    about = "Meter Core - Star Resonance Damage Counter",
+    after_help = ARGS_AFTER_HELP,
+    // -h is taken by --host, so help is only available as --help
+    disable_help_flag = true
+)]
 pub struct AppArgs {
+    /// Web server host (default: 127.0.0.1)
+    #[arg(short = 'h', long)]
     pub host: Option<String>,
+    /// Web server port (default: 8989)
+    #[arg(short, long)]
     pub port: Option<u16>,
+    /// Log level (trace, debug, info, warn, error)
+    #[arg(short, long, value_name = "LEVEL")]
     pub log_level: Option<String>,
+    /// Configuration file path (default: config.json)
+    #[arg(short, long = "config", value_name = "FILE")]
     pub config_file: Option<String>,
+    /// Network interface for packet capture
+    #[arg(short, long)]
     pub interface: Option<String>,
+    /// Enable verbose logging
+    #[arg(short, long)]
     pub verbose: bool,
+    /// Run as daemon
+    #[arg(short, long)]
     pub daemon: bool,
+    /// Replay packets from a pcap/pcapng file instead of live capture
+    #[arg(long, value_name = "FILE")]
+    pub replay: Option<String>,
+    /// Keep the original packet timing when replaying
+    #[arg(long)]
+    pub replay_realtime: bool,
+    /// Print this help message
+    #[arg(long, action = clap::ArgAction::Help)]
+    help: Option<bool>,
 }
 
 impl AppArgs {
+    /// Parse the process arguments, printing usage and exiting on invalid input
     pub fn parse() -> Self {
-        // Simple argument parsing - in practice, you'd use clap or similar
-        let args: Vec<String> = std::env::args().collect();
-
-        let mut host = None;
-        let mut port = None;
-        let mut log_level = None;
-        let mut config_file = None;
-        let mut interface = None;
-        let mut verbose = false;
-        let mut daemon = false;
-
-        let mut i = 1;
-        while i < args.len() {
-            match args[i].as_str() {
-                "--host" | "-h" => {
-                    if i + 1 < args.len() {
-                        host = Some(args[i + 1].clone());
-                        i += 2;
-                    } else {
-                        i += 1;
-                    }
-                }
-                "--port" | "-p" => {
-                    if i + 1 < args.len() {
-                        if let Ok(p) = args[i + 1].parse::<u16>() {
-                            port = Some(p);
-                        }
-                        i += 2;
-                    } else {
-                        i += 1;
-                    }
-                }
-                "--log-level" | "-l" => {
-                    if i + 1 < args.len() {
-                        log_level = Some(args[i + 1].clone());
-                        i += 2;
-                    } else {
-                        i += 1;
-                    }
-                }
-                "--config" | "-c" => {
-                    if i + 1 < args.len() {
-                        config_file = Some(args[i + 1].clone());
-                        i += 2;
-                    } else {
-                        i += 1;
-                    }
-                }
-                "--interface" | "-i" => {
-                    if i + 1 < args.len() {
-                        interface = Some(args[i + 1].clone());
-                        i += 2;
-                    } else {
-                        i += 1;
-                    }
-                }
-                "--verbose" | "-v" => {
-                    verbose = true;
-                    i += 1;
-                }
-                "--daemon" | "-d" => {
-                    daemon = true;
-                    i += 1;
-                }
-                "--help" => {
-                    Self::print_help();
-                    std::process::exit(0);
-                }
-                _ => {
-                    i += 1;
-                }
-            }
-        }
-
-        Self {
-            host,
-            port,
-            log_level,
-            config_file,
-            interface,
-            verbose,
-            daemon,
-        }
-    }
-
-    fn print_help() {
-        println!("Meter Core - Star Resonance Damage Counter");
-        println!();
-        println!("USAGE:");
-        println!("    meter-core [OPTIONS]");
-        println!();
-        println!("OPTIONS:");
-        println!("    -h, --host <HOST>              Web server host (default: 127.0.0.1)");
-        println!("    -p, --port <PORT>              Web server port (default: 8989)");
-        println!("    -l, --log-level <LEVEL>        Log level (trace, debug, info, warn, error)");
-        println!("    -c, --config <FILE>            Configuration file path (default: config.json)");
-        println!("    -i, --interface <INTERFACE>    Network interface for packet capture");
-        println!("    -v, --verbose                  Enable verbose logging");
-        println!("    -d, --daemon                   Run as daemon");
-        println!("        --help                     Print this help message");
-        println!();
-        println!("CONFIGURATION:");
-        println!("    Create a config.json file to customize settings. Copy from config.example.json");
-        println!("    Log level can be set in config file under 'logging.level'");
-        println!("    Priority: Command line > Config file > Environment variables > Defaults");
-        println!();
-        println!("EXAMPLES:");
-        println!("    meter-core --port 8080 --log-level debug");
-        println!("    meter-core --config my-config.json");
-        println!("    cp config.example.json config.json && meter-core");
+        <Self as clap::Parser>::parse()
     }
 }
 
@@ -345,17 +523,80 @@ impl AppConfig {
         if self.web_server.port == 0 {
             errors.push("Web server port cannot be 0".to_string());
         }
+        for origin in &self.web_server.cors_allowed_origins {
+            if !is_valid_origin(origin) {
+                errors.push(format!("Invalid CORS origin: {} (expected scheme://host[:port])", origin));
+            }
+        }
+        if self.web_server.ws_ping_interval > 0 && self.web_server.ws_pong_timeout == 0 {
+            errors.push("Web server ws_pong_timeout must be greater than 0 when pings are enabled".to_string());
+        }
+        if self.web_server.ws_ping_interval > MAX_WS_KEEPALIVE_SECS || self.web_server.ws_pong_timeout > MAX_WS_KEEPALIVE_SECS {
+            errors.push(format!("Web server ws_ping_interval and ws_pong_timeout must be at most {} seconds", MAX_WS_KEEPALIVE_SECS));
+        }
+        let tls_cert = self.web_server.tls_cert_path.as_deref().filter(|path| !path.is_empty());
+        let tls_key = self.web_server.tls_key_path.as_deref().filter(|path| !path.is_empty());
+        match (tls_cert, tls_key) {
+            (Some(_), None) | (None, Some(_)) => {
+                errors.push("Web server tls_cert_path and tls_key_path must be set together".to_string());
+            }
+            (Some(cert), Some(key)) => {
+                for path in [cert, key] {
+                    if !Path::new(path).is_file() {
+                        errors.push(format!("TLS file not found: {}", path));
+                    }
+                }
+            }
+            (None, None) => {}
+        }
 
         // Validate packet capture config
         if self.packet_capture.buffer_size == 0 {
             errors.push("Packet capture buffer size cannot be 0".to_string());
         }
+        if self.packet_capture.server_signature.is_empty() {
+            errors.push("Packet capture server signature cannot be empty".to_string());
+        }
+        if self.packet_capture.service_uuids.is_empty() {
+            errors.push("Packet capture service uuids cannot be empty".to_string());
+        }
+        if self.packet_capture.max_tcp_cache_segments == 0
+            || self.packet_capture.max_tcp_cache_bytes == 0
+            || self.packet_capture.max_data_buffer_bytes == 0
+        {
+            errors.push("Packet capture TCP cache limits must be greater than 0".to_string());
+        }
+        if self.packet_capture.max_packet_size < 6 {
+            errors.push("Packet capture max_packet_size must be at least 6 bytes".to_string());
+        }
+        if self.packet_capture.max_fragment_entries == 0 || self.packet_capture.max_fragments_per_packet == 0 {
+            errors.push("Packet capture IP fragment limits must be greater than 0".to_string());
+        }
+        if self.packet_capture.debug_packet_capacity > MAX_DEBUG_PACKET_CAPACITY {
+            errors.push(format!("Packet capture debug_packet_capacity must be at most {}", MAX_DEBUG_PACKET_CAPACITY));
+        }
+
+        // Validate data manager config
+        if self.data_manager.auto_save_interval == 0 {
+            errors.push("Data manager auto_save_interval must be greater than 0".to_string());
+        }
+
+        // Validate discord config
+        if self.discord.enabled {
+            match self.discord.webhook_url.as_deref() {
+                Some(url) if url.starts_with("https://") => {}
+                _ => errors.push("Discord webhook_url must be an https:// URL when discord is enabled".to_string()),
+            }
+        }
 
         // Validate logging config
         let valid_levels = ["trace", "debug", "info", "warn", "error"];
         if !valid_levels.contains(&self.logging.level.as_str()) {
             errors.push(format!("Invalid log level: {}. Valid levels are: {}", self.logging.level, valid_levels.join(", ")));
         }
+        if !(1..=MAX_LOG_SIZE_MB).contains(&self.logging.max_log_size) {
+            errors.push(format!("Logging max_log_size must be between 1 and {} MB", MAX_LOG_SIZE_MB));
+        }
 
         if errors.is_empty() {
             Ok(())
@@ -365,6 +606,16 @@ impl AppConfig {
     }
 }
 
+/// An origin is an http(s) URL with a host and no path, query or fragment
+fn is_valid_origin(origin: &str) -> bool {
+    let Ok(uri) = origin.trim_end_matches('/').parse::<axum::http::Uri>() else {
+        return false;
+    };
+    matches!(uri.scheme_str(), Some("http") | Some("https"))
+        && uri.host().is_some_and(|host| !host.is_empty())
+        && uri.path_and_query().is_none_or(|pq| pq.as_str() == "/" || pq.as_str().is_empty())
+}
+
 // Environment variable loading
 impl AppConfig {
     pub fn load_from_env(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
@@ -384,7 +635,7 @@ impl AppConfig {
         }
 
         if let Ok(interface) = std::env::var("METER_CORE_INTERFACE") {
-            self.packet_capture.filter = format!("ip and tcp and {}", interface);
+            self.packet_capture.filter = format!("(ip or ipv6) and tcp and {}", interface);
         }
 
         Ok(())
@@ -421,7 +672,8 @@ impl AppConfig {
                 }
 
                 if let Some(skill_path) = &self.data_manager.skill_config_path {
-                    if !Path::new(skill_path).exists() {
+                    let skill_path_buf = Path::new(skill_path);
+                    if !skill_path_buf.exists() && !skill_path_buf.with_extension("csv").exists() {
                         errors.push(format!("Skill config file does not exist: {}", skill_path));
                     }
                 }
@@ -440,6 +692,91 @@ impl AppConfig {
     }
 }
 
+// Hot reload support
+impl AppConfig {
+    /// Settings changed between `self` and `other` that only take effect after a restart
+    pub fn restart_required_changes(&self, other: &AppConfig) -> Vec<&'static str> {
+        let (old, new) = (&self.web_server, &other.web_server);
+        let mut changes = Vec::new();
+        if old.host != new.host {
+            changes.push("web_server.host");
+        }
+        if old.port != new.port {
+            changes.push("web_server.port");
+        }
+        if old.enable_cors != new.enable_cors {
+            changes.push("web_server.enable_cors");
+        }
+        if old.cors_allowed_origins != new.cors_allowed_origins {
+            changes.push("web_server.cors_allowed_origins");
+        }
+        if old.enable_websocket != new.enable_websocket {
+            changes.push("web_server.enable_websocket");
+        }
+        if old.static_files_path != new.static_files_path {
+            changes.push("web_server.static_files_path");
+        }
+        if old.enable_compression != new.enable_compression {
+            changes.push("web_server.enable_compression");
+        }
+        if old.api_token != new.api_token {
+            changes.push("web_server.api_token");
+        }
+        if old.tls_cert_path != new.tls_cert_path {
+            changes.push("web_server.tls_cert_path");
+        }
+        if old.tls_key_path != new.tls_key_path {
+            changes.push("web_server.tls_key_path");
+        }
+
+        // Read once when the capture and parser are started
+        let (old_capture, new_capture) = (&self.packet_capture, &other.packet_capture);
+        if old_capture.record_pcap != new_capture.record_pcap {
+            changes.push("packet_capture.record_pcap");
+        }
+        if old_capture.service_uuids != new_capture.service_uuids {
+            changes.push("packet_capture.service_uuids");
+        }
+        if old_capture.buffer_size != new_capture.buffer_size {
+            changes.push("packet_capture.buffer_size");
+        }
+        if old_capture.mtu != new_capture.mtu {
+            changes.push("packet_capture.mtu");
+        }
+        if old_capture.enable_tcp_reassembly != new_capture.enable_tcp_reassembly {
+            changes.push("packet_capture.enable_tcp_reassembly");
+        }
+        if old_capture.max_connections != new_capture.max_connections {
+            changes.push("packet_capture.max_connections");
+        }
+        if self.data_manager.enable_full_session != other.data_manager.enable_full_session {
+            changes.push("data_manager.enable_full_session");
+        }
+        if self.data_manager.session_file_path != other.data_manager.session_file_path {
+            changes.push("data_manager.session_file_path");
+        }
+        if self.discord.enabled != other.discord.enabled
+            || self.discord.webhook_url != other.discord.webhook_url
+            || self.discord.min_interval_secs != other.discord.min_interval_secs
+        {
+            changes.push("discord");
+        }
+        if self.data_manager.language != other.data_manager.language {
+            changes.push("data_manager.language");
+        }
+        if self.data_manager.name_table_path != other.data_manager.name_table_path {
+            changes.push("data_manager.name_table_path");
+        }
+        if self.data_manager.sqlite_path != other.data_manager.sqlite_path {
+            changes.push("data_manager.sqlite_path");
+        }
+        if self.data_manager.combat_log_file != other.data_manager.combat_log_file {
+            changes.push("data_manager.combat_log_file");
+        }
+        changes
+    }
+}
+
 // Configuration file watching (for hot reload)
 pub struct ConfigWatcher {
     config_path: String,
@@ -500,11 +837,129 @@ mod tests {
         // Reset for next test
         config.web_server.port = 8989;
 
+        // Test zero auto-save interval
+        config.data_manager.auto_save_interval = 0;
+        assert!(config.validate().is_err());
+        config.data_manager.auto_save_interval = 300;
+
+        // Test pings without a pong timeout
+        config.web_server.ws_pong_timeout = 0;
+        assert!(config.validate().is_err());
+        config.web_server.ws_ping_interval = 0;
+        assert!(config.validate().is_ok());
+
+        // Test keepalive values beyond the upper bound
+        config.web_server.ws_pong_timeout = u64::MAX;
+        assert!(config.validate().is_err());
+        config.web_server.ws_pong_timeout = 10;
+        config.web_server.ws_ping_interval = MAX_WS_KEEPALIVE_SECS + 1;
+        assert!(config.validate().is_err());
+        config.web_server.ws_ping_interval = 0;
+
+        // Test oversized debug packet buffer
+        config.packet_capture.debug_packet_capacity = MAX_DEBUG_PACKET_CAPACITY + 1;
+        assert!(config.validate().is_err());
+        config.packet_capture.debug_packet_capacity = MAX_DEBUG_PACKET_CAPACITY;
+        assert!(config.validate().is_ok());
+        config.packet_capture.debug_packet_capacity = 0;
+
+        // Test TLS cert without key, then with missing files
+        config.web_server.tls_cert_path = Some("missing-cert.pem".to_string());
+        assert!(config.validate().is_err());
+        config.web_server.tls_key_path = Some("missing-key.pem".to_string());
+        assert_eq!(config.validate().unwrap_err().len(), 2);
+        config.web_server.tls_cert_path = None;
+        config.web_server.tls_key_path = None;
+
+        // Test out-of-range log size
+        config.logging.max_log_size = 0;
+        assert!(config.validate().is_err());
+        config.logging.max_log_size = u64::MAX;
+        assert!(config.validate().is_err());
+        config.logging.max_log_size = 10;
+
         // Test invalid log level
         config.logging.level = "invalid".to_string();
         assert!(config.validate().is_err());
     }
 
+    #[test]
+    fn test_toml_config_round_trip() {
+        let path = std::env::temp_dir().join(format!("meter-core-test-{}.toml", std::process::id()));
+        let mut config = AppConfig::default();
+        config.web_server.port = 9000;
+        config.logging.log_file_path = None;
+
+        config.save_to_file(&path).unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        let loaded = AppConfig::load_from_file(&path).unwrap();
+        let _ = fs::remove_file(&path);
+
+        assert!(content.contains("[web_server]"));
+        assert_eq!(loaded.web_server.port, 9000);
+        assert_eq!(loaded.logging.log_file_path, None);
+        assert_eq!(loaded.packet_capture.service_uuids, config.packet_capture.service_uuids);
+    }
+
+    #[test]
+    fn test_cors_origin_validation() {
+        let mut config = AppConfig::default();
+        config.web_server.cors_allowed_origins = vec![
+            "http://localhost:5173".to_string(),
+            "https://example.com/".to_string(),
+        ];
+        assert!(config.validate().is_ok());
+
+        for origin in ["localhost:5173", "ftp://example.com", "https://example.com/path", "*"] {
+            config.web_server.cors_allowed_origins = vec![origin.to_string()];
+            assert!(config.validate().is_err(), "{} should be rejected", origin);
+        }
+    }
+
+    #[test]
+    fn test_restart_required_changes() {
+        let config = AppConfig::default();
+        let mut edited = config.clone();
+        edited.logging.level = "debug".to_string();
+        edited.packet_capture.filter = "tcp".to_string();
+        assert!(config.restart_required_changes(&edited).is_empty());
+
+        edited.packet_capture.connection_timeout = 60;
+        edited.packet_capture.max_packet_size = 4096;
+        assert!(config.restart_required_changes(&edited).is_empty());
+
+        edited.web_server.port = 9000;
+        assert_eq!(config.restart_required_changes(&edited), vec!["web_server.port"]);
+
+        edited.web_server.tls_cert_path = Some("cert.pem".to_string());
+        edited.web_server.tls_key_path = Some("key.pem".to_string());
+        edited.packet_capture.service_uuids.push(0x1234);
+        edited.packet_capture.buffer_size *= 2;
+        assert_eq!(
+            config.restart_required_changes(&edited),
+            vec![
+                "web_server.port",
+                "web_server.tls_cert_path",
+                "web_server.tls_key_path",
+                "packet_capture.service_uuids",
+                "packet_capture.buffer_size",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_app_args_rejects_invalid_values() {
+        use clap::Parser;
+
+        let args = AppArgs::try_parse_from(["meter-core", "-h", "0.0.0.0", "--port", "8080", "-v"]).unwrap();
+        assert_eq!(args.host.as_deref(), Some("0.0.0.0"));
+        assert_eq!(args.port, Some(8080));
+        assert!(args.verbose);
+
+        assert!(AppArgs::try_parse_from(["meter-core", "--port", "abc"]).is_err());
+        assert!(AppArgs::try_parse_from(["meter-core", "--unknown"]).is_err());
+    }
+
     #[test]
     fn test_config_modes() {
         // Test that we can create configs for different modes
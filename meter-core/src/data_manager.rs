@@ -1,14 +1,43 @@
+use crate::config::{DataManagerConfig, DiscordConfig, LoggingConfig, PacketCaptureConfig, MAX_LOG_SIZE_MB};
+use crate::discord::DiscordNotifier;
 use crate::models::*;
 use dashmap::DashMap;
-use parking_lot::RwLock;
+use parking_lot::{RwLock, RwLockUpgradableReadGuard};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use tokio::sync::{broadcast, mpsc, oneshot, watch, Mutex, OwnedMutexGuard};
 use chrono::{DateTime, Utc, Duration};
 
+pub mod combat_log_writer;
+pub mod encounter_store;
+
+use combat_log_writer::CombatLogWriter;
+use encounter_store::EncounterStore;
+
+// 时间线保留时长（秒）
+const TIMELINE_RETENTION_SECS: i64 = 600;
+// Default number of records kept in the rolling combat log
+const DEFAULT_COMBAT_LOG_CAPACITY: usize = 5000;
+// Default number of rotated user cache backups
+const DEFAULT_USER_CACHE_BACKUPS: usize = 3;
+// WebSocket广播通道容量
+const WS_CHANNEL_CAPACITY: usize = 16;
+// Finished encounter segments kept in memory for /api/encounters
+const MAX_ENCOUNTER_SEGMENTS: usize = 50;
+// Server clock offsets beyond this are treated as a corrupt SyncServerTime
+const MAX_SERVER_CLOCK_OFFSET_MS: i64 = 24 * 60 * 60 * 1000;
+// Upper bound for the `window_dps` sliding window (seconds)
+pub const MAX_WINDOW_DPS_SECS: u64 = 3600;
+// Upper bound for `encounter_idle_gap_secs`
+pub const MAX_ENCOUNTER_IDLE_GAP_SECS: u64 = 3600;
+// Imported timestamps further ahead of the (server-synced) clock than this are rejected
+const MAX_IMPORT_FUTURE_SKEW_MS: i64 = 24 * 60 * 60 * 1000;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserCache {
     pub uid: u32,
@@ -18,6 +47,90 @@ pub struct UserCache {
     pub max_hp: u32,
 }
 
+/// Sent with a capture restart request; receives the outcome of the restart
+pub type CaptureRestartReply = oneshot::Sender<std::result::Result<(), String>>;
+
+/// One tick's user data, with a hash of each serialized user for change detection
+#[derive(Debug, Default)]
+pub struct UsersSnapshot {
+    pub users: HashMap<u32, serde_json::Value>,
+    pub hashes: HashMap<u32, u64>,
+}
+
+/// Last generated summary of one user, reused until the user is marked dirty
+#[derive(Debug, Clone)]
+struct CachedSummary {
+    summary: serde_json::Value,
+    hash: u64,
+}
+
+/// Whole-party totals for the summary header
+#[derive(Debug, Default, Serialize)]
+pub struct PartyStats {
+    pub member_count: usize,
+    pub total_damage: u64,
+    pub total_healing: u64,
+    pub total_dps: f64,
+    pub total_hps: f64,
+    pub total_deaths: u32,
+    pub top_dps_uid: Option<u32>,
+    pub top_hps_uid: Option<u32>,
+}
+
+/// Metric used to order `get_ranking`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RankingMetric {
+    #[default]
+    Dps,
+    Damage,
+    Healing,
+}
+
+/// Which of a user's skill tables a skill id refers to; a skill can both damage and heal
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SkillKind {
+    #[default]
+    Damage,
+    Healing,
+}
+
+/// State of the encounter currently being recorded
+#[derive(Debug, Default)]
+pub struct EncounterTracker {
+    pub start: Option<DateTime<Utc>>,
+    pub last_activity: Option<DateTime<Utc>>,
+    /// Set once an encounter is finalized; stats are cleared when combat resumes
+    pub pending_reset: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EncounterTopDps {
+    pub uid: u32,
+    pub name: String,
+    pub dps: f64,
+}
+
+/// Encounter boundaries, published while `segment_encounters` is enabled
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EncounterEvent {
+    Started { start: DateTime<Utc> },
+    Ended(EncounterSegment),
+}
+
+/// A finished encounter, saved as a history snapshot under `snapshot_timestamp`
+#[derive(Debug, Clone, Serialize)]
+pub struct EncounterSegment {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub duration_secs: i64,
+    pub total_damage: u64,
+    pub top_dps: Option<EncounterTopDps>,
+    pub snapshot_timestamp: i64,
+}
+
 #[derive(Debug)]
 pub struct DataManager {
     pub users: DashMap<u32, Arc<RwLock<User>>>,
@@ -26,9 +139,46 @@ pub struct DataManager {
     pub settings: Arc<RwLock<GlobalSettings>>,
     pub cache_file_path: String,
     pub settings_file_path: String,
+    pub skill_config_path: Option<String>,
+    /// Where full user stats are persisted; `None` unless full session persistence is enabled
+    pub session_file_path: Option<String>,
+    /// Profession and element names in the configured language
+    pub names: Arc<RwLock<NameTable>>,
+    pub name_table_path: Option<String>,
+    /// Posts encounter summaries when enabled in the config
+    pub discord: Option<DiscordNotifier>,
+    /// SQLite copy of saved snapshots, when `sqlite_path` is configured
+    pub encounter_store: Option<EncounterStore>,
+    /// Durable JSON-lines copy of the combat log, when `combat_log_file` is configured
+    pub combat_log_writer: Option<CombatLogWriter>,
     pub start_time: DateTime<Utc>,
     pub is_paused: Arc<RwLock<bool>>,
     pub last_log_time: Arc<RwLock<DateTime<Utc>>>,
+    pub timelines: DashMap<u32, VecDeque<(DateTime<Utc>, u64)>>,
+    pub ws_tx: broadcast::Sender<Arc<UsersSnapshot>>,
+    pub encounter_tx: broadcast::Sender<EncounterEvent>,
+    pub combat_log: Arc<RwLock<VecDeque<DamageRecord>>>,
+    pub combat_log_capacity: AtomicUsize,
+    /// Rotated copies of the user cache kept on each save
+    pub user_cache_backups: AtomicUsize,
+    pub capture_filter: watch::Sender<String>,
+    /// Set by whoever owns the running capture so the API can ask it to restart
+    pub capture_restart: RwLock<Option<mpsc::Sender<CaptureRestartReply>>>,
+    /// Flipped to `true` when a shutdown has been requested through the API
+    pub shutdown: watch::Sender<bool>,
+    /// Server clock minus local clock (ms), set once a SyncServerTime notify has been seen
+    pub server_clock_offset_ms: Arc<RwLock<Option<i64>>>,
+    /// Packets seen per message type since startup (or the last reset)
+    pub message_type_counts: DashMap<u16, u64>,
+    /// Notify messages seen per method id since startup (or the last reset)
+    pub notify_method_counts: DashMap<u32, u64>,
+    pub encounter: RwLock<EncounterTracker>,
+    /// Finished encounters, oldest first
+    pub encounters: RwLock<VecDeque<EncounterSegment>>,
+    /// Held while a finished encounter's snapshot is saved, so its stats aren't cleared mid-save
+    encounter_save: Arc<Mutex<()>>,
+    /// Per-user summaries from the last tick, without the party percentages
+    user_summaries: DashMap<u32, CachedSummary>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,6 +186,36 @@ pub struct GlobalSettings {
     pub auto_clear_on_server_change: bool,
     pub auto_clear_on_timeout: bool,
     pub only_record_elite_dummy: bool,
+    /// Target ids counted when `only_record_elite_dummy` is set (some rooms spawn several dummies)
+    #[serde(default = "default_elite_dummy_target_ids")]
+    pub elite_dummy_target_ids: Vec<u32>,
+    /// Length of the sliding window used for `window_dps` (seconds)
+    #[serde(default = "default_window_dps_secs")]
+    pub window_dps_secs: u64,
+    /// Split combat into encounters, saving a snapshot whenever damage and healing stop for `encounter_idle_gap_secs`
+    #[serde(default)]
+    pub segment_encounters: bool,
+    #[serde(default = "default_encounter_idle_gap_secs")]
+    pub encounter_idle_gap_secs: u64,
+    /// Users with no damage, healing or taken damage for this long are reported as `idle`
+    #[serde(default = "default_idle_threshold_secs")]
+    pub idle_threshold_secs: u64,
+}
+
+fn default_elite_dummy_target_ids() -> Vec<u32> {
+    vec![75]
+}
+
+fn default_window_dps_secs() -> u64 {
+    5
+}
+
+fn default_encounter_idle_gap_secs() -> u64 {
+    15
+}
+
+fn default_idle_threshold_secs() -> u64 {
+    30
 }
 
 impl Default for GlobalSettings {
@@ -44,12 +224,26 @@ impl Default for GlobalSettings {
             auto_clear_on_server_change: true,
             auto_clear_on_timeout: false,
             only_record_elite_dummy: false,
+            elite_dummy_target_ids: default_elite_dummy_target_ids(),
+            window_dps_secs: default_window_dps_secs(),
+            segment_encounters: false,
+            encounter_idle_gap_secs: default_encounter_idle_gap_secs(),
+            idle_threshold_secs: default_idle_threshold_secs(),
         }
     }
 }
 
+impl GlobalSettings {
+    /// Clamp values that may come from a hand-edited settings file
+    pub fn sanitize(&mut self) {
+        self.window_dps_secs = self.window_dps_secs.clamp(1, MAX_WINDOW_DPS_SECS);
+        self.encounter_idle_gap_secs = self.encounter_idle_gap_secs.clamp(1, MAX_ENCOUNTER_IDLE_GAP_SECS);
+    }
+}
+
 impl DataManager {
     pub fn new() -> Self {
+        let (ws_tx, _) = broadcast::channel(WS_CHANNEL_CAPACITY);
         Self {
             users: DashMap::new(),
             enemies: DashMap::new(),
@@ -57,26 +251,125 @@ impl DataManager {
             settings: Arc::new(RwLock::new(GlobalSettings::default())),
             cache_file_path: "users.json".to_string(),
             settings_file_path: "settings.json".to_string(),
+            skill_config_path: Some("tables/skill_names.json".to_string()),
+            session_file_path: None,
+            names: Arc::new(RwLock::new(NameTable::default())),
+            name_table_path: None,
+            discord: None,
+            encounter_store: None,
+            combat_log_writer: None,
             start_time: Utc::now(),
             is_paused: Arc::new(RwLock::new(false)),
             last_log_time: Arc::new(RwLock::new(Utc::now())),
+            timelines: DashMap::new(),
+            ws_tx,
+            encounter_tx: broadcast::channel(WS_CHANNEL_CAPACITY).0,
+            combat_log: Arc::new(RwLock::new(VecDeque::new())),
+            combat_log_capacity: AtomicUsize::new(DEFAULT_COMBAT_LOG_CAPACITY),
+            user_cache_backups: AtomicUsize::new(DEFAULT_USER_CACHE_BACKUPS),
+            capture_filter: watch::channel(PacketCaptureConfig::default().filter).0,
+            capture_restart: RwLock::new(None),
+            shutdown: watch::channel(false).0,
+            server_clock_offset_ms: Arc::new(RwLock::new(None)),
+            message_type_counts: DashMap::new(),
+            notify_method_counts: DashMap::new(),
+            encounter: RwLock::new(EncounterTracker::default()),
+            encounters: RwLock::new(VecDeque::new()),
+            encounter_save: Arc::new(Mutex::new(())),
+            user_summaries: DashMap::new(),
+        }
+    }
+
+    /// Create a data manager that reads and writes its files at the configured paths
+    pub fn new_with_config(config: &DataManagerConfig) -> Self {
+        Self {
+            cache_file_path: config.cache_file_path.clone(),
+            settings_file_path: config.settings_file_path.clone(),
+            skill_config_path: config.skill_config_path.clone(),
+            session_file_path: (config.enable_persistence && config.enable_full_session)
+                .then(|| config.session_file_path.clone()),
+            names: Arc::new(RwLock::new(NameTable::builtin(config.language))),
+            name_table_path: config.name_table_path.clone(),
+            encounter_store: config.sqlite_path.as_deref().and_then(|path| match EncounterStore::open(path) {
+                Ok(store) => {
+                    log::info!("Recording encounters to SQLite database {}", path);
+                    Some(store)
+                }
+                Err(e) => {
+                    log::error!("Failed to open SQLite database {}: {}", path, e);
+                    None
+                }
+            }),
+            ..Self::new()
+        }
+        .with_config(config)
+    }
+
+    pub fn with_config(self, config: &DataManagerConfig) -> Self {
+        self.apply_config(config);
+        self
+    }
+
+    pub fn with_discord(mut self, config: &DiscordConfig) -> Self {
+        self.discord = DiscordNotifier::from_config(config);
+        self
+    }
+
+    /// Rotation reuses the size and file count limits of the application log
+    pub fn with_combat_log_file(mut self, path: Option<&str>, logging: &LoggingConfig) -> Self {
+        self.combat_log_writer = path.map(|path| {
+            log::info!("Writing combat log to {}", path);
+            let max_bytes = logging.max_log_size.clamp(1, MAX_LOG_SIZE_MB).saturating_mul(1024 * 1024);
+            CombatLogWriter::new(path, max_bytes, logging.max_log_files)
+        });
+        self
+    }
+
+    pub fn flush_combat_log_file(&self) {
+        if let Some(writer) = &self.combat_log_writer {
+            if let Err(e) = writer.flush() {
+                log::warn!("Failed to flush combat log {:?}: {}", writer.path(), e);
+            }
         }
     }
 
+    /// Apply the settings that can change while running (also used by config hot reload)
+    pub fn apply_config(&self, config: &DataManagerConfig) {
+        self.combat_log_capacity.store(config.combat_log_capacity, Ordering::Relaxed);
+        self.user_cache_backups.store(config.user_cache_backups, Ordering::Relaxed);
+    }
+
     pub async fn initialize(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         self.load_user_cache().await?;
+        self.load_full_session().await?;
         self.load_settings().await?;
         self.load_skill_config().await?;
+        self.load_name_table().await?;
         Ok(())
     }
 
+    /// Load the user cache, falling back to the newest backup that still parses
     async fn load_user_cache(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         if !Path::new(&self.cache_file_path).exists() {
             return Ok(());
         }
 
-        let content = fs::read_to_string(&self.cache_file_path)?;
-        let cache_data: HashMap<String, UserCache> = serde_json::from_str(&content)?;
+        let cache_data = match read_user_cache(&self.cache_file_path) {
+            Ok(cache_data) => cache_data,
+            Err(e) => {
+                log::warn!("Failed to load user cache {}: {}", self.cache_file_path, e);
+                let backups = self.user_cache_backups.load(Ordering::Relaxed);
+                let backup = (1..=backups).find_map(|index| {
+                    let path = backup_path(&self.cache_file_path, index);
+                    read_user_cache(&path).ok().map(|cache_data| (path, cache_data))
+                });
+                let Some((path, cache_data)) = backup else {
+                    return Err(e);
+                };
+                log::warn!("Recovered user cache from backup {}", path);
+                cache_data
+            }
+        };
         let entry_count = cache_data.len();
 
         for (uid_str, cache) in cache_data {
@@ -100,31 +393,174 @@ impl DataManager {
         Ok(())
     }
 
+    /// Restore complete user stats saved by `save_full_session`, replacing the cached summaries
+    pub async fn load_full_session(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let Some(path) = self.session_file_path.as_deref() else {
+            return Ok(());
+        };
+        if !Path::new(path).exists() {
+            return Ok(());
+        }
+
+        // A corrupt session must not keep the meter from starting; keep the file for inspection
+        let content = fs::read_to_string(path)?;
+        let session: HashMap<String, User> = match serde_json::from_str(&content) {
+            Ok(session) => session,
+            Err(e) => {
+                let aside = format!("{}.corrupt-{}", path, Utc::now().format("%Y%m%d-%H%M%S"));
+                log::warn!("Failed to parse session file {}: {}; moving it to {} and starting empty", path, e, aside);
+                if let Err(e) = fs::rename(path, &aside) {
+                    log::warn!("Failed to move corrupt session file {}: {}", path, e);
+                }
+                return Ok(());
+            }
+        };
+        let entry_count = session.len();
+
+        for (uid_str, user) in session {
+            if let Ok(uid) = uid_str.parse::<u32>() {
+                self.users.insert(uid, Arc::new(RwLock::new(user)));
+            }
+        }
+
+        log::info!("Restored full session for {} users from {}", entry_count, path);
+        Ok(())
+    }
+
+    /// Save complete user stats; does nothing unless full session persistence is enabled
+    pub async fn save_full_session(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let Some(path) = self.session_file_path.as_deref() else {
+            return Ok(());
+        };
+
+        let session = self.full_session();
+        let content = serde_json::to_string(&session)?;
+        write_file_blocking(path.to_string(), content).await?;
+
+        log::debug!("Saved full session for {} users", session.len());
+        Ok(())
+    }
+
+    /// Complete user stats keyed by uid string, the shape used by the session file
+    pub fn full_session(&self) -> HashMap<String, User> {
+        self.users
+            .iter()
+            .map(|entry| (entry.key().to_string(), entry.value().read().clone()))
+            .collect()
+    }
+
+    /// A timestamped path the current state is written to before an import replaces it;
+    /// never one that already exists, so earlier backups are kept
+    fn import_backup_path(&self) -> String {
+        let base = format!(
+            "{}.pre-import-{}",
+            self.session_file_path.as_deref().unwrap_or("session.json"),
+            Utc::now().format("%Y%m%d-%H%M%S")
+        );
+        std::iter::once(base.clone())
+            .chain((1..).map(|n| format!("{}-{}", base, n)))
+            .find(|path| !Path::new(path).exists())
+            .unwrap_or(base)
+    }
+
+    /// Replace all users with a session in the full-session file format.
+    /// The current users are first written to a timestamped `.pre-import-*` file in
+    /// the same format, so importing that file undoes the import. Enemies are cleared.
+    /// Returns the number of imported users and the backup path.
+    pub fn import_session(&self, session: HashMap<String, User>) -> std::result::Result<(usize, String), String> {
+        if session.is_empty() {
+            return Err("Session contains no users".to_string());
+        }
+        let latest_allowed = self.now() + chrono::Duration::milliseconds(MAX_IMPORT_FUTURE_SKEW_MS);
+        let mut users = Vec::with_capacity(session.len());
+        for (uid_str, user) in session {
+            let uid = uid_str
+                .parse::<u32>()
+                .ok()
+                .filter(|uid| *uid != 0)
+                .ok_or_else(|| format!("Invalid uid key: {}", uid_str))?;
+            if user.uid != uid {
+                return Err(format!("User under key {} has uid {}", uid_str, user.uid));
+            }
+            validate_imported_user(&user, latest_allowed).map_err(|e| format!("User {}: {}", uid, e))?;
+            users.push(user);
+        }
+
+        let backup_path = self.import_backup_path();
+        let backup = serde_json::to_string(&self.full_session()).map_err(|e| e.to_string())?;
+        write_file(&backup_path, backup).map_err(|e| format!("Failed to back up current session to {}: {}", backup_path, e))?;
+
+        self.clear_all();
+        self.users.clear();
+        self.user_summaries.clear();
+        let count = users.len();
+        for mut user in users {
+            user.dirty = true;
+            self.users.insert(user.uid, Arc::new(RwLock::new(user)));
+        }
+
+        log::info!("Imported session with {} users (previous state backed up to {})", count, backup_path);
+        Ok((count, backup_path))
+    }
+
     async fn load_settings(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         if !Path::new(&self.settings_file_path).exists() {
             return Ok(());
         }
 
         let content = fs::read_to_string(&self.settings_file_path)?;
-        let settings: GlobalSettings = serde_json::from_str(&content)?;
+        let mut settings: GlobalSettings = serde_json::from_str(&content)?;
+        settings.sanitize();
         *self.settings.write() = settings;
 
         Ok(())
     }
 
     async fn load_skill_config(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        // Try to load skill names from tables/skill_names.json
-        let skill_file_path = "tables/skill_names.json";
-        if Path::new(skill_file_path).exists() {
-            let content = fs::read_to_string(skill_file_path)?;
-            let mut skill_config = self.skill_config.write();
-            skill_config.load_from_json(&content)?;
+        let Some(skill_file_path) = self.skill_config_path.as_deref() else {
+            return Ok(());
+        };
+        let json_path = Path::new(skill_file_path);
+        // A community `id,name` CSV next to the configured JSON is used when the JSON is missing
+        let csv_path = json_path.with_extension("csv");
+        let is_csv = json_path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+
+        if json_path.exists() && !is_csv {
+            let content = fs::read_to_string(json_path)?;
+            self.skill_config.write().load_from_json(&content)?;
             log::info!("Loaded skill configuration from {}", skill_file_path);
+        } else if csv_path.exists() {
+            let content = fs::read_to_string(&csv_path)?;
+            let loaded = self.skill_config.write().load_from_csv(&content);
+            log::info!("Loaded {} skill names from {:?}", loaded, csv_path);
         }
 
         Ok(())
     }
 
+    async fn load_name_table(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let Some(path) = self.name_table_path.as_deref() else {
+            return Ok(());
+        };
+        if Path::new(path).exists() {
+            let content = fs::read_to_string(path)?;
+            self.names.write().load_from_json(&content)?;
+            log::info!("Loaded profession/element names from {}", path);
+        } else {
+            log::warn!("Name table {} not found, using built-in names", path);
+        }
+
+        Ok(())
+    }
+
+    pub fn profession_name(&self, profession_id: u32) -> Option<String> {
+        self.names.read().profession_name(profession_id)
+    }
+
+    pub fn element_name(&self, property: u32) -> String {
+        self.names.read().element_name(property)
+    }
+
     pub async fn save_user_cache(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let mut cache_data = HashMap::new();
 
@@ -144,19 +580,95 @@ impl DataManager {
         }
 
         let content = serde_json::to_string_pretty(&cache_data)?;
-        fs::write(&self.cache_file_path, content)?;
+        let path = self.cache_file_path.clone();
+        let backups = self.user_cache_backups.load(Ordering::Relaxed);
+        tokio::task::spawn_blocking(move || {
+            // Unchanged saves would only push older backups out
+            if fs::read_to_string(&path).is_ok_and(|previous| previous == content) {
+                return Ok(());
+            }
+            rotate_backups(&path, backups)?;
+            write_file(&path, content)
+        })
+        .await??;
 
         log::debug!("Saved {} user cache entries", cache_data.len());
         Ok(())
     }
 
     pub async fn save_settings(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let settings = self.settings.read();
-        let content = serde_json::to_string_pretty(&*settings)?;
-        fs::write(&self.settings_file_path, content)?;
+        let content = serde_json::to_string_pretty(&*self.settings.read())?;
+        write_file_blocking(self.settings_file_path.clone(), content).await?;
         Ok(())
     }
 
+    pub fn get_capture_filter(&self) -> String {
+        self.capture_filter.borrow().clone()
+    }
+
+    /// Publish a new capture filter; the running capture picks it up on its next packet
+    pub fn set_capture_filter(&self, filter: String) {
+        self.capture_filter.send_replace(filter);
+    }
+
+    pub fn subscribe_capture_filter(&self) -> watch::Receiver<String> {
+        self.capture_filter.subscribe()
+    }
+
+    pub fn set_capture_restart_handler(&self, handler: mpsc::Sender<CaptureRestartReply>) {
+        *self.capture_restart.write() = Some(handler);
+    }
+
+    /// Ask the capture owner to stop and restart packet capture, waiting for the result
+    pub async fn restart_capture(&self) -> std::result::Result<(), String> {
+        let handler = self
+            .capture_restart
+            .read()
+            .clone()
+            .ok_or_else(|| "Packet capture is not running".to_string())?;
+
+        let (reply_tx, reply_rx) = oneshot::channel();
+        handler
+            .send(reply_tx)
+            .await
+            .map_err(|_| "Packet capture is not running".to_string())?;
+        reply_rx
+            .await
+            .map_err(|_| "Packet capture restart was interrupted".to_string())?
+    }
+
+    /// Ask everything watching `subscribe_shutdown` to stop
+    pub fn request_shutdown(&self) {
+        self.shutdown.send_replace(true);
+    }
+
+    pub fn subscribe_shutdown(&self) -> watch::Receiver<bool> {
+        self.shutdown.subscribe()
+    }
+
+    /// Resolves once a shutdown has been requested
+    pub async fn shutdown_requested(&self) {
+        let mut rx = self.subscribe_shutdown();
+        let _ = rx.wait_for(|requested| *requested).await;
+    }
+
+    /// Flush the combat log and save the user cache, full session and settings, logging failures
+    pub async fn persist_all(&self) {
+        self.flush_combat_log_file();
+
+        if let Err(e) = self.save_user_cache().await {
+            log::error!("Failed to save user cache on shutdown: {}", e);
+        }
+
+        if let Err(e) = self.save_full_session().await {
+            log::error!("Failed to save full session on shutdown: {}", e);
+        }
+
+        if let Err(e) = self.save_settings().await {
+            log::error!("Failed to save settings on shutdown: {}", e);
+        }
+    }
+
     pub fn get_or_create_user(&self, uid: u32) -> Arc<RwLock<User>> {
         self.users
             .entry(uid)
@@ -183,18 +695,15 @@ impl DataManager {
         hp_lessen: u64,
         target_uid: u32,
     ) {
-        if *self.is_paused.read() {
+        if !self.should_record_damage_to(target_uid) {
             return;
         }
-
-        if self.settings.read().only_record_elite_dummy && target_uid != 75 {
-            return;
-        }
-
+        let now = self.now();
+        self.track_encounter_activity(now).await;
         let user = self.get_or_create_user(uid);
         {
             let mut user_write = user.write();
-            user_write.add_damage(skill_id, element, damage, is_crit, is_lucky, is_cause_lucky, hp_lessen);
+            user_write.add_damage(skill_id, element.clone(), damage, is_crit, is_lucky, is_cause_lucky, hp_lessen, now);
 
             // Set sub profession based on skill
             if let Some(sub_profession) = get_sub_profession_by_skill_id(skill_id) {
@@ -202,6 +711,28 @@ impl DataManager {
             }
         }
 
+        self.get_or_create_enemy(target_uid)
+            .write()
+            .add_damage_received(uid, damage);
+
+        self.push_combat_log(DamageRecord {
+            timestamp: now,
+            ..DamageRecord::new(
+                uid,
+                target_uid,
+                skill_id,
+                element,
+                damage,
+                hp_lessen,
+                is_crit,
+                is_lucky,
+                is_cause_lucky,
+                false,
+                DamageSource::default(),
+                DamageProperty::default(),
+            )
+        });
+
         *self.last_log_time.write() = Utc::now();
     }
 
@@ -223,11 +754,13 @@ impl DataManager {
         if uid == 0 {
             return; // Skip healing from unknown source
         }
-
+        let now = self.now();
+        self.track_encounter_activity(now).await;
+        let missing_hp = self.apply_heal_to_target(target_uid, healing);
         let user = self.get_or_create_user(uid);
         {
             let mut user_write = user.write();
-            user_write.add_healing(skill_id, element, healing, is_crit, is_lucky, is_cause_lucky);
+            user_write.add_healing(skill_id, element.clone(), healing, is_crit, is_lucky, is_cause_lucky, missing_hp, now);
 
             // Set sub profession based on skill
             if let Some(sub_profession) = get_sub_profession_by_skill_id(skill_id) {
@@ -235,23 +768,118 @@ impl DataManager {
             }
         }
 
+        self.push_combat_log(DamageRecord {
+            is_heal: true,
+            timestamp: now,
+            ..DamageRecord::new(
+                uid,
+                target_uid,
+                skill_id,
+                element,
+                healing,
+                0,
+                is_crit,
+                is_lucky,
+                is_cause_lucky,
+                false,
+                DamageSource::default(),
+                DamageProperty::default(),
+            )
+        });
+
         *self.last_log_time.write() = Utc::now();
     }
 
-    pub async fn add_taken_damage(&self, uid: u32, damage: u32, is_dead: bool) {
+    /// Break down damage dealt by a summon (turret, pet) under its owner
+    pub async fn add_summon_damage(&self, uid: u32, summon_uuid: u64, damage: u64, target_uid: u32) {
+        if !self.should_record_damage_to(target_uid) {
+            return;
+        }
+
+        self.get_or_create_user(uid).write().add_summon_damage(summon_uuid, damage);
+    }
+
+    pub async fn add_miss(&self, uid: u32, skill_id: u32, element: String, target_uid: u32) {
+        if !self.should_record_damage_to(target_uid) {
+            return;
+        }
+
+        let now = self.now();
+        self.get_or_create_user(uid).write().add_miss(skill_id, element, now);
+        *self.last_log_time.write() = Utc::now();
+    }
+
+    /// Record damage taken by `uid` from the entity `source_uid`
+    pub async fn add_taken_damage(&self, uid: u32, source_uid: u32, damage: u32, is_dead: bool) {
         if *self.is_paused.read() {
             return;
         }
 
+        let now = self.now();
         let user = self.get_or_create_user(uid);
         {
             let mut user_write = user.write();
-            user_write.add_taken_damage(damage, is_dead);
+            user_write.add_taken_damage(source_uid, damage, is_dead, now);
         }
 
         *self.last_log_time.write() = Utc::now();
     }
 
+    /// Missing HP of the healed player before the heal, or `None` while their HP is unknown.
+    /// The target's HP is raised by the effective amount so back-to-back heals before the next
+    /// attribute sync aren't all counted as effective.
+    fn apply_heal_to_target(&self, target_uid: u32, healing: u64) -> Option<u64> {
+        let target = self.users.get(&target_uid)?.clone();
+        let mut target = target.write();
+        if target.max_hp == 0 {
+            return None;
+        }
+
+        let missing_hp = target.max_hp.saturating_sub(target.hp) as u64;
+        target.hp += healing.min(missing_hp) as u32;
+        target.dirty = true;
+        Some(missing_hp)
+    }
+
+    // Damage is dropped while paused, and outside the dummies in elite-dummy mode
+    fn should_record_damage_to(&self, target_uid: u32) -> bool {
+        if *self.is_paused.read() {
+            return false;
+        }
+
+        let settings = self.settings.read();
+        !settings.only_record_elite_dummy || settings.elite_dummy_target_ids.contains(&target_uid)
+    }
+
+    fn push_combat_log(&self, record: DamageRecord) {
+        if let Some(writer) = &self.combat_log_writer {
+            if let Err(e) = writer.write(&record) {
+                log::warn!("Failed to write combat log {:?}: {}", writer.path(), e);
+            }
+        }
+
+        let mut combat_log = self.combat_log.write();
+        combat_log.push_back(record);
+        let capacity = self.combat_log_capacity.load(Ordering::Relaxed);
+        while combat_log.len() > capacity {
+            combat_log.pop_front();
+        }
+    }
+
+    /// Most recent combat log records newer than `since`, oldest first
+    pub fn get_combat_log(&self, limit: usize, since: Option<DateTime<Utc>>) -> Vec<DamageRecord> {
+        let combat_log = self.combat_log.read();
+        let mut records: Vec<DamageRecord> = combat_log
+            .iter()
+            .rev()
+            .take_while(|record| since.is_none_or(|since| record.timestamp > since))
+            .take(limit)
+            .cloned()
+            .collect();
+        records.reverse();
+        records
+    }
+
     pub fn set_user_name(&self, uid: u32, name: String) {
         let user = self.get_or_create_user(uid);
         user.write().set_name(name);
@@ -288,64 +916,359 @@ impl DataManager {
     }
 
     pub fn update_dps(&self) {
+        let window_secs = self.settings.read().window_dps_secs;
+        let now = self.now();
         for user_entry in self.users.iter() {
-            user_entry.value().write().update_dps();
+            user_entry.value().write().update_dps(window_secs, now);
+        }
+    }
+
+    /// Anchor combat timestamps to the game server clock (milliseconds since the Unix epoch)
+    pub fn sync_server_time(&self, server_millis: i64) {
+        let offset = match server_millis.checked_sub(Utc::now().timestamp_millis()) {
+            Some(offset) if offset.abs() <= MAX_SERVER_CLOCK_OFFSET_MS => offset,
+            _ => {
+                log::warn!("Ignoring implausible server time {} ms", server_millis);
+                return;
+            }
+        };
+        let previous = self.server_clock_offset_ms.write().replace(offset);
+        if previous.is_none() {
+            log::info!("Synchronized with server clock, offset {} ms", offset);
+        }
+    }
+
+    /// Current time on the server clock, or local time until the server time is known
+    pub fn now(&self) -> DateTime<Utc> {
+        let now = Utc::now();
+        match *self.server_clock_offset_ms.read() {
+            Some(offset) => now.checked_add_signed(Duration::milliseconds(offset)).unwrap_or(now),
+            None => now,
         }
     }
 
+    pub fn record_message_type(&self, msg_type_id: u16) {
+        *self.message_type_counts.entry(msg_type_id).or_insert(0) += 1;
+    }
+
+    pub fn record_notify_method(&self, method_id: u32) {
+        *self.notify_method_counts.entry(method_id).or_insert(0) += 1;
+    }
+
+    /// Opcode counters are cumulative and survive `clear_all`; only this resets them
+    pub fn reset_opcode_counts(&self) {
+        self.message_type_counts.clear();
+        self.notify_method_counts.clear();
+    }
+
     pub fn update_hps(&self) {
         for user_entry in self.users.iter() {
             user_entry.value().write().update_hps();
         }
     }
 
+    /// Record a `(timestamp, total_damage)` sample for every user that has dealt damage
+    pub fn record_timeline(&self) {
+        let now = Utc::now();
+        let cutoff = now - Duration::seconds(TIMELINE_RETENTION_SECS);
+
+        for entry in self.users.iter() {
+            let total_damage = entry.value().read().damage_stats.total_damage;
+            if total_damage == 0 && !self.timelines.contains_key(entry.key()) {
+                continue;
+            }
+
+            let mut samples = self.timelines.entry(*entry.key()).or_default();
+            samples.push_back((now, total_damage));
+            while samples.front().is_some_and(|(t, _)| *t < cutoff) {
+                samples.pop_front();
+            }
+        }
+    }
+
+    /// Bucket a user's timeline samples into `(bucket_start_ms, dps)` points.
+    /// Each bucket's damage is divided by the time its samples actually cover, so the
+    /// still-filling last bucket (and the first one) aren't diluted by the full bucket width.
+    pub fn get_user_timeline(&self, uid: u32, bucket_ms: u64) -> Option<Vec<(i64, f64)>> {
+        if !self.users.contains_key(&uid) {
+            return None;
+        }
+
+        let samples = match self.timelines.get(&uid) {
+            Some(samples) => samples,
+            None => return Some(Vec::new()),
+        };
+
+        let bucket_ms = i64::try_from(bucket_ms).unwrap_or(i64::MAX).max(1);
+        let mut points = Vec::new();
+        // Last sample of the previous bucket: where this bucket's damage and time start
+        let (mut prev_ms, mut prev_total) = match samples.front() {
+            Some((t, total)) => (t.timestamp_millis(), *total),
+            None => return Some(points),
+        };
+        // (bucket index, last sample time, last sample total)
+        let mut current: Option<(i64, i64, u64)> = None;
+        let dps = |damage: u64, span_ms: i64| if span_ms > 0 { damage as f64 * 1000.0 / span_ms as f64 } else { 0.0 };
+
+        for (t, total) in samples.iter() {
+            let t_ms = t.timestamp_millis();
+            let bucket = t_ms.div_euclid(bucket_ms);
+            match current {
+                Some((b, last_ms, last_total)) if b != bucket => {
+                    points.push((b * bucket_ms, dps(last_total.saturating_sub(prev_total), last_ms - prev_ms)));
+                    prev_ms = last_ms;
+                    prev_total = last_total;
+                }
+                _ => {}
+            }
+            current = Some((bucket, t_ms, *total));
+        }
+
+        if let Some((b, last_ms, last_total)) = current {
+            points.push((b * bucket_ms, dps(last_total.saturating_sub(prev_total), last_ms - prev_ms)));
+        }
+
+        Some(points)
+    }
+
+    /// Sum of every user's current DPS
+    pub fn total_dps(&self) -> f64 {
+        self.users
+            .iter()
+            .map(|entry| entry.value().read().damage_stats.dps)
+            .sum()
+    }
+
+    /// Aggregate party totals in a single pass over the users
+    pub fn get_party_stats(&self) -> PartyStats {
+        let mut stats = PartyStats::default();
+        let mut top_dps = 0.0;
+        let mut top_hps = 0.0;
+
+        for entry in self.users.iter() {
+            let user = entry.value().read();
+
+            stats.member_count += 1;
+            stats.total_damage = stats.total_damage.saturating_add(user.damage_stats.total_damage);
+            stats.total_healing = stats.total_healing.saturating_add(user.healing_stats.total_healing);
+            stats.total_dps += user.damage_stats.dps;
+            stats.total_hps += user.healing_stats.hps;
+            stats.total_deaths += user.dead_count;
+
+            if user.damage_stats.dps > top_dps {
+                top_dps = user.damage_stats.dps;
+                stats.top_dps_uid = Some(*entry.key());
+            }
+            if user.healing_stats.hps > top_hps {
+                top_hps = user.healing_stats.hps;
+                stats.top_hps_uid = Some(*entry.key());
+            }
+        }
+
+        stats
+    }
+
     pub fn get_all_users_data(&self) -> HashMap<u32, serde_json::Value> {
+        self.user_summaries_with_hashes().0
+    }
+
+    /// Build one user's summary; the party percentages are filled in by the caller
+    fn user_summary(user: &User) -> serde_json::Value {
+        serde_json::json!({
+            "name": user.name,
+            "profession": format!("{}{}", user.profession, user.sub_profession),
+            "realtime_dps": user.damage_stats.dps,
+            "realtime_dps_max": user.damage_stats.dps_max,
+            "window_dps": user.damage_stats.window_dps,
+            "total_dps": user.damage_stats.dps,
+            "active_dps": user.damage_stats.active_dps,
+            "active_time_ms": user.damage_stats.active_time_ms,
+            "total_damage": {
+                "normal": user.damage_stats.normal_damage,
+                "critical": user.damage_stats.critical_damage,
+                "lucky": user.damage_stats.lucky_damage,
+                "crit_lucky": user.damage_stats.crit_lucky_damage,
+                "total": user.damage_stats.total_damage
+            },
+            "total_count": {
+                "normal": user.damage_stats.normal_count,
+                "critical": user.damage_stats.critical_count,
+                "lucky": user.damage_stats.lucky_count,
+                "total": user.damage_stats.total_count
+            },
+            "miss_count": user.damage_stats.miss_count,
+            "miss_rate": user.damage_stats.miss_rate(),
+            "crit_rate": user.damage_stats.crit_rate(),
+            "lucky_rate": user.damage_stats.lucky_rate(),
+            "element_damage": user.damage_stats.element_damage,
+            "realtime_hps": user.healing_stats.hps,
+            "realtime_hps_max": user.healing_stats.hps_max,
+            "total_hps": user.healing_stats.hps,
+            "total_healing": {
+                "normal": user.healing_stats.normal_healing,
+                "critical": user.healing_stats.critical_healing,
+                "lucky": user.healing_stats.lucky_healing,
+                "crit_lucky": user.healing_stats.crit_lucky_healing,
+                "total": user.healing_stats.total_healing
+            },
+            "effective_healing": user.healing_stats.effective_healing,
+            "overheal": user.healing_stats.overheal,
+            "overheal_rate": user.healing_stats.overheal_rate(),
+            "healing_crit_rate": user.healing_stats.crit_rate(),
+            "healing_lucky_rate": user.healing_stats.lucky_rate(),
+            "taken_damage": user.taken_damage,
+            "taken_by_source": user.taken_by_source,
+            "fight_point": user.fight_point,
+            "hp": user.hp,
+            "max_hp": user.max_hp,
+            "dead_count": user.dead_count,
+            "last_update": user.last_update.timestamp_millis()
+        })
+    }
+
+    /// Summaries of all users plus a change-detection hash for each.
+    /// Only users marked dirty since the last call are regenerated.
+    fn user_summaries_with_hashes(&self) -> (HashMap<u32, serde_json::Value>, HashMap<u32, u64>) {
         let mut result = HashMap::new();
+        let mut base_hashes = HashMap::new();
+        let mut party_damage: u64 = 0;
+        let mut party_healing: u64 = 0;
 
+        // First pass: refresh stale summaries and sum the party totals from the same read
         for entry in self.users.iter() {
             let uid = *entry.key();
-            let user = entry.value().read();
+            let cached = {
+                let user = entry.value().upgradable_read();
+                party_damage = party_damage.saturating_add(user.damage_stats.total_damage);
+                party_healing = party_healing.saturating_add(user.healing_stats.total_healing);
+
+                // Clone out of the cache first so its shard lock is released before inserting
+                let previous = if user.dirty { None } else { self.user_summaries.get(&uid).map(|cached| cached.clone()) };
+                match previous {
+                    Some(cached) => cached,
+                    None => {
+                        let mut user = RwLockUpgradableReadGuard::upgrade(user);
+                        user.dirty = false;
+                        let summary = Self::user_summary(&user);
+                        let mut hasher = DefaultHasher::new();
+                        summary.to_string().hash(&mut hasher);
+                        let cached = CachedSummary { summary, hash: hasher.finish() };
+                        self.user_summaries.insert(uid, cached.clone());
+                        cached
+                    }
+                }
+            };
 
-            let summary = serde_json::json!({
-                "name": user.name,
-                "profession": format!("{}{}", user.profession, user.sub_profession),
-                "realtime_dps": user.damage_stats.dps,
-                "realtime_dps_max": user.damage_stats.dps_max,
-                "total_dps": user.damage_stats.dps,
-                "total_damage": {
-                    "normal": user.damage_stats.normal_damage,
-                    "critical": user.damage_stats.critical_damage,
-                    "lucky": user.damage_stats.lucky_damage,
-                    "crit_lucky": user.damage_stats.crit_lucky_damage,
-                    "total": user.damage_stats.total_damage
-                },
-                "total_count": {
-                    "normal": user.damage_stats.normal_count,
-                    "critical": user.damage_stats.critical_count,
-                    "lucky": user.damage_stats.lucky_count,
-                    "total": user.damage_stats.total_count
-                },
-                "realtime_hps": user.healing_stats.hps,
-                "realtime_hps_max": user.healing_stats.hps_max,
-                "total_hps": user.healing_stats.hps,
-                "total_healing": {
-                    "normal": user.healing_stats.normal_healing,
-                    "critical": user.healing_stats.critical_healing,
-                    "lucky": user.healing_stats.lucky_healing,
-                    "crit_lucky": user.healing_stats.crit_lucky_healing,
-                    "total": user.healing_stats.total_healing
-                },
-                "taken_damage": user.taken_damage,
-                "fight_point": user.fight_point,
-                "hp": user.hp,
-                "max_hp": user.max_hp,
-                "dead_count": user.dead_count
-            });
+            base_hashes.insert(uid, cached.hash);
+            result.insert(uid, cached.summary);
+        }
+        if self.user_summaries.len() > result.len() {
+            self.user_summaries.retain(|uid, _| result.contains_key(uid));
+        }
 
-            result.insert(uid, summary);
+        // Second pass: fill in each user's share of the party total
+        let percent = |value: u64, total: u64| {
+            if total == 0 {
+                0.0
+            } else {
+                value as f64 / total as f64 * 100.0
+            }
+        };
+        let now_ms = self.now().timestamp_millis();
+        let idle_threshold_secs = self.settings.read().idle_threshold_secs;
+        let mut hashes = HashMap::with_capacity(result.len());
+        for (uid, summary) in result.iter_mut() {
+            let damage = summary["total_damage"]["total"].as_u64().unwrap_or(0);
+            let healing = summary["total_healing"]["total"].as_u64().unwrap_or(0);
+            let damage_percent = percent(damage, party_damage);
+            let healing_percent = percent(healing, party_healing);
+            summary["damage_percent"] = serde_json::json!(damage_percent);
+            summary["healing_percent"] = serde_json::json!(healing_percent);
+
+            // idle_seconds ticks constantly, so only the idle flag counts as a change
+            let last_update = summary["last_update"].as_i64().unwrap_or(now_ms);
+            let idle_seconds = (now_ms - last_update).max(0) as f64 / 1000.0;
+            let idle = idle_seconds >= idle_threshold_secs as f64;
+            summary["idle_seconds"] = serde_json::json!(idle_seconds);
+            summary["idle"] = serde_json::json!(idle);
+
+            let mut hasher = DefaultHasher::new();
+            (base_hashes[uid], damage_percent.to_bits(), healing_percent.to_bits(), idle).hash(&mut hasher);
+            hashes.insert(*uid, hasher.finish());
         }
 
-        result
+        (result, hashes)
+    }
+
+    /// User summaries sorted by `metric` (highest first, ties by uid), each with `uid` and a 1-based `rank`
+    pub fn get_ranking(&self, metric: RankingMetric, limit: Option<usize>) -> Vec<serde_json::Value> {
+        let value_of = |summary: &serde_json::Value| match metric {
+            RankingMetric::Dps => summary["total_dps"].as_f64().unwrap_or(0.0),
+            RankingMetric::Damage => summary["total_damage"]["total"].as_u64().unwrap_or(0) as f64,
+            RankingMetric::Healing => summary["total_healing"]["total"].as_u64().unwrap_or(0) as f64,
+        };
+
+        let mut users: Vec<(u32, serde_json::Value)> = self.get_all_users_data().into_iter().collect();
+        users.sort_by(|(a_uid, a), (b_uid, b)| value_of(b).total_cmp(&value_of(a)).then(a_uid.cmp(b_uid)));
+        users.truncate(limit.unwrap_or(usize::MAX));
+
+        users
+            .into_iter()
+            .enumerate()
+            .map(|(i, (uid, mut summary))| {
+                summary["uid"] = serde_json::json!(uid);
+                summary["rank"] = serde_json::json!(i + 1);
+                summary
+            })
+            .collect()
+    }
+
+    /// A user's damage skills and healing skills, both keyed by skill id
+    pub fn get_user_skills(&self, uid: u32) -> Option<(HashMap<u32, SkillStats>, HashMap<u32, SkillStats>)> {
+        self.users.get(&uid).map(|user| {
+            let user = user.read();
+            (user.damage_skills.clone(), user.healing_skills.clone())
+        })
+    }
+
+    /// One damage or healing skill of a user
+    pub fn get_user_skill(&self, uid: u32, skill_id: u32, kind: SkillKind) -> Option<SkillStats> {
+        let user = self.users.get(&uid)?;
+        let user = user.read();
+        let skills = match kind {
+            SkillKind::Damage => &user.damage_skills,
+            SkillKind::Healing => &user.healing_skills,
+        };
+        skills.get(&skill_id).cloned()
+    }
+
+    pub fn get_user_summon_damage(&self, uid: u32) -> Option<HashMap<u64, u64>> {
+        self.users
+            .get(&uid)
+            .map(|user| user.read().summon_damage.clone())
+    }
+
+    /// Build a snapshot of all users for WebSocket clients
+    pub fn users_snapshot(&self) -> UsersSnapshot {
+        let (users, hashes) = self.user_summaries_with_hashes();
+        UsersSnapshot { users, hashes }
+    }
+
+    /// Push one snapshot to all WebSocket subscribers, skipping the work if nobody listens
+    pub fn broadcast_users_update(&self) {
+        if self.ws_tx.receiver_count() == 0 {
+            return;
+        }
+
+        let _ = self.ws_tx.send(Arc::new(self.users_snapshot()));
+    }
+
+    pub fn subscribe_encounter_events(&self) -> broadcast::Receiver<EncounterEvent> {
+        self.encounter_tx.subscribe()
+    }
+
+    pub fn subscribe_updates(&self) -> broadcast::Receiver<Arc<UsersSnapshot>> {
+        self.ws_tx.subscribe()
     }
 
     pub fn get_all_enemies_data(&self) -> HashMap<u32, serde_json::Value> {
@@ -358,7 +1281,8 @@ impl DataManager {
             let data = serde_json::json!({
                 "name": enemy.name,
                 "hp": enemy.hp,
-                "max_hp": enemy.max_hp
+                "max_hp": enemy.max_hp,
+                "total_damage_received": enemy.total_damage_received
             });
 
             result.insert(id, data);
@@ -367,13 +1291,61 @@ impl DataManager {
         result
     }
 
+    /// Damage dealt to an enemy per attacker uid, highest first
+    pub fn get_enemy_damage_breakdown(&self, id: u32) -> Option<(u64, Vec<(u32, u64)>)> {
+        let enemy = self.enemies.get(&id)?;
+        let enemy = enemy.read();
+
+        let mut attackers: Vec<(u32, u64)> = enemy
+            .damage_by_attacker
+            .iter()
+            .map(|(uid, damage)| (*uid, *damage))
+            .collect();
+        attackers.sort_by_key(|(_, damage)| std::cmp::Reverse(*damage));
+
+        Some((enemy.total_damage_received, attackers))
+    }
+
+    /// Reset a single user's stats and drop their derived data. Returns false if unknown.
+    pub fn reset_user(&self, uid: u32) -> bool {
+        let Some(user) = self.users.get(&uid).map(|entry| entry.value().clone()) else {
+            return false;
+        };
+        user.write().reset(self.now());
+
+        self.timelines.remove(&uid);
+        self.combat_log.write().retain(|record| record.attacker_uid != uid);
+        for enemy in self.enemies.iter() {
+            enemy.value().write().remove_attacker(uid);
+        }
+
+        true
+    }
+
     pub fn clear_all(&self) {
+        self.clear_users();
+        self.clear_enemies();
+    }
+
+    /// Reset player stats, keeping enemy HP tracking
+    pub fn clear_users(&self) {
         // Clear all users
+        let now = self.now();
         for user_entry in self.users.iter() {
-            user_entry.value().write().reset();
+            user_entry.value().write().reset(now);
         }
 
-        // Clear all enemies
+        // Clear DPS timelines
+        self.timelines.clear();
+
+        // Clear combat log
+        self.combat_log.write().clear();
+
+        *self.encounter.write() = EncounterTracker::default();
+    }
+
+    /// Forget all enemies, keeping player stats
+    pub fn clear_enemies(&self) {
         self.enemies.clear();
     }
 
@@ -399,4 +1371,520 @@ impl DataManager {
             log::info!("Statistics cleared due to timeout");
         }
     }
+
+    /// Damage or healing resumed: start a new encounter, clearing the stats of the one finalized before
+    /// once its snapshot has been saved
+    async fn track_encounter_activity(&self, now: DateTime<Utc>) {
+        if self.encounter.read().pending_reset {
+            let _saved = self.encounter_save.lock().await;
+            let pending_reset = std::mem::take(&mut self.encounter.write().pending_reset);
+            if pending_reset {
+                self.clear_all();
+            }
+        }
+
+        let started = {
+            let mut encounter = self.encounter.write();
+            encounter.last_activity = Some(now);
+            encounter.start.is_none().then(|| *encounter.start.insert(now))
+        };
+        if let Some(start) = started {
+            if self.settings.read().segment_encounters {
+                let _ = self.encounter_tx.send(EncounterEvent::Started { start });
+            }
+        }
+    }
+
+    /// Finalize the current encounter once no damage or healing was seen for the configured idle gap.
+    /// The caller saves the returned segment's snapshot while holding the guard; the stats are only
+    /// cleared for the next encounter after it is dropped.
+    pub fn finish_idle_encounter(&self) -> Option<(EncounterSegment, OwnedMutexGuard<()>)> {
+        self.finish_idle_encounter_at(self.now())
+    }
+
+    fn finish_idle_encounter_at(&self, now: DateTime<Utc>) -> Option<(EncounterSegment, OwnedMutexGuard<()>)> {
+        let (enabled, gap_secs) = {
+            let settings = self.settings.read();
+            (settings.segment_encounters, settings.encounter_idle_gap_secs)
+        };
+        if !enabled {
+            return None;
+        }
+        let gap = Duration::seconds(gap_secs.clamp(1, MAX_ENCOUNTER_IDLE_GAP_SECS) as i64);
+        let save_guard = self.encounter_save.clone().try_lock_owned().ok()?;
+
+        let (start, end) = {
+            let mut encounter = self.encounter.write();
+            let (Some(start), Some(end)) = (encounter.start, encounter.last_activity) else {
+                return None;
+            };
+            if now.signed_duration_since(end) <= gap {
+                return None;
+            }
+            encounter.start = None;
+            encounter.pending_reset = true;
+            (start, end)
+        };
+
+        let mut total_damage: u64 = 0;
+        let mut top_dps: Option<EncounterTopDps> = None;
+        for entry in self.users.iter() {
+            let user = entry.value().read();
+            total_damage = total_damage.saturating_add(user.damage_stats.total_damage);
+            if user.damage_stats.total_damage > 0
+                && top_dps.as_ref().is_none_or(|top| user.damage_stats.dps > top.dps)
+            {
+                top_dps = Some(EncounterTopDps {
+                    uid: user.uid,
+                    name: user.name.clone(),
+                    dps: user.damage_stats.dps,
+                });
+            }
+        }
+
+        let segment = EncounterSegment {
+            start,
+            end,
+            duration_secs: (end - start).num_seconds(),
+            total_damage,
+            top_dps,
+            snapshot_timestamp: end.timestamp(),
+        };
+
+        {
+            let mut encounters = self.encounters.write();
+            encounters.push_back(segment.clone());
+            while encounters.len() > MAX_ENCOUNTER_SEGMENTS {
+                encounters.pop_front();
+            }
+        }
+        let _ = self.encounter_tx.send(EncounterEvent::Ended(segment.clone()));
+        Some((segment, save_guard))
+    }
+
+    /// Finished encounters, newest first
+    pub fn get_encounters(&self) -> Vec<EncounterSegment> {
+        self.encounters.read().iter().rev().cloned().collect()
+    }
+}
+
+/// Reject imported stats that the meter could never have produced: negative or
+/// non-finite rates, and timestamps that are reversed or lie in the future
+fn validate_imported_user(user: &User, latest_allowed: DateTime<Utc>) -> std::result::Result<(), String> {
+    let damage = &user.damage_stats;
+    let healing = &user.healing_stats;
+    let rates = [
+        ("dps", damage.dps),
+        ("dps_max", damage.dps_max),
+        ("window_dps", damage.window_dps),
+        ("active_dps", damage.active_dps),
+        ("hps", healing.hps),
+        ("hps_max", healing.hps_max),
+    ];
+    let skills = user.damage_skills.values().chain(user.healing_skills.values());
+    let skill_rates = skills.clone().flat_map(|skill| [("crit_rate", skill.crit_rate), ("lucky_rate", skill.lucky_rate)]);
+    if let Some((name, value)) = rates.into_iter().chain(skill_rates).find(|(_, value)| !value.is_finite() || *value < 0.0) {
+        return Err(format!("{} must be a non-negative number, got {}", name, value));
+    }
+
+    let ranges = [damage.time_range, healing.time_range]
+        .into_iter()
+        .chain(skills.map(|skill| skill.time_range))
+        .flatten();
+    for (start, end) in ranges {
+        if start > end {
+            return Err(format!("time range starts at {} after it ends at {}", start, end));
+        }
+        if end > latest_allowed {
+            return Err(format!("time range ends in the future at {}", end));
+        }
+    }
+    if user.last_update > latest_allowed {
+        return Err(format!("last_update is in the future at {}", user.last_update));
+    }
+    Ok(())
+}
+
+fn read_user_cache(path: &str) -> Result<HashMap<String, UserCache>, Box<dyn std::error::Error + Send + Sync>> {
+    let content = fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+fn backup_path(path: &str, index: usize) -> String {
+    format!("{}.{}", path, index)
+}
+
+/// Shift `<path>.1`..`.N-1` up by one and move the current file to `<path>.1`
+fn rotate_backups(path: &str, count: usize) -> std::io::Result<()> {
+    if count == 0 || !Path::new(path).exists() {
+        return Ok(());
+    }
+
+    let _ = fs::remove_file(backup_path(path, count));
+    for index in (1..count).rev() {
+        let from = backup_path(path, index);
+        if Path::new(&from).exists() {
+            fs::rename(&from, backup_path(path, index + 1))?;
+        }
+    }
+    fs::copy(path, backup_path(path, 1))?;
+    Ok(())
+}
+
+/// Write a persistence file, creating its directory first since the paths are configurable
+fn write_file(path: &str, content: String) -> std::io::Result<()> {
+    if let Some(parent) = Path::new(path).parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    crate::utils::write_atomic(path, content)
+}
+
+/// `write_file` on the blocking pool, for the async save paths
+async fn write_file_blocking(path: String, content: String) -> std::io::Result<()> {
+    tokio::task::spawn_blocking(move || write_file(&path, content))
+        .await
+        .map_err(std::io::Error::other)?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_encounter_finalized_after_idle_gap() {
+        let data_manager = Arc::new(DataManager::new());
+        data_manager.settings.write().segment_encounters = true;
+        let start = Utc::now();
+        let mut events = data_manager.subscribe_encounter_events();
+
+        data_manager.track_encounter_activity(start).await;
+        data_manager.track_encounter_activity(start + Duration::seconds(30)).await;
+        data_manager.get_or_create_user(1).write().add_damage(1001, "🔥火".to_string(), 1000, false, false, false, 1000, start);
+        data_manager.get_or_create_user(1).write().damage_stats.dps = 100.0;
+
+        // Still within the idle gap
+        assert!(data_manager.finish_idle_encounter_at(start + Duration::seconds(40)).is_none());
+
+        let (segment, save_guard) = data_manager.finish_idle_encounter_at(start + Duration::seconds(50)).unwrap();
+        assert_eq!(segment.duration_secs, 30);
+        assert_eq!(segment.total_damage, 1000);
+        assert_eq!(segment.top_dps.as_ref().map(|top| top.uid), Some(1));
+        assert!(matches!(events.try_recv(), Ok(EncounterEvent::Started { start: s }) if s == start));
+        assert!(matches!(events.try_recv(), Ok(EncounterEvent::Ended(ended)) if ended.duration_secs == 30));
+        assert!(data_manager.finish_idle_encounter_at(start + Duration::seconds(60)).is_none());
+
+        // Stats from the finished encounter are kept until its snapshot is saved and combat resumes
+        let resumed = tokio::spawn({
+            let data_manager = data_manager.clone();
+            async move { data_manager.track_encounter_activity(start + Duration::seconds(90)).await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!resumed.is_finished());
+        assert_eq!(data_manager.get_or_create_user(1).read().damage_stats.total_damage, 1000);
+        drop(save_guard);
+        resumed.await.unwrap();
+        assert_eq!(data_manager.get_or_create_user(1).read().damage_stats.total_damage, 0);
+        assert_eq!(data_manager.encounter.read().start, Some(start + Duration::seconds(90)));
+        assert_eq!(data_manager.get_encounters().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_healing_counts_as_encounter_activity() {
+        let data_manager = DataManager::new();
+        data_manager.settings.write().segment_encounters = true;
+        data_manager.settings.write().encounter_idle_gap_secs = u64::MAX;
+
+        data_manager.add_healing(1, 2001, "🌟光".to_string(), 500, false, false, false, 1).await;
+        assert!(data_manager.encounter.read().start.is_some());
+        assert!(data_manager.finish_idle_encounter_at(Utc::now() + Duration::days(1)).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_encounter_uses_server_clock() {
+        let data_manager = DataManager::new();
+        data_manager.settings.write().segment_encounters = true;
+        data_manager.settings.write().encounter_idle_gap_secs = 60;
+        data_manager.sync_server_time(Utc::now().timestamp_millis() - 120_000);
+
+        data_manager.add_damage(1, 1001, "🔥火".to_string(), 100, false, false, false, 100, 75).await;
+        let start = data_manager.encounter.read().start.unwrap();
+        let hit = data_manager.get_or_create_user(1).read().damage_stats.time_range.unwrap().0;
+        assert_eq!(start, hit);
+        assert!(start < Utc::now() - Duration::seconds(100));
+
+        // Idle time is measured on the server clock too; on the local clock it would already exceed the gap
+        assert!(data_manager.finish_idle_encounter().is_none());
+    }
+
+    #[test]
+    fn test_clear_scopes() {
+        let data_manager = DataManager::new();
+        let now = Utc::now();
+        data_manager.get_or_create_user(1).write().add_damage(1001, "🔥火".to_string(), 1000, false, false, false, 1000, now);
+        data_manager.get_or_create_enemy(75).write().add_damage_received(1, 1000);
+
+        data_manager.clear_enemies();
+        assert!(data_manager.enemies.is_empty());
+        assert_eq!(data_manager.get_or_create_user(1).read().damage_stats.total_damage, 1000);
+
+        data_manager.get_or_create_enemy(75).write().add_damage_received(1, 1000);
+        data_manager.clear_users();
+        assert_eq!(data_manager.get_or_create_user(1).read().damage_stats.total_damage, 0);
+        assert_eq!(data_manager.enemies.len(), 1);
+    }
+
+    #[test]
+    fn test_ranking_sorted_by_metric() {
+        let data_manager = DataManager::new();
+        let now = Utc::now();
+        data_manager.get_or_create_user(1).write().add_damage(1001, "🔥火".to_string(), 100, false, false, false, 100, now);
+        data_manager.get_or_create_user(2).write().add_damage(1001, "🔥火".to_string(), 500, false, false, false, 500, now);
+        data_manager.get_or_create_user(3).write().add_healing(2001, "🌟光".to_string(), 900, false, false, false, None, now);
+
+        let by_damage = data_manager.get_ranking(RankingMetric::Damage, Some(2));
+        assert_eq!(by_damage.len(), 2);
+        assert_eq!((by_damage[0]["uid"].as_u64(), by_damage[0]["rank"].as_u64()), (Some(2), Some(1)));
+        assert_eq!((by_damage[1]["uid"].as_u64(), by_damage[1]["rank"].as_u64()), (Some(1), Some(2)));
+
+        let by_healing = data_manager.get_ranking(RankingMetric::Healing, None);
+        assert_eq!(by_healing.len(), 3);
+        assert_eq!(by_healing[0]["uid"].as_u64(), Some(3));
+        // Equal values keep a stable uid order
+        assert_eq!(by_healing[1]["uid"].as_u64(), Some(1));
+    }
+
+    #[test]
+    fn test_summaries_regenerated_only_when_dirty() {
+        let data_manager = DataManager::new();
+        let now = Utc::now();
+        data_manager.get_or_create_user(1).write().add_damage(1001, "🔥火".to_string(), 100, false, false, false, 100, now);
+        data_manager.get_or_create_user(2).write().add_damage(1001, "🔥火".to_string(), 300, false, false, false, 300, now);
+
+        data_manager.update_dps();
+        let first = data_manager.users_snapshot();
+        assert!(!data_manager.get_or_create_user(1).read().dirty);
+
+        // Nothing changed: the same summaries and hashes come back
+        data_manager.update_dps();
+        assert!(!data_manager.get_or_create_user(1).read().dirty);
+        let idle = data_manager.users_snapshot();
+        assert_eq!(idle.hashes, first.hashes);
+
+        // Only the damaged user is rebuilt, but both shares of the party total move
+        data_manager.get_or_create_user(2).write().add_damage(1001, "🔥火".to_string(), 600, false, false, false, 600, now);
+        let changed = data_manager.users_snapshot();
+        assert_eq!(changed.users[&2]["total_damage"]["total"].as_u64(), Some(900));
+        assert_eq!(changed.users[&1]["damage_percent"].as_f64(), Some(10.0));
+        assert_ne!(changed.hashes[&1], first.hashes[&1]);
+        assert_ne!(changed.hashes[&2], first.hashes[&2]);
+    }
+
+    #[tokio::test]
+    async fn test_users_report_idle_after_threshold() {
+        let data_manager = DataManager::new();
+        // Idle time is measured on the server clock that stamps the hits
+        data_manager.sync_server_time(Utc::now().timestamp_millis() + 120_000);
+        let earlier = data_manager.now() - Duration::seconds(60);
+        data_manager.get_or_create_user(1).write().add_damage(1001, "🔥火".to_string(), 100, false, false, false, 100, earlier);
+
+        let snapshot = data_manager.users_snapshot();
+        assert_eq!(snapshot.users[&1]["last_update"].as_i64(), Some(earlier.timestamp_millis()));
+        assert!(snapshot.users[&1]["idle_seconds"].as_f64().unwrap() >= 60.0);
+        assert_eq!(snapshot.users[&1]["idle"], true);
+
+        // Taking damage counts as activity too
+        data_manager.add_taken_damage(1, 75, 10, false).await;
+        let active = data_manager.users_snapshot();
+        assert_eq!(active.users[&1]["idle"], false);
+        assert_ne!(active.hashes[&1], snapshot.hashes[&1]);
+    }
+
+    #[tokio::test]
+    async fn test_user_cache_falls_back_to_backup() {
+        let dir = std::env::temp_dir().join(format!("meter-core-usercache-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let cache_path = dir.join("users.json").to_string_lossy().into_owned();
+
+        let data_manager = DataManager { cache_file_path: cache_path.clone(), ..DataManager::new() };
+        data_manager.set_user_name(1, "First".to_string());
+        data_manager.save_user_cache().await.unwrap();
+        data_manager.set_user_name(1, "Second".to_string());
+        data_manager.save_user_cache().await.unwrap();
+        // Unchanged data doesn't rotate the backups again
+        data_manager.save_user_cache().await.unwrap();
+        assert!(Path::new(&format!("{}.1", cache_path)).exists());
+        assert!(!Path::new(&format!("{}.2", cache_path)).exists());
+
+        fs::write(&cache_path, "{ not json").unwrap();
+        let restored = DataManager { cache_file_path: cache_path.clone(), ..DataManager::new() };
+        restored.load_user_cache().await.unwrap();
+        assert_eq!(restored.get_or_create_user(1).read().name, "First");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_import_session_replaces_users_and_backs_up() {
+        let dir = std::env::temp_dir().join(format!("meter-core-import-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut data_manager = DataManager::new();
+        data_manager.session_file_path = Some(dir.join("session.json").to_string_lossy().to_string());
+
+        data_manager.get_or_create_user(1).write().add_damage(1001, "fire".to_string(), 100, false, false, false, 100, Utc::now());
+        data_manager.get_or_create_enemy(75);
+
+        let mut imported = User::new(2);
+        imported.set_name("Friend".to_string());
+        let session = HashMap::from([("2".to_string(), imported.clone())]);
+
+        let bad = HashMap::from([("3".to_string(), imported.clone())]);
+        assert!(data_manager.import_session(bad).is_err());
+        assert!(data_manager.users.contains_key(&1));
+
+        let (count, first_backup) = data_manager.import_session(session).unwrap();
+        assert_eq!(count, 1);
+        assert!(!data_manager.users.contains_key(&1));
+        assert_eq!(data_manager.users.get(&2).unwrap().read().name, "Friend");
+        assert!(data_manager.enemies.is_empty());
+
+        // The backup is itself importable and restores the previous users
+        let backup: HashMap<String, User> = serde_json::from_str(&fs::read_to_string(&first_backup).unwrap()).unwrap();
+        assert!(backup.contains_key("1"));
+        let (count, second_backup) = data_manager.import_session(backup).unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(data_manager.users.get(&1).unwrap().read().damage_stats.total_damage, 100);
+
+        // A second import keeps the first backup instead of overwriting it
+        assert_ne!(first_backup, second_backup);
+        assert!(Path::new(&first_backup).exists());
+        let backup: HashMap<String, User> = serde_json::from_str(&fs::read_to_string(&second_backup).unwrap()).unwrap();
+        assert!(backup.contains_key("2"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_import_session_validates_users() {
+        let data_manager = DataManager::new();
+        let import = |user: User| data_manager.import_session(HashMap::from([(user.uid.to_string(), user)]));
+
+        assert!(import(User::new(0)).unwrap_err().contains("Invalid uid"));
+
+        let mut negative = User::new(5);
+        negative.damage_stats.dps = -1.0;
+        assert!(import(negative).unwrap_err().contains("dps"));
+
+        let mut future = User::new(5);
+        future.last_update = Utc::now() + chrono::Duration::days(7);
+        assert!(import(future).unwrap_err().contains("last_update"));
+
+        let mut reversed = User::new(5);
+        let now = Utc::now();
+        reversed.damage_stats.time_range = Some((now, now - chrono::Duration::seconds(10)));
+        assert!(import(reversed).unwrap_err().contains("time range"));
+
+        assert!(data_manager.users.is_empty());
+    }
+
+    #[test]
+    fn test_user_timeline_buckets() {
+        let data_manager = DataManager::new();
+        assert!(data_manager.get_user_timeline(1, 5000).is_none());
+        data_manager.get_or_create_user(1);
+        assert_eq!(data_manager.get_user_timeline(1, 5000), Some(Vec::new()));
+
+        // One sample per second at 1000 damage/s for 12s, starting on a bucket boundary
+        let start = DateTime::from_timestamp_millis(1_700_000_000_000).unwrap();
+        let samples = (0..=12).map(|s| (start + Duration::seconds(s), s as u64 * 1000)).collect();
+        data_manager.timelines.insert(1, samples);
+
+        let points = data_manager.get_user_timeline(1, 5000).unwrap();
+        let start_ms = start.timestamp_millis();
+        assert_eq!(points.iter().map(|(t, _)| *t - start_ms).collect::<Vec<_>>(), vec![0, 5000, 10000]);
+        // The partial last bucket (2s so far) reports the same rate as the full ones
+        for (_, dps) in &points {
+            assert!((dps - 1000.0).abs() < 1e-9, "{:?}", points);
+        }
+
+        // A single sample has no elapsed time and reports 0 instead of dividing by zero
+        data_manager.timelines.insert(1, VecDeque::from([(start, 5000)]));
+        assert_eq!(data_manager.get_user_timeline(1, 5000), Some(vec![(start_ms, 0.0)]));
+        assert!(data_manager.get_user_timeline(1, 0).is_some());
+    }
+
+    #[test]
+    fn test_sync_server_time_rejects_implausible_offsets() {
+        let data_manager = DataManager::new();
+        data_manager.sync_server_time(i64::MAX);
+        data_manager.sync_server_time(i64::MIN);
+        assert_eq!(*data_manager.server_clock_offset_ms.read(), None);
+
+        let server_millis = Utc::now().timestamp_millis() + 5000;
+        data_manager.sync_server_time(server_millis);
+        let offset = data_manager.server_clock_offset_ms.read().unwrap();
+        assert!((4000..=5000).contains(&offset));
+        assert!(data_manager.now() > Utc::now());
+    }
+
+    #[tokio::test]
+    async fn test_loaded_settings_are_clamped() {
+        let dir = std::env::temp_dir().join(format!("meter-core-settings-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let settings_path = dir.join("settings.json");
+        let mut settings = GlobalSettings { window_dps_secs: u64::MAX, ..GlobalSettings::default() };
+        fs::write(&settings_path, serde_json::to_string(&settings).unwrap()).unwrap();
+
+        let data_manager = DataManager { settings_file_path: settings_path.to_string_lossy().into_owned(), ..DataManager::new() };
+        data_manager.load_settings().await.unwrap();
+        assert_eq!(data_manager.settings.read().window_dps_secs, MAX_WINDOW_DPS_SECS);
+
+        settings.window_dps_secs = 0;
+        settings.sanitize();
+        assert_eq!(settings.window_dps_secs, 1);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_session_is_moved_aside() {
+        let dir = std::env::temp_dir().join(format!("meter-core-session-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let session_path = dir.join("session.json");
+        fs::write(&session_path, "{ not json").unwrap();
+
+        let data_manager = DataManager {
+            session_file_path: Some(session_path.to_string_lossy().into_owned()),
+            ..DataManager::new()
+        };
+        data_manager.load_full_session().await.unwrap();
+        assert!(data_manager.users.is_empty());
+        assert!(!session_path.exists());
+        let moved: Vec<_> = fs::read_dir(&dir).unwrap().filter_map(|entry| entry.ok()).collect();
+        assert_eq!(moved.len(), 1);
+        assert!(moved[0].file_name().to_string_lossy().starts_with("session.json.corrupt-"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_get_user_skill_by_kind() {
+        let data_manager = DataManager::new();
+        let now = Utc::now();
+        {
+            let user = data_manager.get_or_create_user(1);
+            let mut user = user.write();
+            user.add_damage(100, "🌟光".to_string(), 500, false, false, false, 500, now);
+            user.add_healing(100, "🌟光".to_string(), 300, false, false, false, None, now);
+        }
+
+        let damage = data_manager.get_user_skill(1, 100, SkillKind::Damage).unwrap();
+        let healing = data_manager.get_user_skill(1, 100, SkillKind::Healing).unwrap();
+        assert_eq!(damage.skill_type, "damage");
+        assert_eq!(healing.skill_type, "healing");
+        assert_eq!(healing.total_damage, 300);
+        assert!(data_manager.get_user_skill(1, 200, SkillKind::Damage).is_none());
+    }
 }
@@ -0,0 +1,135 @@
+//! Append-only JSON-lines combat log, rotated by size
+
+use crate::models::DamageRecord;
+use parking_lot::Mutex;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+struct WriterState {
+    file: Option<BufWriter<File>>,
+    written: u64,
+}
+
+/// Writes every combat log record as one JSON object per line. When the file reaches
+/// `max_bytes` it is renamed to `<path>.1` (older files shift up to `<path>.<max_files>`).
+#[derive(Debug)]
+pub struct CombatLogWriter {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    state: Mutex<WriterState>,
+}
+
+impl CombatLogWriter {
+    pub fn new(path: impl Into<PathBuf>, max_bytes: u64, max_files: usize) -> Self {
+        Self {
+            path: path.into(),
+            max_bytes: max_bytes.max(1),
+            max_files,
+            state: Mutex::new(WriterState { file: None, written: 0 }),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Buffer one record; data reaches the disk on `flush` or when the buffer fills up
+    pub fn write(&self, record: &DamageRecord) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        let mut state = self.state.lock();
+        if state.file.is_some() && state.written + line.len() as u64 > self.max_bytes {
+            if let Some(mut file) = state.file.take() {
+                file.flush()?;
+            }
+            self.rotate()?;
+            state.written = 0;
+        }
+
+        if state.file.is_none() {
+            if let Some(parent) = self.path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+                fs::create_dir_all(parent)?;
+            }
+            let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+            state.written = file.metadata()?.len();
+            state.file = Some(BufWriter::new(file));
+        }
+
+        if let Some(file) = state.file.as_mut() {
+            file.write_all(&line)?;
+        }
+        state.written += line.len() as u64;
+        Ok(())
+    }
+
+    pub fn flush(&self) -> std::io::Result<()> {
+        match self.state.lock().file.as_mut() {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&self) -> std::io::Result<()> {
+        if self.max_files == 0 {
+            return fs::remove_file(&self.path);
+        }
+
+        let _ = fs::remove_file(self.rotated_path(self.max_files));
+        for index in (1..self.max_files).rev() {
+            let from = self.rotated_path(index);
+            if from.exists() {
+                fs::rename(&from, self.rotated_path(index + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{DamageProperty, DamageSource};
+
+    fn record(damage: u64) -> DamageRecord {
+        DamageRecord::new(1, 2, 1001, "🔥火".to_string(), damage, damage, false, false, false, false, DamageSource::default(), DamageProperty::default())
+    }
+
+    #[test]
+    fn test_writes_json_lines_and_rotates_by_size() {
+        let dir = std::env::temp_dir().join(format!("meter-core-combatlog-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("combat.jsonl");
+        let line_len = serde_json::to_vec(&record(100)).unwrap().len() as u64 + 1;
+
+        // Room for two lines per file, keep one rotated file
+        let writer = CombatLogWriter::new(&path, line_len * 2, 1);
+        for damage in [100, 200, 300, 400, 500] {
+            writer.write(&record(damage)).unwrap();
+        }
+        writer.flush().unwrap();
+
+        let current = fs::read_to_string(&path).unwrap();
+        let rotated = fs::read_to_string(dir.join("combat.jsonl.1")).unwrap();
+        let damages = |content: &str| {
+            content
+                .lines()
+                .map(|line| serde_json::from_str::<DamageRecord>(line).unwrap().damage)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(damages(&current), vec![500]);
+        assert_eq!(damages(&rotated), vec![300, 400]);
+        assert!(!dir.join("combat.jsonl.2").exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
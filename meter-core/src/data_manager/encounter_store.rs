@@ -0,0 +1,262 @@
+//! Optional SQLite store for encounters, queryable across sessions
+
+use super::DataManager;
+use parking_lot::Mutex;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS encounters (
+    timestamp     INTEGER PRIMARY KEY,
+    label         TEXT,
+    duration_secs INTEGER NOT NULL,
+    total_damage  INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS encounter_users (
+    timestamp     INTEGER NOT NULL REFERENCES encounters(timestamp) ON DELETE CASCADE,
+    uid           INTEGER NOT NULL,
+    name          TEXT NOT NULL,
+    profession    TEXT NOT NULL,
+    total_damage  INTEGER NOT NULL,
+    dps           REAL NOT NULL,
+    total_healing INTEGER NOT NULL,
+    hps           REAL NOT NULL,
+    deaths        INTEGER NOT NULL,
+    PRIMARY KEY (timestamp, uid)
+);
+CREATE TABLE IF NOT EXISTS encounter_skills (
+    timestamp    INTEGER NOT NULL REFERENCES encounters(timestamp) ON DELETE CASCADE,
+    uid          INTEGER NOT NULL,
+    skill_id     INTEGER NOT NULL,
+    skill_type   TEXT NOT NULL,
+    element      TEXT NOT NULL,
+    total_damage INTEGER NOT NULL,
+    hit_count    INTEGER NOT NULL,
+    crit_count   INTEGER NOT NULL,
+    lucky_count  INTEGER NOT NULL,
+    PRIMARY KEY (timestamp, uid, skill_id, skill_type)
+);
+CREATE INDEX IF NOT EXISTS encounter_users_profession ON encounter_users(profession);
+";
+
+// Rows returned when the query doesn't set a limit
+const DEFAULT_QUERY_LIMIT: u32 = 100;
+
+/// Filters for `EncounterStore::query`; all fields are optional
+#[derive(Debug, Default, Deserialize)]
+pub struct EncounterQuery {
+    pub profession: Option<String>,
+    pub min_dps: Option<f64>,
+    pub uid: Option<u32>,
+    pub limit: Option<u32>,
+}
+
+/// One player's totals in one saved encounter
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EncounterUserRow {
+    pub timestamp: i64,
+    pub label: Option<String>,
+    pub uid: u32,
+    pub name: String,
+    pub profession: String,
+    pub total_damage: u64,
+    pub dps: f64,
+    pub total_healing: u64,
+    pub hps: f64,
+    pub deaths: u32,
+}
+
+#[derive(Debug)]
+pub struct EncounterStore {
+    conn: Mutex<Connection>,
+}
+
+impl EncounterStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> rusqlite::Result<Self> {
+        Self::init(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> rusqlite::Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> rusqlite::Result<Self> {
+        conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    /// Store the current per-user totals and per-skill breakdown under `timestamp`,
+    /// replacing any encounter previously saved with the same timestamp
+    pub fn record_encounter(
+        &self,
+        timestamp: i64,
+        label: Option<&str>,
+        data_manager: &DataManager,
+    ) -> rusqlite::Result<()> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM encounters WHERE timestamp = ?1", params![timestamp])?;
+        tx.execute(
+            "INSERT INTO encounters (timestamp, label, duration_secs, total_damage) VALUES (?1, ?2, 0, 0)",
+            params![timestamp, label],
+        )?;
+
+        let mut total_damage: u64 = 0;
+        let mut fight_range = None;
+        {
+            let mut insert_user = tx.prepare(
+                "INSERT INTO encounter_users
+                 (timestamp, uid, name, profession, total_damage, dps, total_healing, hps, deaths)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            )?;
+            let mut insert_skill = tx.prepare(
+                "INSERT INTO encounter_skills
+                 (timestamp, uid, skill_id, skill_type, element, total_damage, hit_count, crit_count, lucky_count)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            )?;
+
+            for entry in data_manager.users.iter() {
+                let user = entry.value().read();
+                if user.damage_stats.total_damage == 0 && user.healing_stats.total_healing == 0 {
+                    continue;
+                }
+
+                total_damage = total_damage.saturating_add(user.damage_stats.total_damage);
+                if let Some((start, end)) = user.damage_stats.time_range {
+                    fight_range = Some(match fight_range {
+                        Some((first, last)) => (std::cmp::min(first, start), std::cmp::max(last, end)),
+                        None => (start, end),
+                    });
+                }
+
+                insert_user.execute(params![
+                    timestamp,
+                    user.uid,
+                    user.name,
+                    user.profession,
+                    user.damage_stats.total_damage as i64,
+                    user.damage_stats.dps,
+                    user.healing_stats.total_healing as i64,
+                    user.healing_stats.hps,
+                    user.dead_count,
+                ])?;
+
+                for skill in user.damage_skills.values().chain(user.healing_skills.values()) {
+                    insert_skill.execute(params![
+                        timestamp,
+                        user.uid,
+                        skill.skill_id,
+                        skill.skill_type,
+                        skill.element,
+                        skill.total_damage as i64,
+                        skill.total_count,
+                        skill.crit_count,
+                        skill.lucky_count,
+                    ])?;
+                }
+            }
+        }
+
+        let duration_secs = fight_range.map_or(0, |(start, end): (chrono::DateTime<chrono::Utc>, _)| {
+            (end - start).num_seconds()
+        });
+        tx.execute(
+            "UPDATE encounters SET duration_secs = ?2, total_damage = ?3 WHERE timestamp = ?1",
+            params![timestamp, duration_secs, total_damage as i64],
+        )?;
+        tx.commit()
+    }
+
+    /// Per-player rows across all stored encounters, newest first
+    pub fn query(&self, query: &EncounterQuery) -> rusqlite::Result<Vec<EncounterUserRow>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(
+            "SELECT u.timestamp, e.label, u.uid, u.name, u.profession, u.total_damage, u.dps,
+                    u.total_healing, u.hps, u.deaths
+             FROM encounter_users u JOIN encounters e ON e.timestamp = u.timestamp
+             WHERE (?1 IS NULL OR u.profession = ?1)
+               AND (?2 IS NULL OR u.dps >= ?2)
+               AND (?3 IS NULL OR u.uid = ?3)
+             ORDER BY u.timestamp DESC, u.dps DESC
+             LIMIT ?4",
+        )?;
+
+        let rows = stmt.query_map(
+            params![
+                query.profession,
+                query.min_dps,
+                query.uid,
+                query.limit.unwrap_or(DEFAULT_QUERY_LIMIT)
+            ],
+            |row| {
+                Ok(EncounterUserRow {
+                    timestamp: row.get(0)?,
+                    label: row.get(1)?,
+                    uid: row.get(2)?,
+                    name: row.get(3)?,
+                    profession: row.get(4)?,
+                    total_damage: row.get::<_, i64>(5)? as u64,
+                    dps: row.get(6)?,
+                    total_healing: row.get::<_, i64>(7)? as u64,
+                    hps: row.get(8)?,
+                    deaths: row.get(9)?,
+                })
+            },
+        )?;
+        rows.collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_record_and_query_encounters() {
+        let data_manager = DataManager::new();
+        let now = Utc::now();
+        data_manager.set_user_profession(1, "Frost Mage".to_string());
+        data_manager.set_user_profession(2, "Marksman".to_string());
+        data_manager.get_or_create_user(1).write().add_damage(1001, "❄️冰".to_string(), 5000, false, false, false, 5000, now);
+        data_manager.get_or_create_user(2).write().add_damage(2001, "⚔️物".to_string(), 100, false, false, false, 100, now);
+        for uid in [1, 2] {
+            data_manager.get_or_create_user(uid).write().damage_stats.dps = if uid == 1 { 500.0 } else { 10.0 };
+        }
+
+        let store = EncounterStore::open_in_memory().unwrap();
+        store.record_encounter(1_700_000_000, Some("boss"), &data_manager).unwrap();
+        // Saving the same timestamp again replaces the encounter instead of duplicating rows
+        store.record_encounter(1_700_000_000, Some("boss"), &data_manager).unwrap();
+
+        let all = store.query(&EncounterQuery::default()).unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].uid, 1);
+        assert_eq!(all[0].label.as_deref(), Some("boss"));
+
+        let mages = store
+            .query(&EncounterQuery { profession: Some("Frost Mage".to_string()), ..Default::default() })
+            .unwrap();
+        assert_eq!(mages.len(), 1);
+        assert_eq!(mages[0].total_damage, 5000);
+
+        let fast = store.query(&EncounterQuery { min_dps: Some(100.0), ..Default::default() }).unwrap();
+        assert_eq!(fast.iter().map(|row| row.uid).collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn test_record_saturated_totals() {
+        let data_manager = DataManager::new();
+        for uid in [1, 2] {
+            data_manager.get_or_create_user(uid).write().damage_stats.total_damage = u64::MAX;
+        }
+
+        let store = EncounterStore::open_in_memory().unwrap();
+        store.record_encounter(1_700_000_000, None, &data_manager).unwrap();
+        let rows = store.query(&EncounterQuery::default()).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert!(rows.iter().all(|row| row.total_damage == u64::MAX));
+    }
+}
@@ -0,0 +1,221 @@
+//! Encounter summaries posted to a Discord webhook
+
+use crate::config::DiscordConfig;
+use crate::data_manager::DataManager;
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+// Players listed in the summary
+const TOP_PLAYERS: usize = 5;
+// Discord embed accent color (blue)
+const EMBED_COLOR: u32 = 0x3498db;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerSummary {
+    pub name: String,
+    pub profession: String,
+    pub dps: f64,
+    pub total_damage: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EncounterSummary {
+    /// Highest DPS first, at most `TOP_PLAYERS`
+    pub top_players: Vec<PlayerSummary>,
+    pub total_damage: u64,
+    pub duration_secs: i64,
+    pub deaths: u32,
+}
+
+impl EncounterSummary {
+    pub fn from_data_manager(data_manager: &DataManager) -> Self {
+        let mut players = Vec::new();
+        let mut total_damage: u64 = 0;
+        let mut deaths: u32 = 0;
+        let mut fight_range: Option<(chrono::DateTime<chrono::Utc>, chrono::DateTime<chrono::Utc>)> = None;
+
+        for entry in data_manager.users.iter() {
+            let user = entry.value().read();
+            // Per-user totals may already be saturated, so the sums must not overflow either
+            deaths = deaths.saturating_add(user.dead_count);
+            if user.damage_stats.total_damage == 0 {
+                continue;
+            }
+
+            total_damage = total_damage.saturating_add(user.damage_stats.total_damage);
+            if let Some((start, end)) = user.damage_stats.time_range {
+                fight_range = Some(match fight_range {
+                    Some((first, last)) => (first.min(start), last.max(end)),
+                    None => (start, end),
+                });
+            }
+
+            players.push(PlayerSummary {
+                name: if user.name.is_empty() { user.uid.to_string() } else { user.name.clone() },
+                profession: user.profession.clone(),
+                dps: user.damage_stats.dps,
+                total_damage: user.damage_stats.total_damage,
+            });
+        }
+
+        players.sort_by(|a, b| b.dps.total_cmp(&a.dps));
+        players.truncate(TOP_PLAYERS);
+
+        Self {
+            top_players: players,
+            total_damage,
+            duration_secs: fight_range.map_or(0, |(start, end)| (end - start).num_seconds()),
+            deaths,
+        }
+    }
+
+    /// Webhook payload with the summary as a single embed
+    pub fn to_webhook_payload(&self, title: &str) -> Value {
+        let ranking = self
+            .top_players
+            .iter()
+            .enumerate()
+            .map(|(i, player)| {
+                let profession = if player.profession.is_empty() {
+                    String::new()
+                } else {
+                    format!(" ({})", player.profession)
+                };
+                format!(
+                    "**{}.** {}{} — {} DPS, {} dmg",
+                    i + 1,
+                    player.name,
+                    profession,
+                    format_number(player.dps as u64),
+                    format_number(player.total_damage)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        json!({
+            "embeds": [{
+                "title": title,
+                "color": EMBED_COLOR,
+                "fields": [
+                    { "name": "Top DPS", "value": if ranking.is_empty() { "-".to_string() } else { ranking }, "inline": false },
+                    { "name": "Total damage", "value": format_number(self.total_damage), "inline": true },
+                    { "name": "Duration", "value": format!("{}:{:02}", self.duration_secs / 60, self.duration_secs % 60), "inline": true },
+                    { "name": "Deaths", "value": self.deaths.to_string(), "inline": true }
+                ]
+            }]
+        })
+    }
+}
+
+/// Posts encounter summaries, at most once per `min_interval_secs`
+#[derive(Debug)]
+pub struct DiscordNotifier {
+    webhook_url: String,
+    min_interval: Duration,
+    client: reqwest::Client,
+    last_sent: Mutex<Option<Instant>>,
+}
+
+impl DiscordNotifier {
+    /// Returns `None` when notifications are disabled or no webhook is configured
+    pub fn from_config(config: &DiscordConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+        let webhook_url = config.webhook_url.clone().filter(|url| !url.is_empty())?;
+        let client = reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build().ok()?;
+
+        Some(Self {
+            webhook_url,
+            min_interval: Duration::from_secs(config.min_interval_secs),
+            client,
+            last_sent: Mutex::new(None),
+        })
+    }
+
+    /// Post a summary of the current stats in the background. Errors are only logged.
+    pub fn notify_encounter(&self, data_manager: &DataManager, title: &str) {
+        let summary = EncounterSummary::from_data_manager(data_manager);
+        if summary.total_damage == 0 {
+            return;
+        }
+
+        {
+            let mut last_sent = self.last_sent.lock();
+            if last_sent.is_some_and(|sent| sent.elapsed() < self.min_interval) {
+                log::debug!("Skipping Discord summary, last one was sent less than {:?} ago", self.min_interval);
+                return;
+            }
+            *last_sent = Some(Instant::now());
+        }
+
+        let request = self.client.post(&self.webhook_url).json(&summary.to_webhook_payload(title));
+        tokio::spawn(async move {
+            match request.send().await.and_then(|response| response.error_for_status()) {
+                Ok(_) => log::info!("Posted encounter summary to Discord"),
+                Err(e) => log::warn!("Failed to post encounter summary to Discord: {}", e),
+            }
+        });
+    }
+}
+
+/// 1234567 -> "1,234,567"
+fn format_number(value: u64) -> String {
+    let digits = value.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_webhook_payload_lists_top_players() {
+        let summary = EncounterSummary {
+            top_players: vec![
+                PlayerSummary { name: "Alice".to_string(), profession: "Stormblade".to_string(), dps: 12345.6, total_damage: 1_234_567 },
+                PlayerSummary { name: "Bob".to_string(), profession: String::new(), dps: 999.0, total_damage: 99_900 },
+            ],
+            total_damage: 1_334_467,
+            duration_secs: 125,
+            deaths: 1,
+        };
+
+        let payload = summary.to_webhook_payload("Boss cleared");
+        let embed = &payload["embeds"][0];
+        assert_eq!(embed["title"], "Boss cleared");
+        assert_eq!(
+            embed["fields"][0]["value"],
+            "**1.** Alice (Stormblade) — 12,345 DPS, 1,234,567 dmg\n**2.** Bob — 999 DPS, 99,900 dmg"
+        );
+        assert_eq!(embed["fields"][1]["value"], "1,334,467");
+        assert_eq!(embed["fields"][2]["value"], "2:05");
+        assert_eq!(embed["fields"][3]["value"], "1");
+    }
+
+    #[test]
+    fn test_summary_totals_saturate() {
+        let data_manager = DataManager::new();
+        for uid in [1, 2] {
+            let user = data_manager.get_or_create_user(uid);
+            let mut user = user.write();
+            user.damage_stats.total_damage = u64::MAX;
+            user.dead_count = u32::MAX;
+        }
+
+        let summary = EncounterSummary::from_data_manager(&data_manager);
+        assert_eq!(summary.total_damage, u64::MAX);
+        assert_eq!(summary.deaths, u32::MAX);
+        assert_eq!(summary.top_players.len(), 2);
+    }
+}
@@ -2,14 +2,18 @@ pub mod models;
 pub mod data_manager;
 pub mod packet_parser;
 pub mod packet_capture;
+pub mod capture_backend;
+pub mod pcap;
+pub mod network;
 pub mod web_server;
 pub mod config;
+pub mod discord;
 
 use std::sync::Arc;
-use tokio::sync::{Mutex, RwLock};
+use tokio::sync::Mutex;
 use std::collections::HashMap;
 use chrono::Utc;
-use log::{info, warn, error};
+use log::{info, error};
 use tokio::task::JoinHandle;
 
 // Error types
@@ -81,7 +85,41 @@ pub type Result<T> = std::result::Result<T, MeterError>;
 
 // Utility functions
 pub mod utils {
+    use std::io::Write;
     use std::path::Path;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    // Distinguishes temporary files of concurrent writes to the same target within this process
+    static TMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    /// Write a file so readers see either the old or the new content, never a partial one:
+    /// the data goes to a temporary file in the same directory which is then renamed over the target.
+    /// This blocks on disk I/O; async callers should run it through `spawn_blocking`.
+    pub fn write_atomic<P: AsRef<Path>>(path: P, content: impl AsRef<[u8]>) -> std::io::Result<()> {
+        let path = path.as_ref();
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "path has no file name"))?;
+        let mut tmp_name = std::ffi::OsString::from(".");
+        tmp_name.push(file_name);
+        tmp_name.push(format!(
+            ".{}.{}.tmp",
+            std::process::id(),
+            TMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let tmp_path = path.with_file_name(tmp_name);
+
+        let result = (|| {
+            let mut file = std::fs::File::create(&tmp_path)?;
+            file.write_all(content.as_ref())?;
+            file.sync_all()?;
+            std::fs::rename(&tmp_path, path)
+        })();
+        if result.is_err() {
+            let _ = std::fs::remove_file(&tmp_path);
+        }
+        result
+    }
 
     pub fn is_windivert_installed() -> bool {
         // Check for WinDivert DLLs in the current directory
@@ -136,15 +174,25 @@ pub mod utils {
 
 use data_manager::DataManager;
 use packet_capture::PacketCapture;
-use web_server::WebServer;
-use config::{AppConfig, AppArgs};
+use web_server::{HistoryManager, WebServer};
+use config::{AppConfig, AppArgs, ConfigMode, ConfigWatcher};
+
+// How often the JSON-lines combat log is flushed to disk
+const COMBAT_LOG_FLUSH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+// How often the config file is checked for edits
+const CONFIG_RELOAD_INTERVAL: std::time::Duration = std::time::Duration::from_secs(3);
 
 pub struct MeterCore {
     data_manager: Arc<DataManager>,
-    packet_capture: Option<PacketCapture>,
+    packet_capture: Option<Arc<Mutex<PacketCapture>>>,
     web_server: Option<WebServer>,
     tasks: Vec<JoinHandle<()>>,
+    // Kept apart from `tasks` so the standalone binary can wait for it to finish
+    server_task: Option<JoinHandle<()>>,
     config: AppConfig,
+    config_mode: ConfigMode,
+    args: AppArgs,
 }
 
 impl MeterCore {
@@ -163,6 +211,8 @@ impl MeterCore {
         // Parse command line arguments
         let args = AppArgs::parse();
 
+        let config_mode = if use_tauri_config { ConfigMode::Tauri } else { ConfigMode::Standalone };
+
         // Load configuration based on mode (needed for logging setup)
         let config = if use_tauri_config {
             AppConfig::load_for_tauri().unwrap_or_else(|e| {
@@ -179,8 +229,12 @@ impl MeterCore {
         // Initialize logging (only if not already initialized)
         let log_level = args.log_level.as_deref()
             .unwrap_or(&config.logging.level);
-        if let Err(_) = env_logger::try_init_from_env(env_logger::Env::default().default_filter_or(log_level)) {
-            // Logger already initialized, skip
+        if std::env::var_os("RUST_LOG").is_some() {
+            let _ = env_logger::try_init_from_env(env_logger::Env::default());
+        } else if env_logger::Builder::new().filter_level(log::LevelFilter::Trace).try_init().is_ok() {
+            // The logger lets everything through; the global max level does the filtering
+            // so that config hot reload can change it later
+            log::set_max_level(log_level.parse().unwrap_or(log::LevelFilter::Info));
         }
 
         info!("Starting Meter Core - Star Resonance Damage Counter");
@@ -197,7 +251,11 @@ impl MeterCore {
         info!("Configuration loaded successfully");
 
         // Initialize data manager
-        let data_manager = Arc::new(DataManager::new());
+        let data_manager = Arc::new(
+            DataManager::new_with_config(&config.data_manager)
+                .with_discord(&config.discord)
+                .with_combat_log_file(config.data_manager.combat_log_file.as_deref(), &config.logging),
+        );
         data_manager.initialize().await?;
 
         info!("Data manager initialized");
@@ -207,17 +265,25 @@ impl MeterCore {
             packet_capture: None,
             web_server: None,
             tasks: Vec::new(),
+            server_task: None,
             config,
+            config_mode,
+            args,
         })
     }
 
     pub async fn start(&mut self) -> std::result::Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        // Initialize packet capture
-        let packet_capture = PacketCapture::new(self.data_manager.clone());
-        self.packet_capture = Some(packet_capture);
+        // Initialize packet capture (replay from a pcap file when requested)
+        let packet_capture = match &self.args.replay {
+            Some(path) => PacketCapture::from_pcap(self.data_manager.clone(), path, self.args.replay_realtime),
+            None => PacketCapture::new(self.data_manager.clone()),
+        }
+        .with_config(&self.config.packet_capture);
+        self.packet_capture = Some(Arc::new(Mutex::new(packet_capture)));
 
         // Initialize web server
-        let web_server = WebServer::new(self.data_manager.clone());
+        let web_server = WebServer::new(self.data_manager.clone())
+            .with_config((&self.config.web_server).into());
         self.web_server = Some(web_server);
 
         // Start background tasks
@@ -229,33 +295,85 @@ impl MeterCore {
                 if !data_manager_clone.is_paused() {
                     data_manager_clone.update_dps();
                     data_manager_clone.update_hps();
+                    data_manager_clone.record_timeline();
+                    data_manager_clone.broadcast_users_update();
                 }
                 data_manager_clone.check_timeout_clear();
+                if let Some((segment, _save_guard)) = data_manager_clone.finish_idle_encounter() {
+                    let label = format!("Encounter {}", segment.start.with_timezone(&chrono::Local).format("%H:%M:%S"));
+                    if let Err(e) = HistoryManager::new(data_manager_clone.clone())
+                        .save_snapshot_with_label(segment.snapshot_timestamp, Some(label))
+                        .await
+                    {
+                        log::error!("Failed to save encounter snapshot: {}", e);
+                    }
+                }
             }
         });
         self.tasks.push(update_task);
 
         // Start auto-save task
+        let auto_save_interval = self.config.data_manager.auto_save_interval.max(1);
+        info!("Auto-saving user data every {}s", auto_save_interval);
         let data_manager_clone = self.data_manager.clone();
         let save_task = tokio::spawn(async move {
-            let mut interval = tokio::time::interval(std::time::Duration::from_secs(300)); // 5 minutes
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(auto_save_interval));
             loop {
                 interval.tick().await;
                 if let Err(e) = data_manager_clone.save_user_cache().await {
                     error!("Failed to auto-save user cache: {}", e);
                 }
+                if let Err(e) = data_manager_clone.save_full_session().await {
+                    error!("Failed to auto-save full session: {}", e);
+                }
             }
         });
         self.tasks.push(save_task);
 
-        // Start packet capture
-        if let Some(mut packet_capture) = self.packet_capture.take() {
-            let capture_task = tokio::spawn(async move {
-                if let Err(e) = packet_capture.start_capture().await {
-                    error!("Packet capture failed: {}", e);
+        // Flush the combat log file regularly so a crash loses at most a second of records
+        if self.data_manager.combat_log_writer.is_some() {
+            let data_manager_clone = self.data_manager.clone();
+            let flush_task = tokio::spawn(async move {
+                let mut interval = tokio::time::interval(COMBAT_LOG_FLUSH_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    data_manager_clone.flush_combat_log_file();
                 }
             });
-            self.tasks.push(capture_task);
+            self.tasks.push(flush_task);
+        }
+
+        // Start config hot reload task
+        match AppConfig::find_config_file(&self.config_mode) {
+            Some(path) => {
+                let reload_task = spawn_config_reload_task(
+                    path,
+                    self.config.clone(),
+                    self.args.log_level.is_some(),
+                    self.data_manager.clone(),
+                );
+                self.tasks.push(reload_task);
+            }
+            None => info!("No configuration file to watch, hot reload disabled"),
+        }
+
+        // Start packet capture (keep the handle so stop() can shut it down)
+        if let Some(packet_capture) = &self.packet_capture {
+            if let Err(e) = packet_capture.lock().await.start_capture().await {
+                error!("Packet capture failed: {}", e);
+            }
+            let restart_task = packet_capture::spawn_restart_handler(packet_capture.clone(), self.data_manager.clone());
+            self.tasks.push(restart_task);
+
+            // Stop capturing once /api/shutdown has saved everything; the web server stops itself
+            let packet_capture = packet_capture.clone();
+            let data_manager_clone = self.data_manager.clone();
+            let shutdown_task = tokio::spawn(async move {
+                data_manager_clone.shutdown_requested().await;
+                info!("Shutdown requested via API, stopping packet capture");
+                packet_capture.lock().await.stop();
+            });
+            self.tasks.push(shutdown_task);
         }
 
         // Start web server
@@ -265,37 +383,54 @@ impl MeterCore {
                     error!("Web server failed: {}", e);
                 }
             });
-            self.tasks.push(server_task);
+            self.server_task = Some(server_task);
         }
 
         info!("Meter Core started successfully");
         Ok(())
     }
 
+    /// Wait for Ctrl+C, or for the web server to exit after /api/shutdown
+    pub async fn wait_for_shutdown(&mut self) {
+        let server_finished = async {
+            match self.server_task.as_mut() {
+                Some(task) => {
+                    let _ = task.await;
+                }
+                None => std::future::pending().await,
+            }
+        };
+
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {
+                info!("Received shutdown signal");
+            }
+            // Ends only once the /api/shutdown response has been sent
+            _ = server_finished => {
+                info!("Web server task finished");
+            }
+        }
+    }
+
     pub async fn stop(&mut self) -> std::result::Result<(), Box<dyn std::error::Error + Send + Sync>> {
         info!("Stopping Meter Core...");
 
+        // Signal packet capture first so the capture loop closes its WinDivert handle
+        if let Some(packet_capture) = self.packet_capture.take() {
+            packet_capture.lock().await.stop();
+        }
+
         // Stop all tasks
         for task in &self.tasks {
             task.abort();
         }
         self.tasks.clear();
-
-        // Stop packet capture (this will handle WinDivert cleanup)
-        if let Some(ref mut packet_capture) = self.packet_capture {
-            // Note: PacketCapture should implement a stop method
-            // For now, we'll log the intent
-            warn!("Packet capture stop not implemented yet - WinDivert cleanup needed");
+        if let Some(server_task) = self.server_task.take() {
+            server_task.abort();
         }
 
         // Save final data
-        if let Err(e) = self.data_manager.save_user_cache().await {
-            error!("Failed to save user cache on shutdown: {}", e);
-        }
-
-        if let Err(e) = self.data_manager.save_settings().await {
-            error!("Failed to save settings on shutdown: {}", e);
-        }
+        self.data_manager.persist_all().await;
 
         info!("Meter Core stopped successfully");
         Ok(())
@@ -306,9 +441,133 @@ impl MeterCore {
     }
 
     pub fn is_running(&self) -> bool {
-        !self.tasks.is_empty()
+        !self.tasks.is_empty() || self.server_task.is_some()
     }
 }
 
+/// Poll the config file and apply the hot-reloadable settings whenever it changes
+fn spawn_config_reload_task(
+    path: std::path::PathBuf,
+    mut current: AppConfig,
+    log_level_from_args: bool,
+    data_manager: Arc<DataManager>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut watcher = ConfigWatcher::new(path.to_string_lossy().into_owned());
+        // The first check only records the current modification time
+        let _ = watcher.check_for_changes();
+        info!("Watching {:?} for configuration changes", path);
+
+        let mut interval = tokio::time::interval(CONFIG_RELOAD_INTERVAL);
+        loop {
+            interval.tick().await;
+            match watcher.check_for_changes() {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => {
+                    log::debug!("Failed to check {:?} for changes: {}", path, e);
+                    continue;
+                }
+            }
+
+            // A half-written or malformed file keeps the running configuration
+            let mut config = match AppConfig::load_from_file(&path) {
+                Ok(config) => config,
+                Err(e) => {
+                    log::warn!("Ignoring edited configuration {:?}: {}", path, e);
+                    continue;
+                }
+            };
+            if let Err(e) = config.load_from_env() {
+                log::warn!("Ignoring edited configuration {:?}: {}", path, e);
+                continue;
+            }
+            if let Err(errors) = config.validate() {
+                log::warn!("Ignoring edited configuration {:?}, validation failed: {:?}", path, errors);
+                continue;
+            }
+
+            apply_config_reload(&current, &config, log_level_from_args, &data_manager);
+            current = config;
+        }
+    })
+}
+
+fn apply_config_reload(current: &AppConfig, config: &AppConfig, log_level_from_args: bool, data_manager: &DataManager) {
+    if config.logging.level != current.logging.level {
+        if log_level_from_args || std::env::var_os("RUST_LOG").is_some() {
+            log::warn!("logging.level changed but the log level is set on the command line or RUST_LOG, ignoring");
+        } else if let Ok(level) = config.logging.level.parse() {
+            log::set_max_level(level);
+            info!("Log level changed to {}", config.logging.level);
+        }
+    }
+
+    // Only push the filter when it was edited so an interface picked through the API is kept
+    if config.packet_capture.filter != current.packet_capture.filter {
+        data_manager.set_capture_filter(config.packet_capture.filter.clone());
+        info!("Capture filter changed to: {}", config.packet_capture.filter);
+    }
+
+    packet_capture::apply_runtime_config(&config.packet_capture);
+    data_manager.apply_config(&config.data_manager);
+
+    for setting in current.restart_required_changes(config) {
+        log::warn!("{} changed in the configuration file, restart to apply it", setting);
+    }
+
+    info!("Configuration reloaded");
+}
+
 // Re-export for convenience
 pub use models::*;
+
+#[cfg(test)]
+mod tests {
+    use super::utils::write_atomic;
+
+    #[test]
+    fn test_write_atomic_keeps_previous_file_on_interrupted_write() {
+        let dir = std::env::temp_dir().join(format!("meter-core-atomic-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("users.json");
+        let tmp_files = || {
+            std::fs::read_dir(&dir)
+                .unwrap()
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_name().to_string_lossy().ends_with(".tmp"))
+                .count()
+        };
+
+        write_atomic(&path, r#"{"good":true}"#).unwrap();
+
+        // A crash mid-write leaves only a truncated temporary file behind
+        std::fs::write(dir.join(".users.json.1.0.tmp"), r#"{"goo"#).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), r#"{"good":true}"#);
+
+        // The next save uses its own temporary file and leaves no new one behind
+        write_atomic(&path, r#"{"good":false}"#).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), r#"{"good":false}"#);
+        assert_eq!(tmp_files(), 1);
+
+        // Concurrent writers to the same target never share a temporary file
+        std::thread::scope(|scope| {
+            for i in 0..8 {
+                let path = &path;
+                scope.spawn(move || write_atomic(path, format!(r#"{{"writer":{}}}"#, i)).unwrap());
+            }
+        });
+        assert!(serde_json::from_str::<serde_json::Value>(&std::fs::read_to_string(&path).unwrap()).is_ok());
+        assert_eq!(tmp_files(), 1);
+
+        // A write that fails at the rename removes its temporary file
+        let blocked = dir.join("blocked");
+        std::fs::create_dir_all(blocked.join("child")).unwrap();
+        assert!(write_atomic(&blocked, r#"{"partial"#).is_err());
+        assert!(blocked.join("child").exists());
+        assert_eq!(tmp_files(), 1);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -15,6 +15,8 @@ pub struct DamageRecord {
     pub is_miss: bool,
     pub damage_source: DamageSource,
     pub damage_property: DamageProperty,
+    #[serde(default)]
+    pub is_heal: bool,
     pub timestamp: DateTime<Utc>,
 }
 
@@ -82,6 +84,7 @@ impl DamageRecord {
             is_miss,
             damage_source,
             damage_property,
+            is_heal: false,
             timestamp: Utc::now(),
         }
     }
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use chrono::{DateTime, Utc};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -7,6 +8,10 @@ pub struct Enemy {
     pub name: String,
     pub hp: u32,
     pub max_hp: u32,
+    #[serde(default)]
+    pub total_damage_received: u64,
+    #[serde(default)]
+    pub damage_by_attacker: HashMap<u32, u64>,
     pub last_update: DateTime<Utc>,
 }
 
@@ -17,6 +22,8 @@ impl Enemy {
             name: format!("Enemy_{}", id),
             hp: 0,
             max_hp: 0,
+            total_damage_received: 0,
+            damage_by_attacker: HashMap::new(),
             last_update: Utc::now(),
         }
     }
@@ -36,6 +43,20 @@ impl Enemy {
         self.last_update = Utc::now();
     }
 
+    pub fn add_damage_received(&mut self, attacker_uid: u32, damage: u64) {
+        // 长时间的木桩/首领战也不会溢出
+        self.total_damage_received = self.total_damage_received.saturating_add(damage);
+        let by_attacker = self.damage_by_attacker.entry(attacker_uid).or_insert(0);
+        *by_attacker = by_attacker.saturating_add(damage);
+        self.last_update = Utc::now();
+    }
+
+    pub fn remove_attacker(&mut self, attacker_uid: u32) {
+        if let Some(damage) = self.damage_by_attacker.remove(&attacker_uid) {
+            self.total_damage_received = self.total_damage_received.saturating_sub(damage);
+        }
+    }
+
     pub fn is_dead(&self) -> bool {
         self.hp == 0
     }
@@ -48,7 +69,33 @@ impl Default for Enemy {
             name: String::new(),
             hp: 0,
             max_hp: 0,
+            total_damage_received: 0,
+            damage_by_attacker: HashMap::new(),
             last_update: Utc::now(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_without_damage_fields() {
+        let json = r#"{"id":75,"name":"木桩","hp":100,"max_hp":1000,"last_update":"2024-01-01T00:00:00Z"}"#;
+        let enemy: Enemy = serde_json::from_str(json).unwrap();
+        assert_eq!(enemy.total_damage_received, 0);
+        assert!(enemy.damage_by_attacker.is_empty());
+    }
+
+    #[test]
+    fn test_damage_received_saturates() {
+        let mut enemy = Enemy::new(75);
+        enemy.add_damage_received(1, u64::MAX - 10);
+        enemy.add_damage_received(1, 1000);
+        enemy.add_damage_received(2, 5);
+        assert_eq!(enemy.total_damage_received, u64::MAX);
+        assert_eq!(enemy.damage_by_attacker[&1], u64::MAX);
+        assert_eq!(enemy.damage_by_attacker[&2], 5);
+    }
+}
@@ -2,8 +2,10 @@ pub mod user;
 pub mod enemy;
 pub mod damage;
 pub mod skill;
+pub mod names;
 
 pub use user::*;
 pub use enemy::*;
 pub use damage::*;
 pub use skill::*;
+pub use names::*;
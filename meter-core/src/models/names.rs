@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// 职业、元素名称使用的语言
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    #[default]
+    Zh,
+    En,
+}
+
+/// 职业、元素名称表；内置中英文，可从JSON文件覆盖或补充翻译
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NameTable {
+    pub professions: HashMap<u32, String>,
+    pub elements: HashMap<u32, String>,
+}
+
+const PROFESSIONS: [(u32, &str, &str); 11] = [
+    (1, "雷影剑士", "Stormblade"),
+    (2, "冰魔导师", "Frost Mage"),
+    (3, "涤罪恶火·战斧", "Purging Flame (Axe)"),
+    (4, "青岚骑士", "Wind Knight"),
+    (5, "森语者", "Verdant Oracle"),
+    (8, "雷霆一闪·手炮", "Thunder Flash (Hand Cannon)"),
+    (9, "巨刃守护者", "Heavy Guardian"),
+    (10, "暗灵祈舞·仪刀/仪仗", "Shadow Dancer (Ritual Blade/Staff)"),
+    (11, "神射手", "Marksman"),
+    (12, "神盾骑士", "Shield Knight"),
+    (13, "灵魂乐手", "Beat Performer"),
+];
+
+const ELEMENTS: [(u32, &str, &str); 9] = [
+    (0, "⚔️物", "⚔️ Physical"),
+    (1, "🔥火", "🔥 Fire"),
+    (2, "❄️冰", "❄️ Ice"),
+    (3, "⚡雷", "⚡ Thunder"),
+    (4, "🍀森", "🍀 Forest"),
+    (5, "💨风", "💨 Wind"),
+    (6, "⛰️岩", "⛰️ Rock"),
+    (7, "🌟光", "🌟 Light"),
+    (8, "🌑暗", "🌑 Dark"),
+];
+
+// 未知元素按物理显示
+const FALLBACK_ELEMENT: u32 = 0;
+
+impl NameTable {
+    pub fn builtin(language: Language) -> Self {
+        let pick = |zh: &str, en: &str| match language {
+            Language::Zh => zh.to_string(),
+            Language::En => en.to_string(),
+        };
+
+        Self {
+            professions: PROFESSIONS.iter().map(|&(id, zh, en)| (id, pick(zh, en))).collect(),
+            elements: ELEMENTS.iter().map(|&(id, zh, en)| (id, pick(zh, en))).collect(),
+        }
+    }
+
+    pub fn profession_name(&self, profession_id: u32) -> Option<String> {
+        self.professions.get(&profession_id).cloned()
+    }
+
+    pub fn element_name(&self, property: u32) -> String {
+        self.elements
+            .get(&property)
+            .or_else(|| self.elements.get(&FALLBACK_ELEMENT))
+            .cloned()
+            .unwrap_or_else(|| property.to_string())
+    }
+
+    /// 格式与技能名称表相同：`{"professions": {"1": "..."}, "elements": {"0": "..."}}`，只覆盖文件中给出的条目
+    pub fn load_from_json(&mut self, json_data: &str) -> Result<(), serde_json::Error> {
+        let data: serde_json::Value = serde_json::from_str(json_data)?;
+        for (section, table) in [("professions", &mut self.professions), ("elements", &mut self.elements)] {
+            if let Some(names) = data.get(section).and_then(|v| v.as_object()) {
+                for (key, value) in names {
+                    if let (Ok(id), Some(name)) = (key.parse::<u32>(), value.as_str()) {
+                        table.insert(id, name.to_string());
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for NameTable {
+    fn default() -> Self {
+        Self::builtin(Language::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_names_by_language() {
+        let zh = NameTable::builtin(Language::Zh);
+        let en = NameTable::builtin(Language::En);
+
+        assert_eq!(zh.profession_name(2).as_deref(), Some("冰魔导师"));
+        assert_eq!(en.profession_name(2).as_deref(), Some("Frost Mage"));
+        assert_eq!(en.profession_name(6), None);
+        assert_eq!(en.element_name(1), "🔥 Fire");
+        assert_eq!(en.element_name(99), "⚔️ Physical");
+    }
+
+    #[test]
+    fn test_load_from_json_overrides_entries() {
+        let mut table = NameTable::builtin(Language::En);
+        table
+            .load_from_json(r#"{"professions": {"1": "Blitzklinge", "x": "ignored"}, "elements": {"1": "🔥 Feuer"}}"#)
+            .unwrap();
+
+        assert_eq!(table.profession_name(1).as_deref(), Some("Blitzklinge"));
+        assert_eq!(table.profession_name(2).as_deref(), Some("Frost Mage"));
+        assert_eq!(table.element_name(1), "🔥 Feuer");
+    }
+}
@@ -1,5 +1,8 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use super::names::{Language, NameTable};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SkillConfig {
@@ -52,6 +55,34 @@ impl SkillConfig {
         }
         Ok(())
     }
+
+    /// 读取 `id,name` 格式的CSV技能表，返回加载的条目数；格式错误的行会被跳过并记录警告
+    pub fn load_from_csv(&mut self, csv_data: &str) -> usize {
+        let mut loaded = 0;
+        for (line_no, line) in csv_data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            // 技能名中可能含有逗号，只按第一个逗号拆分
+            let Some((id, name)) = line.split_once(',') else {
+                log::warn!("Skipping malformed skill CSV line {}: {}", line_no + 1, line);
+                continue;
+            };
+            let name = name.trim().trim_matches('"');
+            match id.trim().trim_matches('"').parse::<u32>() {
+                Ok(skill_id) if !name.is_empty() => {
+                    self.add_skill(skill_id, name.to_string());
+                    loaded += 1;
+                }
+                // 表头行
+                Err(_) if line_no == 0 => {}
+                _ => log::warn!("Skipping malformed skill CSV line {}: {}", line_no + 1, line),
+            }
+        }
+        loaded
+    }
 }
 
 impl Default for SkillConfig {
@@ -72,42 +103,83 @@ impl SkillInfo {
     }
 }
 
-// 职业相关的技能映射
+// 职业相关的技能映射：流派名称及能识别该流派的技能id
+const SUB_PROFESSION_SKILLS: [(&str, &[u32]); 16] = [
+    ("射线", &[1241]),
+    ("协奏", &[2307, 2361, 55302]),
+    ("愈合", &[20301]),
+    ("惩戒", &[1518, 1541, 21402]),
+    ("狂音", &[2306]),
+    ("冰矛", &[120901, 120902]),
+    ("居合", &[1714, 1734]),
+    ("月刃", &[44701, 179906]),
+    ("鹰弓", &[220112, 2203622]),
+    ("狼弓", &[2292, 1700820, 1700825, 1700827]),
+    ("空枪", &[1419]),
+    ("重装", &[1405, 1418]),
+    ("防盾", &[2405]),
+    ("光盾", &[2406]),
+    ("岩盾", &[199902]),
+    ("格挡", &[1930, 1931, 1934, 1935]),
+];
+
 pub fn get_sub_profession_by_skill_id(skill_id: u32) -> Option<String> {
-    match skill_id {
-        1241 => Some("射线".to_string()),
-        2307 | 2361 | 55302 => Some("协奏".to_string()),
-        20301 => Some("愈合".to_string()),
-        1518 | 1541 | 21402 => Some("惩戒".to_string()),
-        2306 => Some("狂音".to_string()),
-        120901 | 120902 => Some("冰矛".to_string()),
-        1714 | 1734 => Some("居合".to_string()),
-        44701 | 179906 => Some("月刃".to_string()),
-        220112 | 2203622 => Some("鹰弓".to_string()),
-        2292 | 1700820 | 1700825 | 1700827 => Some("狼弓".to_string()),
-        1419 => Some("空枪".to_string()),
-        1405 | 1418 => Some("重装".to_string()),
-        2405 => Some("防盾".to_string()),
-        2406 => Some("光盾".to_string()),
-        199902 => Some("岩盾".to_string()),
-        1930 | 1931 | 1934 | 1935 => Some("格挡".to_string()),
-        _ => None,
-    }
+    SUB_PROFESSION_SKILLS
+        .iter()
+        .find(|(_, skill_ids)| skill_ids.contains(&skill_id))
+        .map(|(name, _)| name.to_string())
 }
 
-pub fn get_profession_name_from_id(profession_id: u32) -> Option<String> {
-    match profession_id {
-        1 => Some("雷影剑士".to_string()),
-        2 => Some("冰魔导师".to_string()),
-        3 => Some("涤罪恶火·战斧".to_string()),
-        4 => Some("青岚骑士".to_string()),
-        5 => Some("森语者".to_string()),
-        8 => Some("雷霆一闪·手炮".to_string()),
-        9 => Some("巨刃守护者".to_string()),
-        10 => Some("暗灵祈舞·仪刀/仪仗".to_string()),
-        11 => Some("神射手".to_string()),
-        12 => Some("神盾骑士".to_string()),
-        13 => Some("灵魂乐手".to_string()),
-        _ => None,
+/// 全部技能id到流派名称的映射，供前端同步
+pub fn sub_profession_skill_map() -> HashMap<u32, &'static str> {
+    SUB_PROFESSION_SKILLS
+        .iter()
+        .flat_map(|&(name, skill_ids)| skill_ids.iter().map(move |&skill_id| (skill_id, name)))
+        .collect()
+}
+
+/// 按配置的语言查询内置职业名称；每种语言的名称表只构建一次
+pub fn get_profession_name_from_id(profession_id: u32, language: Language) -> Option<String> {
+    static ZH: OnceLock<NameTable> = OnceLock::new();
+    static EN: OnceLock<NameTable> = OnceLock::new();
+    let names = match language {
+        Language::Zh => &ZH,
+        Language::En => &EN,
+    };
+    names.get_or_init(|| NameTable::builtin(language)).profession_name(profession_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_from_csv_skips_malformed_rows() {
+        let mut config = SkillConfig::new();
+        let loaded = config.load_from_csv("id,name\n1201,雨打潮生\n\nbad row\nabc,Name\n1202,\"Tide, Final Hit\"\n1203,\n");
+
+        assert_eq!(loaded, 2);
+        assert_eq!(config.get_skill_name(1201), "雨打潮生");
+        assert_eq!(config.get_skill_name(1202), "Tide, Final Hit");
+        assert_eq!(config.get_skill_name(1203), "1203");
+    }
+
+    #[test]
+    fn test_profession_name_uses_language() {
+        let zh = get_profession_name_from_id(1, Language::Zh);
+        let en = get_profession_name_from_id(1, Language::En);
+        assert!(zh.is_some() && en.is_some());
+        assert_ne!(zh, en);
+        assert_eq!(get_profession_name_from_id(1, Language::Zh), zh);
+    }
+
+    #[test]
+    fn test_sub_profession_lookup_matches_map() {
+        assert_eq!(get_sub_profession_by_skill_id(1700825).as_deref(), Some("狼弓"));
+        assert_eq!(get_sub_profession_by_skill_id(1), None);
+
+        let map = sub_profession_skill_map();
+        assert_eq!(map.len(), 31);
+        assert!(map.iter().all(|(&skill_id, &name)| get_sub_profession_by_skill_id(skill_id).as_deref() == Some(name)));
     }
 }
@@ -1,8 +1,17 @@
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{HashMap, VecDeque};
+use chrono::{DateTime, Duration, Utc};
 
+// 同一次死亡可能带有多个致命伤害标记，该时间内的重复死亡只计一次
+const DEATH_DEDUP_MS: i64 = 3000;
+// 相邻两次命中间隔不超过该值时计入活跃战斗时间
+const ACTIVE_GAP_MS: i64 = 3000;
+// 旧格式的 skill_usage 把治疗技能id加上该偏移后与伤害技能存在同一个表里
+const LEGACY_HEALING_SKILL_OFFSET: u32 = 1_000_000_000;
+
+// 序列化由派生代码完成；反序列化先拆分旧格式的 skill_usage（见下方 Deserialize 实现）
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(remote = "Self")]
 pub struct User {
     pub uid: u32,
     pub name: String,
@@ -15,9 +24,28 @@ pub struct User {
     pub damage_stats: DamageStats,
     pub healing_stats: HealingStats,
     pub taken_damage: u32,
+    // 按攻击者uid统计的承受伤害（首领、小怪、机关等）
+    #[serde(default)]
+    pub taken_by_source: HashMap<u32, u64>,
     pub dead_count: u32,
-    pub skill_usage: HashMap<u32, SkillStats>,
+    // 伤害技能和治疗技能分开统计，同一技能id可能同时造成伤害和治疗
+    #[serde(default)]
+    pub damage_skills: HashMap<u32, SkillStats>,
+    #[serde(default)]
+    pub healing_skills: HashMap<u32, SkillStats>,
+    // 召唤物（炮台、宠物等）造成的伤害，按召唤物uuid区分；已计入总伤害
+    #[serde(default)]
+    pub summon_damage: HashMap<u64, u64>,
     pub last_update: DateTime<Utc>,
+    // 最近命中记录，用于计算滑动窗口DPS
+    #[serde(skip)]
+    pub recent_hits: VecDeque<(DateTime<Utc>, u64)>,
+    // 最近一次记录死亡的时间，用于去重
+    #[serde(skip)]
+    pub last_death_at: Option<DateTime<Utc>>,
+    // 汇总数据有变化，需要重新生成缓存的JSON（见 DataManager::get_all_users_data）
+    #[serde(skip, default = "default_dirty")]
+    pub dirty: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,8 +60,20 @@ pub struct DamageStats {
     pub critical_count: u32,
     pub lucky_count: u32,
     pub total_count: u32,
+    #[serde(default)]
+    pub miss_count: u32,
     pub dps: f64,
     pub dps_max: f64,
+    #[serde(default)]
+    pub window_dps: f64,
+    // 活跃战斗时间：只累计间隔不超过 ACTIVE_GAP_MS 的相邻命中之间的时间
+    #[serde(default)]
+    pub active_time_ms: u64,
+    #[serde(default)]
+    pub active_dps: f64,
+    // 按元素名称统计的伤害
+    #[serde(default)]
+    pub element_damage: HashMap<String, u64>,
     pub time_range: Option<(DateTime<Utc>, DateTime<Utc>)>,
 }
 
@@ -50,6 +90,11 @@ pub struct HealingStats {
     pub total_count: u32,
     pub hps: f64,
     pub hps_max: f64,
+    // 有效治疗 = 不超过目标缺失生命值的部分，其余计为过量治疗
+    #[serde(default)]
+    pub effective_healing: u64,
+    #[serde(default)]
+    pub overheal: u64,
     pub time_range: Option<(DateTime<Utc>, DateTime<Utc>)>,
 }
 
@@ -65,10 +110,37 @@ pub struct SkillStats {
     pub lucky_count: u32,
     pub crit_rate: f64,
     pub lucky_rate: f64,
+    #[serde(default = "default_min_hit")]
+    pub min_hit: u64,
+    #[serde(default)]
+    pub max_hit: u64,
+    #[serde(default)]
+    pub miss_count: u32,
+    #[serde(default)]
+    pub hit_histogram: HitHistogram,
+    // 该技能首次与最近一次命中的时间，用于计算技能DPS
+    #[serde(default)]
+    pub time_range: Option<(DateTime<Utc>, DateTime<Utc>)>,
     pub damage_breakdown: DamageBreakdown,
     pub count_breakdown: CountBreakdown,
 }
 
+/// 对数分桶数量：第 i 桶为二进制位数为 i 的数值（0、1、2-3、4-7……），覆盖整个 u64
+pub const HIT_HISTOGRAM_BUCKETS: usize = 65;
+
+/// 单次伤害/治疗量的对数分桶直方图，桶数固定
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HitHistogram {
+    counts: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct HistogramBucket {
+    pub min: u64,
+    pub max: u64,
+    pub count: u32,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DamageBreakdown {
     pub normal: u64,
@@ -86,6 +158,34 @@ pub struct CountBreakdown {
     pub total: u32,
 }
 
+impl Serialize for User {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        User::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for User {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let mut value = serde_json::Value::deserialize(deserializer)?;
+        let legacy = value.as_object_mut().and_then(|object| object.remove("skill_usage"));
+        let mut user = User::deserialize(value).map_err(D::Error::custom)?;
+
+        // 旧格式：id >= 1e9 的是治疗技能，减去偏移后放入 healing_skills
+        if let Some(legacy) = legacy {
+            let skills: HashMap<u32, SkillStats> = serde_json::from_value(legacy).map_err(D::Error::custom)?;
+            for (skill_id, stats) in skills {
+                match skill_id.checked_sub(LEGACY_HEALING_SKILL_OFFSET) {
+                    Some(healing_id) => user.healing_skills.insert(healing_id, stats),
+                    None => user.damage_skills.insert(skill_id, stats),
+                };
+            }
+        }
+        Ok(user)
+    }
+}
+
 impl Default for User {
     fn default() -> Self {
         Self {
@@ -100,9 +200,15 @@ impl Default for User {
             damage_stats: DamageStats::default(),
             healing_stats: HealingStats::default(),
             taken_damage: 0,
+            taken_by_source: HashMap::new(),
             dead_count: 0,
-            skill_usage: HashMap::new(),
+            damage_skills: HashMap::new(),
+            healing_skills: HashMap::new(),
+            summon_damage: HashMap::new(),
             last_update: Utc::now(),
+            recent_hits: VecDeque::new(),
+            last_death_at: None,
+            dirty: true,
         }
     }
 }
@@ -120,8 +226,13 @@ impl Default for DamageStats {
             critical_count: 0,
             lucky_count: 0,
             total_count: 0,
+            miss_count: 0,
             dps: 0.0,
             dps_max: 0.0,
+            window_dps: 0.0,
+            active_time_ms: 0,
+            active_dps: 0.0,
+            element_damage: HashMap::new(),
             time_range: None,
         }
     }
@@ -141,6 +252,8 @@ impl Default for HealingStats {
             total_count: 0,
             hps: 0.0,
             hps_max: 0.0,
+            effective_healing: 0,
+            overheal: 0,
             time_range: None,
         }
     }
@@ -154,21 +267,26 @@ impl User {
         }
     }
 
-    pub fn add_damage(&mut self, skill_id: u32, element: String, damage: u64, is_crit: bool, is_lucky: bool, is_cause_lucky: bool, hp_lessen: u64) {
-        let now = Utc::now();
+    // `now` 为服务器校准后的时间，由 DataManager 提供
+    pub fn add_damage(&mut self, skill_id: u32, element: String, damage: u64, is_crit: bool, is_lucky: bool, is_cause_lucky: bool, hp_lessen: u64, now: DateTime<Utc>) {
+        self.dirty = true;
 
         // 更新总体伤害统计
         if is_crit && is_lucky {
-            self.damage_stats.crit_lucky_damage += damage;
+            self.damage_stats.crit_lucky_damage = self.damage_stats.crit_lucky_damage.saturating_add(damage);
         } else if is_crit {
-            self.damage_stats.critical_damage += damage;
+            self.damage_stats.critical_damage = self.damage_stats.critical_damage.saturating_add(damage);
         } else if is_lucky {
-            self.damage_stats.lucky_damage += damage;
+            self.damage_stats.lucky_damage = self.damage_stats.lucky_damage.saturating_add(damage);
         } else {
-            self.damage_stats.normal_damage += damage;
+            self.damage_stats.normal_damage = self.damage_stats.normal_damage.saturating_add(damage);
         }
-        self.damage_stats.total_damage += damage;
-        self.damage_stats.hp_lessen += hp_lessen;
+        if saturating_accumulate(&mut self.damage_stats.total_damage, damage) {
+            log::warn!("User {} total damage reached u64::MAX and is capped; clear the statistics to keep DPS meaningful", self.uid);
+        }
+        self.damage_stats.hp_lessen = self.damage_stats.hp_lessen.saturating_add(hp_lessen);
+        let element_total = self.damage_stats.element_damage.entry(element.clone()).or_insert(0);
+        *element_total = element_total.saturating_add(damage);
 
         // 更新次数统计
         if is_crit {
@@ -181,10 +299,15 @@ impl User {
             self.damage_stats.normal_count += 1;
         }
         self.damage_stats.total_count += 1;
+        self.recent_hits.push_back((now, damage));
 
         // 更新时间范围
         match self.damage_stats.time_range {
-            Some((start, _)) => {
+            Some((start, last)) => {
+                let gap_ms = (now - last).num_milliseconds();
+                if (0..=ACTIVE_GAP_MS).contains(&gap_ms) {
+                    self.damage_stats.active_time_ms += gap_ms as u64;
+                }
                 self.damage_stats.time_range = Some((start, now));
             }
             None => {
@@ -193,27 +316,17 @@ impl User {
         }
 
         // 更新技能使用统计
-        let skill_key = skill_id;
-        if !self.skill_usage.contains_key(&skill_key) {
-            self.skill_usage.insert(skill_key, SkillStats {
-                skill_id,
-                display_name: skill_id.to_string(), // 暂时使用skill_id作为名称
-                skill_type: "damage".to_string(),
-                element,
-                total_damage: 0,
-                total_count: 0,
-                crit_count: 0,
-                lucky_count: 0,
-                crit_rate: 0.0,
-                lucky_rate: 0.0,
-                damage_breakdown: DamageBreakdown::default(),
-                count_breakdown: CountBreakdown::default(),
-            });
-        }
-
-        if let Some(skill_stat) = self.skill_usage.get_mut(&skill_key) {
-            skill_stat.total_damage += damage;
+        self.damage_skills
+            .entry(skill_id)
+            .or_insert_with(|| SkillStats::new(skill_id, "damage", element));
+
+        if let Some(skill_stat) = self.damage_skills.get_mut(&skill_id) {
+            skill_stat.total_damage = skill_stat.total_damage.saturating_add(damage);
             skill_stat.total_count += 1;
+            skill_stat.min_hit = skill_stat.min_hit.min(damage);
+            skill_stat.max_hit = skill_stat.max_hit.max(damage);
+            skill_stat.hit_histogram.record(damage);
+            skill_stat.extend_time_range(now);
             if is_crit {
                 skill_stat.crit_count += 1;
             }
@@ -223,15 +336,15 @@ impl User {
 
             // 更新技能伤害细分
             if is_crit && is_cause_lucky {
-                skill_stat.damage_breakdown.crit_lucky += damage;
+                skill_stat.damage_breakdown.crit_lucky = skill_stat.damage_breakdown.crit_lucky.saturating_add(damage);
             } else if is_crit {
-                skill_stat.damage_breakdown.critical += damage;
+                skill_stat.damage_breakdown.critical = skill_stat.damage_breakdown.critical.saturating_add(damage);
             } else if is_cause_lucky {
-                skill_stat.damage_breakdown.lucky += damage;
+                skill_stat.damage_breakdown.lucky = skill_stat.damage_breakdown.lucky.saturating_add(damage);
             } else {
-                skill_stat.damage_breakdown.normal += damage;
+                skill_stat.damage_breakdown.normal = skill_stat.damage_breakdown.normal.saturating_add(damage);
             }
-            skill_stat.damage_breakdown.total += damage;
+            skill_stat.damage_breakdown.total = skill_stat.damage_breakdown.total.saturating_add(damage);
 
             // 更新技能次数细分
             if is_crit {
@@ -261,21 +374,26 @@ impl User {
         self.last_update = now;
     }
 
-    pub fn add_healing(&mut self, skill_id: u32, element: String, healing: u64, is_crit: bool, is_lucky: bool, is_cause_lucky: bool) {
-        let now = Utc::now();
-        let skill_key = skill_id + 1000000000; // 区分治疗技能
+    /// `missing_hp` 为治疗时目标缺失的生命值；未知时全部计为有效治疗
+    pub fn add_healing(&mut self, skill_id: u32, element: String, healing: u64, is_crit: bool, is_lucky: bool, is_cause_lucky: bool, missing_hp: Option<u64>, now: DateTime<Utc>) {
+        self.dirty = true;
 
         // 更新总体治疗统计
         if is_crit && is_lucky {
-            self.healing_stats.crit_lucky_healing += healing;
+            self.healing_stats.crit_lucky_healing = self.healing_stats.crit_lucky_healing.saturating_add(healing);
         } else if is_crit {
-            self.healing_stats.critical_healing += healing;
+            self.healing_stats.critical_healing = self.healing_stats.critical_healing.saturating_add(healing);
         } else if is_lucky {
-            self.healing_stats.lucky_healing += healing;
+            self.healing_stats.lucky_healing = self.healing_stats.lucky_healing.saturating_add(healing);
         } else {
-            self.healing_stats.normal_healing += healing;
+            self.healing_stats.normal_healing = self.healing_stats.normal_healing.saturating_add(healing);
         }
-        self.healing_stats.total_healing += healing;
+        if saturating_accumulate(&mut self.healing_stats.total_healing, healing) {
+            log::warn!("User {} total healing reached u64::MAX and is capped; clear the statistics to keep HPS meaningful", self.uid);
+        }
+        let effective = missing_hp.map_or(healing, |missing| healing.min(missing));
+        self.healing_stats.effective_healing = self.healing_stats.effective_healing.saturating_add(effective);
+        self.healing_stats.overheal = self.healing_stats.overheal.saturating_add(healing - effective);
 
         // 更新次数统计
         if is_crit {
@@ -300,26 +418,17 @@ impl User {
         }
 
         // 更新技能使用统计
-        if !self.skill_usage.contains_key(&skill_key) {
-            self.skill_usage.insert(skill_key, SkillStats {
-                skill_id,
-                display_name: skill_id.to_string(),
-                skill_type: "healing".to_string(),
-                element,
-                total_damage: 0,
-                total_count: 0,
-                crit_count: 0,
-                lucky_count: 0,
-                crit_rate: 0.0,
-                lucky_rate: 0.0,
-                damage_breakdown: DamageBreakdown::default(),
-                count_breakdown: CountBreakdown::default(),
-            });
-        }
-
-        if let Some(skill_stat) = self.skill_usage.get_mut(&skill_key) {
-            skill_stat.total_damage += healing;
+        self.healing_skills
+            .entry(skill_id)
+            .or_insert_with(|| SkillStats::new(skill_id, "healing", element));
+
+        if let Some(skill_stat) = self.healing_skills.get_mut(&skill_id) {
+            skill_stat.total_damage = skill_stat.total_damage.saturating_add(healing);
             skill_stat.total_count += 1;
+            skill_stat.min_hit = skill_stat.min_hit.min(healing);
+            skill_stat.max_hit = skill_stat.max_hit.max(healing);
+            skill_stat.hit_histogram.record(healing);
+            skill_stat.extend_time_range(now);
             if is_crit {
                 skill_stat.crit_count += 1;
             }
@@ -329,15 +438,15 @@ impl User {
 
             // 更新技能治疗细分（复用damage_breakdown字段）
             if is_crit && is_cause_lucky {
-                skill_stat.damage_breakdown.crit_lucky += healing;
+                skill_stat.damage_breakdown.crit_lucky = skill_stat.damage_breakdown.crit_lucky.saturating_add(healing);
             } else if is_crit {
-                skill_stat.damage_breakdown.critical += healing;
+                skill_stat.damage_breakdown.critical = skill_stat.damage_breakdown.critical.saturating_add(healing);
             } else if is_cause_lucky {
-                skill_stat.damage_breakdown.lucky += healing;
+                skill_stat.damage_breakdown.lucky = skill_stat.damage_breakdown.lucky.saturating_add(healing);
             } else {
-                skill_stat.damage_breakdown.normal += healing;
+                skill_stat.damage_breakdown.normal = skill_stat.damage_breakdown.normal.saturating_add(healing);
             }
-            skill_stat.damage_breakdown.total += healing;
+            skill_stat.damage_breakdown.total = skill_stat.damage_breakdown.total.saturating_add(healing);
 
             // 更新技能次数细分
             if is_crit {
@@ -367,14 +476,65 @@ impl User {
         self.last_update = now;
     }
 
-    pub fn add_taken_damage(&mut self, damage: u32, is_dead: bool) {
-        self.taken_damage += damage as u32;
+    /// 记录召唤物造成的伤害（总伤害已通过 `add_damage` 计入主人）
+    pub fn add_summon_damage(&mut self, summon_uuid: u64, damage: u64) {
+        self.dirty = true;
+        saturating_accumulate(self.summon_damage.entry(summon_uuid).or_insert(0), damage);
+    }
+
+    /// 记录一次未命中，伤害为0所以不影响伤害统计和时间范围
+    pub fn add_miss(&mut self, skill_id: u32, element: String, now: DateTime<Utc>) {
+        self.dirty = true;
+        self.damage_stats.miss_count += 1;
+        self.damage_skills
+            .entry(skill_id)
+            .or_insert_with(|| SkillStats::new(skill_id, "damage", element))
+            .miss_count += 1;
+        self.last_update = now;
+    }
+
+    /// `source_uid` 为造成伤害的实体uid（通常是怪物）
+    pub fn add_taken_damage(&mut self, source_uid: u32, damage: u32, is_dead: bool, now: DateTime<Utc>) {
+        self.dirty = true;
+        self.taken_damage = self.taken_damage.saturating_add(damage);
+        let source_total = self.taken_by_source.entry(source_uid).or_insert(0);
+        *source_total = source_total.saturating_add(damage as u64);
+        self.last_update = now;
         if is_dead {
-            self.dead_count += 1;
+            self.record_death(now);
+        }
+    }
+
+    /// 记录一次死亡，短时间内的重复事件只计一次；返回是否计数
+    pub fn record_death(&mut self, now: DateTime<Utc>) -> bool {
+        if let Some(last) = self.last_death_at {
+            if now - last < Duration::milliseconds(DEATH_DEDUP_MS) {
+                return false;
+            }
+        }
+        self.last_death_at = Some(now);
+        self.dead_count += 1;
+        self.dirty = true;
+        true
+    }
+
+    // DPS随时间变化时才标记为脏，空闲时不重新生成汇总
+    pub fn update_dps(&mut self, window_secs: u64, now: DateTime<Utc>) {
+        let before = self.dps_values();
+        self.recompute_dps(window_secs, now);
+        if self.dps_values() != before {
+            self.dirty = true;
         }
     }
 
-    pub fn update_dps(&mut self) {
+    fn dps_values(&self) -> [f64; 4] {
+        let stats = &self.damage_stats;
+        [stats.dps, stats.dps_max, stats.window_dps, stats.active_dps]
+    }
+
+    fn recompute_dps(&mut self, window_secs: u64, now: DateTime<Utc>) {
+        self.update_window_dps(window_secs, now);
+
         if let Some((start, end)) = self.damage_stats.time_range {
             let duration_ms = (end - start).num_milliseconds() as f64;
             if duration_ms > 0.0 {
@@ -388,9 +548,40 @@ impl User {
                 }
             }
         }
+
+        if self.damage_stats.active_time_ms > 0 {
+            self.damage_stats.active_dps =
+                self.damage_stats.total_damage as f64 / self.damage_stats.active_time_ms as f64 * 1000.0;
+        }
+    }
+
+    /// 计算最近 `window_secs` 秒内的DPS，并淘汰窗口外的命中记录
+    fn update_window_dps(&mut self, window_secs: u64, now: DateTime<Utc>) {
+        let window_secs = window_secs.max(1);
+        // 窗口过大时无法算出截止时间，此时保留全部命中记录
+        let cutoff = i64::try_from(window_secs)
+            .ok()
+            .and_then(Duration::try_seconds)
+            .and_then(|window| now.checked_sub_signed(window));
+        if let Some(cutoff) = cutoff {
+            while self.recent_hits.front().is_some_and(|(t, _)| *t < cutoff) {
+                self.recent_hits.pop_front();
+            }
+        }
+
+        let window_damage = self.recent_hits.iter().fold(0u64, |sum, (_, damage)| sum.saturating_add(*damage));
+        self.damage_stats.window_dps = window_damage as f64 / window_secs as f64;
     }
 
     pub fn update_hps(&mut self) {
+        let before = (self.healing_stats.hps, self.healing_stats.hps_max);
+        self.recompute_hps();
+        if (self.healing_stats.hps, self.healing_stats.hps_max) != before {
+            self.dirty = true;
+        }
+    }
+
+    fn recompute_hps(&mut self) {
         if let Some((start, end)) = self.healing_stats.time_range {
             let duration_ms = (end - start).num_milliseconds() as f64;
             if duration_ms > 0.0 {
@@ -406,20 +597,30 @@ impl User {
         }
     }
 
-    pub fn reset(&mut self) {
+    // 只重置战斗统计；level/hp/max_hp 属于角色属性，由服务器同步，保留不变
+    pub fn reset(&mut self, now: DateTime<Utc>) {
+        self.dirty = true;
         self.damage_stats = DamageStats::default();
         self.healing_stats = HealingStats::default();
         self.taken_damage = 0;
-        self.skill_usage.clear();
+        self.taken_by_source.clear();
+        self.dead_count = 0;
+        self.damage_skills.clear();
+        self.healing_skills.clear();
+        self.summon_damage.clear();
+        self.recent_hits.clear();
+        self.last_death_at = None;
         self.fight_point = 0;
-        self.last_update = Utc::now();
+        self.last_update = now;
     }
 
     pub fn set_name(&mut self, name: String) {
+        self.dirty = true;
         self.name = name;
     }
 
     pub fn set_profession(&mut self, profession: String) {
+        self.dirty = true;
         if profession != self.profession {
             self.sub_profession.clear();
         }
@@ -427,14 +628,17 @@ impl User {
     }
 
     pub fn set_sub_profession(&mut self, sub_profession: String) {
+        self.dirty = true;
         self.sub_profession = sub_profession;
     }
 
     pub fn set_fight_point(&mut self, fight_point: u32) {
+        self.dirty = true;
         self.fight_point = fight_point;
     }
 
     pub fn set_attr(&mut self, key: &str, value: u32) {
+        self.dirty = true;
         match key {
             "hp" => self.hp = value,
             "max_hp" => self.max_hp = value,
@@ -444,6 +648,174 @@ impl User {
     }
 }
 
+/// 饱和累加，避免超长会话中总量溢出回绕；首次达到上限时返回 true
+fn saturating_accumulate(total: &mut u64, amount: u64) -> bool {
+    let capped = *total != u64::MAX && total.checked_add(amount).is_none();
+    *total = total.saturating_add(amount);
+    capped
+}
+
+fn default_dirty() -> bool {
+    true
+}
+
+fn default_min_hit() -> u64 {
+    u64::MAX
+}
+
+impl DamageStats {
+    /// 未命中次数占出手次数（命中+未命中）的比例
+    pub fn miss_rate(&self) -> f64 {
+        miss_rate(self.miss_count, self.total_count)
+    }
+
+    /// 暴击次数占命中次数的比例
+    pub fn crit_rate(&self) -> f64 {
+        hit_rate(self.critical_count, self.total_count)
+    }
+
+    /// 幸运次数占命中次数的比例
+    pub fn lucky_rate(&self) -> f64 {
+        hit_rate(self.lucky_count, self.total_count)
+    }
+}
+
+impl HealingStats {
+    /// 暴击次数占治疗次数的比例
+    pub fn crit_rate(&self) -> f64 {
+        hit_rate(self.critical_count, self.total_count)
+    }
+
+    /// 幸运次数占治疗次数的比例
+    pub fn lucky_rate(&self) -> f64 {
+        hit_rate(self.lucky_count, self.total_count)
+    }
+
+    /// 过量治疗占总治疗量的比例
+    pub fn overheal_rate(&self) -> f64 {
+        if self.total_healing > 0 {
+            self.overheal as f64 / self.total_healing as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+impl SkillStats {
+    pub fn new(skill_id: u32, skill_type: &str, element: String) -> Self {
+        Self {
+            skill_id,
+            display_name: skill_id.to_string(), // 暂时使用skill_id作为名称
+            skill_type: skill_type.to_string(),
+            element,
+            total_damage: 0,
+            total_count: 0,
+            crit_count: 0,
+            lucky_count: 0,
+            crit_rate: 0.0,
+            lucky_rate: 0.0,
+            min_hit: u64::MAX,
+            max_hit: 0,
+            miss_count: 0,
+            hit_histogram: HitHistogram::default(),
+            time_range: None,
+            damage_breakdown: DamageBreakdown::default(),
+            count_breakdown: CountBreakdown::default(),
+        }
+    }
+
+    /// 未命中次数占出手次数（命中+未命中）的比例
+    pub fn miss_rate(&self) -> f64 {
+        miss_rate(self.miss_count, self.total_count)
+    }
+
+    fn extend_time_range(&mut self, now: DateTime<Utc>) {
+        self.time_range = Some(match self.time_range {
+            Some((start, _)) => (start, now),
+            None => (now, now),
+        });
+    }
+
+    /// 技能首末次命中之间的每秒伤害/治疗量；只命中一次时为0
+    pub fn dps(&self) -> f64 {
+        match self.time_range {
+            Some((start, end)) => {
+                let duration_ms = (end - start).num_milliseconds();
+                if duration_ms > 0 {
+                    self.total_damage as f64 / duration_ms as f64 * 1000.0
+                } else {
+                    0.0
+                }
+            }
+            None => 0.0,
+        }
+    }
+
+    /// 平均单次伤害/治疗量，按需计算
+    pub fn avg_hit(&self) -> f64 {
+        if self.total_count > 0 {
+            self.total_damage as f64 / self.total_count as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+impl HitHistogram {
+    fn bucket_of(value: u64) -> usize {
+        (u64::BITS - value.leading_zeros()) as usize
+    }
+
+    pub fn record(&mut self, value: u64) {
+        if self.counts.len() < HIT_HISTOGRAM_BUCKETS {
+            self.counts.resize(HIT_HISTOGRAM_BUCKETS, 0);
+        }
+        let count = &mut self.counts[Self::bucket_of(value)];
+        *count = count.saturating_add(1);
+    }
+
+    /// 非空的桶，按数值从小到大
+    pub fn buckets(&self) -> Vec<HistogramBucket> {
+        // 导入的数据可能带有多余的桶，全部并入最后一桶
+        let mut counts = [0u32; HIT_HISTOGRAM_BUCKETS];
+        for (i, &count) in self.counts.iter().enumerate() {
+            let slot = &mut counts[i.min(HIT_HISTOGRAM_BUCKETS - 1)];
+            *slot = slot.saturating_add(count);
+        }
+
+        counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(i, &count)| {
+                let bits = i as u32;
+                HistogramBucket {
+                    min: if bits == 0 { 0 } else { 1u64.checked_shl(bits - 1).unwrap_or(0) },
+                    max: u64::MAX.checked_shr(u64::BITS.saturating_sub(bits)).unwrap_or(0),
+                    count,
+                }
+            })
+            .collect()
+    }
+}
+
+fn hit_rate(count: u32, total_count: u32) -> f64 {
+    if total_count > 0 {
+        count as f64 / total_count as f64
+    } else {
+        0.0
+    }
+}
+
+fn miss_rate(miss_count: u32, hit_count: u32) -> f64 {
+    let attempts = miss_count as u64 + hit_count as u64;
+    if attempts > 0 {
+        miss_count as f64 / attempts as f64
+    } else {
+        0.0
+    }
+}
+
 impl Default for DamageBreakdown {
     fn default() -> Self {
         Self {
@@ -466,3 +838,252 @@ impl Default for CountBreakdown {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reset_clears_dead_count() {
+        let mut user = User::new(1);
+        user.set_attr("level", 60);
+        user.add_taken_damage(75, 1000, true, Utc::now());
+        assert_eq!(user.dead_count, 1);
+
+        user.reset(Utc::now());
+        assert_eq!(user.dead_count, 0);
+        assert_eq!(user.taken_damage, 0);
+        assert_eq!(user.level, 60);
+    }
+
+    #[test]
+    fn test_record_death_deduplicates() {
+        let mut user = User::new(1);
+        let now = Utc::now();
+
+        // 同一次死亡的多个致命伤害几乎同时到达，只计一次
+        user.add_taken_damage(75, 1000, true, Utc::now());
+        assert!(!user.record_death(Utc::now()));
+        assert_eq!(user.dead_count, 1);
+
+        assert!(user.record_death(now + Duration::seconds(10)));
+        assert_eq!(user.dead_count, 2);
+    }
+
+    #[test]
+    fn test_add_miss_counts_without_damage() {
+        let mut user = User::new(1);
+        let now = Utc::now();
+        user.add_damage(100, "fire".to_string(), 500, false, false, false, 500, now);
+        user.add_damage(100, "fire".to_string(), 500, false, false, false, 500, now);
+        user.add_damage(100, "fire".to_string(), 500, false, false, false, 500, now);
+        user.add_miss(100, "fire".to_string(), now);
+
+        assert_eq!(user.damage_stats.miss_count, 1);
+        assert_eq!(user.damage_stats.total_damage, 1500);
+        assert_eq!(user.damage_stats.miss_rate(), 0.25);
+
+        let skill = &user.damage_skills[&100];
+        assert_eq!(skill.miss_count, 1);
+        assert_eq!(skill.total_count, 3);
+        assert_eq!(skill.miss_rate(), 0.25);
+
+        // 次数接近u32上限时相加不溢出
+        assert_eq!(miss_rate(u32::MAX, u32::MAX), 0.5);
+    }
+
+    #[test]
+    fn test_element_damage_breakdown() {
+        let mut user = User::new(1);
+        let now = Utc::now();
+        user.add_damage(100, "🔥火".to_string(), 500, false, false, false, 500, now);
+        user.add_damage(101, "🔥火".to_string(), 300, true, false, false, 300, now);
+        user.add_damage(200, "❄️冰".to_string(), 200, false, false, false, 200, now);
+
+        assert_eq!(user.damage_stats.element_damage["🔥火"], 800);
+        assert_eq!(user.damage_stats.element_damage["❄️冰"], 200);
+
+        user.reset(Utc::now());
+        assert!(user.damage_stats.element_damage.is_empty());
+    }
+
+    #[test]
+    fn test_active_time_skips_idle_gaps() {
+        let mut user = User::new(1);
+        let start = Utc::now();
+        user.add_damage(100, "🔥火".to_string(), 1000, false, false, false, 1000, start);
+        user.add_damage(100, "🔥火".to_string(), 1000, false, false, false, 1000, start + Duration::seconds(2));
+        // 空闲18秒后再次命中，不计入活跃时间
+        user.add_damage(100, "🔥火".to_string(), 1000, false, false, false, 1000, start + Duration::seconds(20));
+        user.add_damage(100, "🔥火".to_string(), 1000, false, false, false, 1000, start + Duration::seconds(22));
+        user.update_dps(5, start + Duration::seconds(22));
+
+        assert_eq!(user.damage_stats.active_time_ms, 4000);
+        assert_eq!(user.damage_stats.active_dps, 1000.0);
+        assert!((user.damage_stats.dps - 4000.0 / 22.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_add_healing_splits_overheal() {
+        let mut user = User::new(1);
+        let now = Utc::now();
+        user.add_healing(200, "🌟光".to_string(), 1000, false, false, false, Some(300), now);
+        // 目标生命值未知时全部计为有效治疗
+        user.add_healing(200, "🌟光".to_string(), 500, false, false, false, None, now);
+
+        assert_eq!(user.healing_stats.total_healing, 1500);
+        assert_eq!(user.healing_stats.effective_healing, 800);
+        assert_eq!(user.healing_stats.overheal, 700);
+        assert!((user.healing_stats.overheal_rate() - 700.0 / 1500.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_healing_skills_keyed_by_raw_skill_id() {
+        let mut user = User::new(1);
+        let now = Utc::now();
+        // 接近u32::MAX的技能id不会溢出，同一id的伤害和治疗分开统计
+        user.add_damage(u32::MAX, "🌟光".to_string(), 700, false, false, false, 700, now);
+        user.add_healing(u32::MAX, "🌟光".to_string(), 400, false, false, false, None, now);
+
+        assert_eq!(user.damage_skills[&u32::MAX].total_damage, 700);
+        assert_eq!(user.healing_skills[&u32::MAX].total_damage, 400);
+        assert_eq!(user.healing_skills[&u32::MAX].skill_type, "healing");
+    }
+
+    #[test]
+    fn test_totals_saturate_instead_of_wrapping() {
+        let mut user = User::new(1);
+        let now = Utc::now();
+        user.add_damage(100, "🔥火".to_string(), u64::MAX - 10, false, false, false, 0, now);
+        user.add_damage(100, "🔥火".to_string(), 1000, true, false, false, 0, now);
+        user.add_healing(200, "🌟光".to_string(), u64::MAX, false, false, false, None, now);
+        user.add_healing(200, "🌟光".to_string(), 1000, false, false, false, None, now);
+        user.update_dps(5, now);
+        user.dirty = false;
+        user.add_summon_damage(9, u64::MAX);
+        user.add_summon_damage(9, 1000);
+
+        assert!(user.dirty);
+        assert_eq!(user.summon_damage[&9], u64::MAX);
+        assert_eq!(user.damage_stats.total_damage, u64::MAX);
+        assert_eq!(user.damage_stats.element_damage["🔥火"], u64::MAX);
+        assert_eq!(user.damage_skills[&100].total_damage, u64::MAX);
+        assert_eq!(user.healing_stats.total_healing, u64::MAX);
+        assert_eq!(user.healing_skills[&200].damage_breakdown.total, u64::MAX);
+        assert!(user.damage_stats.window_dps > 0.0);
+    }
+
+    #[test]
+    fn test_window_dps_huge_window_does_not_panic() {
+        let mut user = User::new(1);
+        let now = Utc::now();
+        user.add_damage(100, "🔥火".to_string(), 1000, false, false, false, 0, now);
+        user.update_dps(u64::MAX, now);
+        user.update_dps(i64::MAX as u64, now);
+
+        assert_eq!(user.recent_hits.len(), 1);
+    }
+
+    #[test]
+    fn test_taken_damage_by_source() {
+        let mut user = User::new(1);
+        user.add_taken_damage(75, 1000, false, Utc::now());
+        user.add_taken_damage(80, 200, false, Utc::now());
+        user.add_taken_damage(75, 500, false, Utc::now());
+
+        assert_eq!(user.taken_damage, 1700);
+        assert_eq!(user.taken_by_source[&75], 1500);
+        assert_eq!(user.taken_by_source[&80], 200);
+
+        user.reset(Utc::now());
+        assert!(user.taken_by_source.is_empty());
+    }
+
+    #[test]
+    fn test_hit_histogram_log_buckets() {
+        let mut histogram = HitHistogram::default();
+        assert!(histogram.buckets().is_empty());
+
+        for value in [0, 1, 2, 3, 1000, 1023, 1024, u64::MAX] {
+            histogram.record(value);
+        }
+
+        let buckets = histogram.buckets();
+        assert_eq!(buckets[0], HistogramBucket { min: 0, max: 0, count: 1 });
+        assert_eq!(buckets[1], HistogramBucket { min: 1, max: 1, count: 1 });
+        assert_eq!(buckets[2], HistogramBucket { min: 2, max: 3, count: 2 });
+        assert_eq!(buckets[3], HistogramBucket { min: 512, max: 1023, count: 2 });
+        assert_eq!(buckets[4], HistogramBucket { min: 1024, max: 2047, count: 1 });
+        assert_eq!(buckets[5], HistogramBucket { min: 1 << 63, max: u64::MAX, count: 1 });
+        assert_eq!(histogram.counts.len(), HIT_HISTOGRAM_BUCKETS);
+
+        // 导入的数据桶数过多时并入最后一桶，不会溢出
+        let imported: HitHistogram = serde_json::from_str(&format!("{{\"counts\":{:?}}}", vec![1u32; 70])).unwrap();
+        let buckets = imported.buckets();
+        assert_eq!(buckets.len(), HIT_HISTOGRAM_BUCKETS);
+        assert_eq!(buckets[HIT_HISTOGRAM_BUCKETS - 1], HistogramBucket { min: 1 << 63, max: u64::MAX, count: 6 });
+    }
+
+    #[test]
+    fn test_user_crit_and_lucky_rates() {
+        let mut user = User::new(1);
+        assert_eq!(user.damage_stats.crit_rate(), 0.0);
+        assert_eq!(user.healing_stats.lucky_rate(), 0.0);
+
+        let now = Utc::now();
+        user.add_damage(1001, "fire".to_string(), 100, true, false, false, 100, now);
+        user.add_damage(1001, "fire".to_string(), 100, true, true, false, 100, now);
+        user.add_damage(1001, "fire".to_string(), 100, false, false, false, 100, now);
+        user.add_damage(1001, "fire".to_string(), 100, false, false, false, 100, now);
+        assert_eq!(user.damage_stats.crit_rate(), 0.5);
+        assert_eq!(user.damage_stats.lucky_rate(), 0.25);
+
+        user.add_healing(2001, "light".to_string(), 50, false, true, false, None, now);
+        assert_eq!(user.healing_stats.crit_rate(), 0.0);
+        assert_eq!(user.healing_stats.lucky_rate(), 1.0);
+    }
+
+    #[test]
+    fn test_skill_dps_uses_skill_time_range() {
+        let mut user = User::new(1);
+        let start = Utc::now();
+        user.add_damage(1001, "fire".to_string(), 1000, false, false, false, 1000, start);
+        assert_eq!(user.damage_skills[&1001].dps(), 0.0);
+
+        user.add_damage(2002, "ice".to_string(), 500, false, false, false, 500, start + chrono::Duration::seconds(1));
+        user.add_damage(1001, "fire".to_string(), 1000, false, false, false, 1000, start + chrono::Duration::seconds(4));
+        assert_eq!(user.damage_skills[&1001].time_range, Some((start, start + chrono::Duration::seconds(4))));
+        assert_eq!(user.damage_skills[&1001].dps(), 500.0);
+
+        user.reset(Utc::now());
+        assert!(user.damage_skills.is_empty());
+    }
+
+    #[test]
+    fn test_legacy_skill_usage_is_split() {
+        let mut user = User::new(7);
+        let now = Utc::now();
+        user.add_damage(100, "🔥火".to_string(), 500, false, false, false, 500, now);
+        user.add_healing(100, "🌿森".to_string(), 300, false, false, false, None, now);
+
+        // 构造旧格式：治疗技能以 id + 1e9 存在 skill_usage 中
+        let mut json = serde_json::to_value(&user).unwrap();
+        let object = json.as_object_mut().unwrap();
+        let mut legacy = object.remove("damage_skills").unwrap();
+        let healing = object.remove("healing_skills").unwrap();
+        legacy.as_object_mut().unwrap().insert("1000000100".to_string(), healing["100"].clone());
+        object.insert("skill_usage".to_string(), legacy);
+
+        let loaded: User = serde_json::from_value(json).unwrap();
+        assert_eq!(loaded.damage_skills.len(), 1);
+        assert_eq!(loaded.damage_skills[&100].total_damage, 500);
+        assert_eq!(loaded.healing_skills.len(), 1);
+        assert_eq!(loaded.healing_skills[&100].total_damage, 300);
+        assert_eq!(loaded.healing_skills[&100].skill_type, "healing");
+
+        // 新格式原样往返
+        let round_trip: User = serde_json::from_str(&serde_json::to_string(&user).unwrap()).unwrap();
+        assert_eq!(round_trip.damage_skills[&100].total_damage, 500);
+        assert_eq!(round_trip.healing_skills[&100].total_damage, 300);
+    }
+}
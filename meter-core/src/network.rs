@@ -0,0 +1,175 @@
+//! 网络适配器枚举，供界面选择捕获网卡
+
+use serde::Serialize;
+
+/// 一个网络适配器
+#[derive(Debug, Clone, Serialize)]
+pub struct NetworkInterface {
+    /// 接口索引，对应WinDivert过滤器中的 `ifIdx`
+    pub index: u32,
+    pub name: String,
+    pub description: String,
+    pub ips: Vec<String>,
+    pub is_up: bool,
+    pub is_loopback: bool,
+}
+
+/// 枚举本机的网络适配器
+pub fn list_network_interfaces() -> std::io::Result<Vec<NetworkInterface>> {
+    platform::list_network_interfaces()
+}
+
+/// 选择默认捕获网卡：第一个已启用、非回环且有IP地址的适配器
+pub fn find_default_interface() -> Option<NetworkInterface> {
+    list_network_interfaces()
+        .ok()?
+        .into_iter()
+        .find(|iface| iface.is_up && !iface.is_loopback && !iface.ips.is_empty())
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::NetworkInterface;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+    use winapi::shared::ifdef::IfOperStatusUp;
+    use winapi::shared::winerror::{ERROR_BUFFER_OVERFLOW, ERROR_SUCCESS};
+    use winapi::shared::ws2def::{AF_INET, AF_INET6, AF_UNSPEC, SOCKADDR_IN};
+    use winapi::shared::ws2ipdef::SOCKADDR_IN6_LH;
+    use winapi::um::iphlpapi::GetAdaptersAddresses;
+    use winapi::um::iptypes::{
+        GAA_FLAG_SKIP_ANYCAST, GAA_FLAG_SKIP_DNS_SERVER, GAA_FLAG_SKIP_MULTICAST, IP_ADAPTER_ADDRESSES_LH,
+    };
+
+    const IF_TYPE_SOFTWARE_LOOPBACK: u32 = 24;
+
+    pub fn list_network_interfaces() -> std::io::Result<Vec<NetworkInterface>> {
+        let flags = GAA_FLAG_SKIP_ANYCAST | GAA_FLAG_SKIP_MULTICAST | GAA_FLAG_SKIP_DNS_SERVER;
+        let mut size: u32 = 16 * 1024;
+        // 用u64保证缓冲区对齐
+        let mut buffer: Vec<u64>;
+
+        loop {
+            buffer = vec![0u64; (size as usize).div_ceil(8)];
+            let result = unsafe {
+                GetAdaptersAddresses(
+                    AF_UNSPEC as u32,
+                    flags,
+                    std::ptr::null_mut(),
+                    buffer.as_mut_ptr() as *mut IP_ADAPTER_ADDRESSES_LH,
+                    &mut size,
+                )
+            };
+            match result {
+                ERROR_SUCCESS => break,
+                ERROR_BUFFER_OVERFLOW => continue,
+                code => return Err(std::io::Error::from_raw_os_error(code as i32)),
+            }
+        }
+
+        let mut interfaces = Vec::new();
+        let mut adapter = buffer.as_ptr() as *const IP_ADAPTER_ADDRESSES_LH;
+        while !adapter.is_null() {
+            let current = unsafe { &*adapter };
+
+            let mut ips = Vec::new();
+            let mut unicast = current.FirstUnicastAddress;
+            while !unicast.is_null() {
+                let address = unsafe { &*unicast };
+                let sockaddr = address.Address.lpSockaddr;
+                if !sockaddr.is_null() {
+                    match unsafe { (*sockaddr).sa_family } as i32 {
+                        AF_INET => {
+                            let addr = unsafe { &*(sockaddr as *const SOCKADDR_IN) };
+                            let raw = unsafe { *addr.sin_addr.S_un.S_addr() };
+                            ips.push(Ipv4Addr::from(u32::from_be(raw)).to_string());
+                        }
+                        AF_INET6 => {
+                            let addr = unsafe { &*(sockaddr as *const SOCKADDR_IN6_LH) };
+                            let bytes = unsafe { *addr.sin6_addr.u.Byte() };
+                            ips.push(Ipv6Addr::from(bytes).to_string());
+                        }
+                        _ => {}
+                    }
+                }
+                unicast = address.Next;
+            }
+
+            let index = unsafe { current.u.s().IfIndex };
+            interfaces.push(NetworkInterface {
+                index: if index != 0 { index } else { current.Ipv6IfIndex },
+                name: unsafe { wide_to_string(current.FriendlyName) },
+                description: unsafe { wide_to_string(current.Description) },
+                ips,
+                is_up: current.OperStatus == IfOperStatusUp,
+                is_loopback: current.IfType == IF_TYPE_SOFTWARE_LOOPBACK,
+            });
+
+            adapter = current.Next;
+        }
+
+        Ok(interfaces)
+    }
+
+    unsafe fn wide_to_string(ptr: *const u16) -> String {
+        if ptr.is_null() {
+            return String::new();
+        }
+        let mut len = 0;
+        while *ptr.add(len) != 0 {
+            len += 1;
+        }
+        String::from_utf16_lossy(std::slice::from_raw_parts(ptr, len))
+    }
+}
+
+#[cfg(unix)]
+mod platform {
+    use super::NetworkInterface;
+    use std::collections::BTreeMap;
+    use std::ffi::CStr;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    pub fn list_network_interfaces() -> std::io::Result<Vec<NetworkInterface>> {
+        let mut ifaddrs: *mut libc::ifaddrs = std::ptr::null_mut();
+        if unsafe { libc::getifaddrs(&mut ifaddrs) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        // getifaddrs 每个地址返回一项，按接口名合并
+        let mut interfaces: BTreeMap<String, NetworkInterface> = BTreeMap::new();
+        let mut current = ifaddrs;
+        while !current.is_null() {
+            let entry = unsafe { &*current };
+            let name = unsafe { CStr::from_ptr(entry.ifa_name) }.to_string_lossy().into_owned();
+            let flags = entry.ifa_flags as libc::c_int;
+
+            let iface = interfaces.entry(name.clone()).or_insert_with(|| NetworkInterface {
+                index: unsafe { libc::if_nametoindex(entry.ifa_name) },
+                description: name.clone(),
+                name,
+                ips: Vec::new(),
+                is_up: flags & libc::IFF_UP != 0,
+                is_loopback: flags & libc::IFF_LOOPBACK != 0,
+            });
+
+            if !entry.ifa_addr.is_null() {
+                match unsafe { (*entry.ifa_addr).sa_family } as libc::c_int {
+                    libc::AF_INET => {
+                        let addr = unsafe { &*(entry.ifa_addr as *const libc::sockaddr_in) };
+                        iface.ips.push(Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)).to_string());
+                    }
+                    libc::AF_INET6 => {
+                        let addr = unsafe { &*(entry.ifa_addr as *const libc::sockaddr_in6) };
+                        iface.ips.push(Ipv6Addr::from(addr.sin6_addr.s6_addr).to_string());
+                    }
+                    _ => {}
+                }
+            }
+
+            current = entry.ifa_next;
+        }
+
+        unsafe { libc::freeifaddrs(ifaddrs) };
+        Ok(interfaces.into_values().collect())
+    }
+}
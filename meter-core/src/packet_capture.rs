@@ -1,82 +1,524 @@
-//! 使用WinDivert进行网络数据包捕获
-
-const BUF_SIZE: usize = 10 * 1024 * 1024; // 10MB缓冲区
+//! 网络数据包捕获（Windows使用WinDivert，其他平台使用libpcap）
 
+use crate::capture_backend::{CaptureBackend, ReplayBackend};
 use crate::{MeterError, Result};
 use crate::utils;
 use async_channel::{Receiver, Sender};
+use dashmap::DashMap;
 use lazy_static::lazy_static;
-use std::collections::BTreeMap;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use tokio::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{watch, Mutex};
 use tokio::task;
-use windivert::prelude::*;
 
 // PacketCapture 结构体包装
 pub struct PacketCapture {
-    filter: String,
+    data_manager: Arc<crate::data_manager::DataManager>,
+    // 停止标志，捕获循环每次迭代时检查
+    shutdown: Arc<AtomicBool>,
+    // 设置后从pcap文件回放，而不是实时捕获
+    replay: Option<ReplaySource>,
+    // 设置后将捕获的数据包录制到pcap文件
+    record_pcap: Option<PathBuf>,
+    // 当前捕获循环的任务句柄，重启时等待它退出并关闭捕获句柄
+    capture_task: Option<task::JoinHandle<()>>,
+    // 捕获配置，解析器从中读取服务ID等设置
+    config: crate::config::PacketCaptureConfig,
+}
+
+struct ReplaySource {
+    path: PathBuf,
+    // 是否按原始包间隔回放
+    realtime: bool,
 }
 
 impl PacketCapture {
-    pub fn new(_data_manager: Arc<crate::data_manager::DataManager>) -> Self {
+    pub fn new(data_manager: Arc<crate::data_manager::DataManager>) -> Self {
+        Self {
+            data_manager,
+            shutdown: Arc::new(AtomicBool::new(false)),
+            replay: None,
+            record_pcap: None,
+            capture_task: None,
+            config: crate::config::PacketCaptureConfig::default(),
+        }
+    }
+
+    /// 应用配置文件中的捕获设置（过滤器、录制路径）
+    pub fn with_config(mut self, config: &crate::config::PacketCaptureConfig) -> Self {
+        self.update_filter(config.filter.clone());
+        self.record_pcap = config.record_pcap.as_ref().map(PathBuf::from);
+        apply_runtime_config(config);
+        self.config = config.clone();
+        self
+    }
+
+    /// 从pcap/pcapng文件回放数据包，走与实时捕获相同的处理流程
+    pub fn from_pcap(
+        data_manager: Arc<crate::data_manager::DataManager>,
+        path: impl Into<PathBuf>,
+        realtime: bool,
+    ) -> Self {
         Self {
-            filter: "ip and tcp".to_string(),
+            replay: Some(ReplaySource {
+                path: path.into(),
+                realtime,
+            }),
+            ..Self::new(data_manager)
         }
     }
 
     pub async fn start_capture(&mut self) -> Result<()> {
-        let rx = start_capture(self.filter.clone())?;
+        self.shutdown.store(false, Ordering::SeqCst);
+
+        let (rx, capture_task) = match &self.replay {
+            Some(replay) => start_replay(&replay.path, replay.realtime, self.shutdown.clone())?,
+            // 过滤器通过watch通道下发，运行中修改会重建捕获句柄
+            None => start_capture_with_updates(
+                self.data_manager.subscribe_capture_filter(),
+                self.shutdown.clone(),
+                self.record_pcap.clone(),
+            )?,
+        };
+        self.capture_task = Some(capture_task);
         log::info!("Packet capture started");
 
-        // 这里可以启动一个任务来处理接收到的数据包
+        // 定期清理超时的服务器连接和IP分片，停止捕获后退出
+        let shutdown = self.shutdown.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(CONNECTION_CLEANUP_INTERVAL);
+            while !shutdown.load(Ordering::SeqCst) {
+                interval.tick().await;
+                cleanup_stale_connections().await;
+                cleanup_stale_fragments().await;
+            }
+        });
+
+        // 将重组出的游戏包交给解析器，更新统计数据
+        let mut parser = crate::packet_parser::PacketParser::new(self.data_manager.clone()).with_config(&self.config);
         tokio::spawn(async move {
             while let Ok((opcode, data)) = rx.recv().await {
-                // 处理接收到的数据包
                 log::debug!("Received packet: opcode=0x{:04x}, size={}", opcode, data.len());
+                parser.process_packet(&rebuild_frame(opcode, &data)).await;
             }
         });
 
         Ok(())
     }
 
+    /// 停止捕获：后端接收超时后捕获循环退出并关闭捕获句柄，无需等待下一个数据包
+    pub fn stop(&self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        log::info!("Packet capture stop requested");
+    }
+
+    /// 停止当前捕获、重置服务器识别状态后重新开始捕获，无需重启程序
+    pub async fn restart(&mut self) -> Result<()> {
+        self.stop();
+        // 等旧的捕获循环退出并关闭句柄后再打开新的后端，避免两个句柄同时抓包
+        if let Some(capture_task) = self.capture_task.take() {
+            if let Err(e) = capture_task.await {
+                log::warn!("Old capture task ended abnormally: {}", e);
+            }
+        }
+        // 旧的捕获循环仍持有旧标志，换用新标志，避免 start_capture 把旧循环重新置为运行
+        self.shutdown = Arc::new(AtomicBool::new(false));
+        reset_server_identification().await;
+        self.start_capture().await
+    }
+
     pub fn update_filter(&mut self, filter: String) {
-        self.filter = filter;
-        log::info!("Updated packet filter to: {}", self.filter);
+        log::info!("Updated packet filter to: {}", filter);
+        self.data_manager.set_capture_filter(filter);
+    }
+
+    pub fn get_current_filter(&self) -> String {
+        self.data_manager.get_capture_filter()
     }
 
-    pub fn get_current_filter(&self) -> &str {
-        &self.filter
+    /// 读取捕获统计（计数器和速率），不需要异步锁
+    pub fn get_stats() -> CaptureStats {
+        get_capture_stats()
     }
+
+    /// 只读取原子计数器，不更新速率采样
+    pub fn snapshot_counters() -> CaptureCounters {
+        snapshot_counters()
+    }
+}
+
+/// 处理来自API的重启请求（见 `DataManager::restart_capture`），在原位重启捕获
+pub fn spawn_restart_handler(
+    capture: Arc<Mutex<PacketCapture>>,
+    data_manager: Arc<crate::data_manager::DataManager>,
+) -> task::JoinHandle<()> {
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<crate::data_manager::CaptureRestartReply>(1);
+    data_manager.set_capture_restart_handler(tx);
+
+    tokio::spawn(async move {
+        while let Some(reply) = rx.recv().await {
+            log::info!("Restarting packet capture");
+            let result = capture.lock().await.restart().await.map_err(|e| e.to_string());
+            if let Err(e) = &result {
+                log::error!("Packet capture restart failed: {}", e);
+            }
+            let _ = reply.send(result);
+        }
+    })
 }
 
 // 全局状态变量
 lazy_static::lazy_static! {
-    static ref CURRENT_SERVER: Arc<Mutex<String>> = Arc::new(Mutex::new(String::new()));
-    static ref SERVER_IDENTIFIED: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
-    static ref TCP_CACHE: Arc<Mutex<BTreeMap<u32, Vec<u8>>>> = Arc::new(Mutex::new(BTreeMap::new()));
-    static ref TCP_NEXT_SEQ: Arc<Mutex<i64>> = Arc::new(Mutex::new(-1));
-    static ref TCP_LOCK: Arc<Mutex<()>> = Arc::new(Mutex::new(()));
-    static ref DATA_BUFFER: Arc<Mutex<Vec<u8>>> = Arc::new(Mutex::new(Vec::new()));
+    // 已识别的游戏服务器连接（服务器->客户端方向），每条连接独立加锁、独立重组
+    static ref SERVER_CONNECTIONS: ConnectionMap = DashMap::new();
+    // 只用于串行化新连接的识别（清理旧连接、插入新连接），已识别连接的数据包不经过该锁
+    static ref IDENTIFY_LOCK: Mutex<()> = Mutex::new(());
+    // 每个连接的重组缓存上限
+    static ref TCP_CACHE_LIMITS: parking_lot::RwLock<TcpCacheLimits> = parking_lot::RwLock::new(TcpCacheLimits::from(&crate::config::PacketCaptureConfig::default()));
+    // 连接超时时间（秒），超过该时间没有数据的连接会被清理
+    static ref CONNECTION_TIMEOUT_SECS: AtomicU64 = AtomicU64::new(crate::config::PacketCaptureConfig::default().connection_timeout);
+    static ref MAX_PACKET_SIZE: AtomicU64 = AtomicU64::new(crate::config::PacketCaptureConfig::default().max_packet_size as u64);
     static ref TCP_LAST_TIME: Arc<Mutex<u64>> = Arc::new(Mutex::new(0));
     // 调试计数器
     static ref PACKET_COUNTER: AtomicU64 = AtomicU64::new(0);
     static ref FILTERED_PACKETS: AtomicU64 = AtomicU64::new(0);
-    // 服务器切换检测计数器
-    static ref MISMATCHED_PACKETS: Arc<Mutex<u32>> = Arc::new(Mutex::new(0));
+    // 统计计数器
+    static ref PROCESSED_PACKETS: AtomicU64 = AtomicU64::new(0);
+    static ref TCP_CACHE_SIZE: AtomicU64 = AtomicU64::new(0);
+    static ref CAPTURE_STARTED_AT: AtomicU64 = AtomicU64::new(0);
+    static ref LAST_PACKET_AT: AtomicU64 = AtomicU64::new(0);
+    static ref BYTES_CAPTURED: AtomicU64 = AtomicU64::new(0);
+    static ref BYTES_PROCESSED: AtomicU64 = AtomicU64::new(0);
+    // 最近的游戏包原始数据，仅在配置了 debug_packet_capacity 时记录，用于 /api/debug/packets
+    static ref DEBUG_PACKETS: parking_lot::Mutex<VecDeque<DebugPacket>> = parking_lot::Mutex::new(VecDeque::new());
+    static ref DEBUG_PACKET_CAPACITY: AtomicUsize = AtomicUsize::new(0);
+    // 速率采样，只在读取统计时更新，不影响接收路径
+    static ref RATE_SAMPLES: parking_lot::Mutex<VecDeque<RateSample>> = parking_lot::Mutex::new(VecDeque::new());
+    // 服务器识别签名，启动时从配置加载
+    static ref SERVER_SIGNATURE: parking_lot::RwLock<ServerSignature> = parking_lot::RwLock::new(ServerSignature::default());
+    // IP分片缓存
+    static ref FRAGMENT_CACHE: Arc<Mutex<HashMap<FragmentKey, FragmentBuffer>>> = Arc::new(Mutex::new(HashMap::new()));
+    static ref FRAGMENT_LIMITS: parking_lot::RwLock<FragmentLimits> = parking_lot::RwLock::new(FragmentLimits::from(&crate::config::PacketCaptureConfig::default()));
 }
 
-// 解析IP头部并返回TCP数据包
+/// 识别游戏服务器所用的签名
+#[derive(Debug, Clone)]
+pub struct ServerSignature {
+    pub signature: Vec<u8>,
+    pub offset: usize,
+    pub login_response_len: usize,
+}
+
+impl Default for ServerSignature {
+    fn default() -> Self {
+        let config = crate::config::PacketCaptureConfig::default();
+        Self {
+            signature: config.server_signature,
+            offset: config.signature_offset,
+            login_response_len: config.login_response_len,
+        }
+    }
+}
+
+/// 更新服务器识别签名（协议变更时无需重新编译）
+pub fn set_server_signature(signature: ServerSignature) {
+    *SERVER_SIGNATURE.write() = signature;
+}
+
+/// 应用运行时可修改的配置（连接超时、TCP缓存上限、包长度上限、服务器签名），配置热重载时也会调用
+pub fn apply_runtime_config(config: &crate::config::PacketCaptureConfig) {
+    CONNECTION_TIMEOUT_SECS.store(config.connection_timeout, Ordering::Relaxed);
+    MAX_PACKET_SIZE.store(config.max_packet_size as u64, Ordering::Relaxed);
+    set_debug_packet_capacity(config.debug_packet_capacity);
+    *TCP_CACHE_LIMITS.write() = TcpCacheLimits::from(config);
+    *FRAGMENT_LIMITS.write() = FragmentLimits::from(config);
+    set_server_signature(ServerSignature {
+        signature: config.server_signature.clone(),
+        offset: config.signature_offset,
+        login_response_len: config.login_response_len,
+    });
+}
+
+// TCP标志位
+const TCP_FIN: u8 = 0x01;
+const TCP_SYN: u8 = 0x02;
+const TCP_RST: u8 = 0x04;
+
+// 清理空闲连接的检查间隔
+const CONNECTION_CLEANUP_INTERVAL: Duration = Duration::from_secs(30);
+// 回放等待包间隔时检查停止标志的间隔
+const PACING_STEP: Duration = Duration::from_millis(100);
+
+/// TCP连接标识（区分数据流方向）
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ConnectionKey {
+    pub src_ip: String,
+    pub src_port: u16,
+    pub dst_ip: String,
+    pub dst_port: u16,
+}
+
+impl ConnectionKey {
+    /// 同一连接反方向的标识
+    pub fn reversed(&self) -> Self {
+        Self {
+            src_ip: self.dst_ip.clone(),
+            src_port: self.dst_port,
+            dst_ip: self.src_ip.clone(),
+            dst_port: self.src_port,
+        }
+    }
+}
+
+impl std::fmt::Display for ConnectionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{} -> {}:{}", self.src_ip, self.src_port, self.dst_ip, self.dst_port)
+    }
+}
+
+/// 每个连接的重组缓存上限，防止丢包时缓存无限增长
+#[derive(Debug, Clone, Copy)]
+struct TcpCacheLimits {
+    max_segments: usize,
+    max_bytes: usize,
+    max_data_buffer: usize,
+}
+
+impl From<&crate::config::PacketCaptureConfig> for TcpCacheLimits {
+    fn from(config: &crate::config::PacketCaptureConfig) -> Self {
+        Self {
+            max_segments: config.max_tcp_cache_segments,
+            max_bytes: config.max_tcp_cache_bytes,
+            max_data_buffer: config.max_data_buffer_bytes,
+        }
+    }
+}
+
+/// 单个服务器连接的重组状态
+struct ServerState {
+    tcp_cache: BTreeMap<u32, Vec<u8>>,
+    next_seq: i64,
+    data_buffer: Vec<u8>,
+    // 最后一次收到数据的Unix时间戳（秒）
+    last_seen: u64,
+}
+
+impl ServerState {
+    fn new() -> Self {
+        Self {
+            tcp_cache: BTreeMap::new(),
+            next_seq: -1,
+            data_buffer: Vec::new(),
+            last_seen: unix_now_secs(),
+        }
+    }
+
+    /// 缓存超过上限时丢弃离期望序列号最远的分段；数据缓冲区超过上限时重置数据流，返回是否丢弃了数据
+    ///
+    /// 离期望序列号最近的分段最可能在缺口补齐后拼接上，因此保留。缓冲区中的半个包无法再拼完整时，
+    /// 同时清空数据缓冲区和分段缓存，等待下一个游戏包开头重新同步。
+    fn enforce_limits(&mut self, limits: &TcpCacheLimits) -> bool {
+        let mut cached_bytes: usize = self.tcp_cache.values().map(Vec::len).sum();
+        let mut dropped = 0;
+        while self.tcp_cache.len() > limits.max_segments || cached_bytes > limits.max_bytes {
+            // 按与期望序列号的距离选择（处理32位回绕）
+            let expected = self.next_seq as u32;
+            let Some(furthest) = self.tcp_cache.keys().copied().max_by_key(|&seq| seq.wrapping_sub(expected)) else {
+                break;
+            };
+            if let Some(segment) = self.tcp_cache.remove(&furthest) {
+                cached_bytes -= segment.len();
+                dropped += 1;
+            }
+        }
+
+        if dropped > 0 {
+            log::warn!(
+                "⚠️ TCP缓存超过上限，丢弃{}个离期望序列号最远的分段 (剩余: {} 个分段, {} bytes)",
+                dropped,
+                self.tcp_cache.len(),
+                cached_bytes
+            );
+        }
+        if self.data_buffer.len() <= limits.max_data_buffer {
+            return dropped > 0;
+        }
+
+        log::warn!(
+            "⚠️ 数据缓冲区超过上限: {} bytes，清空缓冲区并重置数据流",
+            self.data_buffer.len()
+        );
+        self.data_buffer.clear();
+        self.tcp_cache.clear();
+        self.next_seq = -1;
+        true
+    }
+}
+
+// IP分片缓存键：(源IP, 目的IP, 协议, 标识)
+type FragmentKey = ([u8; 4], [u8; 4], u8, u16);
+
+// 同一IP数据包的分片集合
+#[derive(Debug)]
+struct FragmentBuffer {
+    // 首个分片的IP头部
+    header: Option<Vec<u8>>,
+    // 字节偏移 -> 分片载荷
+    fragments: BTreeMap<usize, Vec<u8>>,
+    // 收到最后一个分片后才知道总载荷长度
+    total_len: Option<usize>,
+    // 收到第一个分片的时间，用于淘汰
+    first_seen: Instant,
+}
+
+impl FragmentBuffer {
+    fn new() -> Self {
+        Self {
+            header: None,
+            fragments: BTreeMap::new(),
+            total_len: None,
+            first_seen: Instant::now(),
+        }
+    }
+}
+
+/// IP分片缓存上限，防止大量永远等不齐的分片占满内存
+#[derive(Debug, Clone, Copy)]
+struct FragmentLimits {
+    max_entries: usize,
+    max_fragments_per_packet: usize,
+}
+
+impl From<&crate::config::PacketCaptureConfig> for FragmentLimits {
+    fn from(config: &crate::config::PacketCaptureConfig) -> Self {
+        Self {
+            max_entries: config.max_fragment_entries,
+            max_fragments_per_packet: config.max_fragments_per_packet,
+        }
+    }
+}
+
+// 分片超过该时间仍未到齐则丢弃
+const FRAGMENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// 13位分片偏移占字节6的低5位和字节7，单位为8字节；字节6的高3位是标志位
+fn fragment_offset(ip_data: &[u8]) -> usize {
+    ((((ip_data[6] & 0x1F) as u16) << 8 | ip_data[7] as u16) as usize) * 8
+}
+
+// MF（More Fragments）标志
+fn more_fragments(ip_data: &[u8]) -> bool {
+    ip_data[6] & 0x20 != 0
+}
+
+/// 处理IP分片：未分片的数据包原样返回，分片到齐后返回重组后的IP数据包，否则返回None
+fn handle_ip_fragmentation<'a>(
+    cache: &mut HashMap<FragmentKey, FragmentBuffer>,
+    limits: &FragmentLimits,
+    ip_data: &'a [u8],
+) -> Option<Cow<'a, [u8]>> {
+    if ip_data.len() < 20 || ip_data[0] >> 4 != 4 {
+        return Some(Cow::Borrowed(ip_data));
+    }
+
+    let offset = fragment_offset(ip_data);
+    let more = more_fragments(ip_data);
+    if offset == 0 && !more {
+        return Some(Cow::Borrowed(ip_data));
+    }
+
+    let header_len = ((ip_data[0] & 0x0F) as usize) * 4;
+    let total_len = u16::from_be_bytes([ip_data[2], ip_data[3]]) as usize;
+    if header_len < 20 || total_len < header_len || ip_data.len() < total_len {
+        return None;
+    }
+
+    let key = (
+        [ip_data[12], ip_data[13], ip_data[14], ip_data[15]],
+        [ip_data[16], ip_data[17], ip_data[18], ip_data[19]],
+        ip_data[9],
+        u16::from_be_bytes([ip_data[4], ip_data[5]]),
+    );
+
+    if !cache.contains_key(&key) && cache.len() >= limits.max_entries {
+        if let Some(oldest) = cache.iter().min_by_key(|(_, buffer)| buffer.first_seen).map(|(key, _)| *key) {
+            cache.remove(&oldest);
+            log::warn!("⚠️ IP分片缓存已满({}个数据包)，丢弃最早的未完成分片", limits.max_entries);
+        }
+    }
+
+    let buffer = cache.entry(key).or_insert_with(FragmentBuffer::new);
+    if !buffer.fragments.contains_key(&offset) && buffer.fragments.len() >= limits.max_fragments_per_packet {
+        log::warn!("⚠️ IP数据包分片数超过上限({})，丢弃该数据包的所有分片", limits.max_fragments_per_packet);
+        cache.remove(&key);
+        return None;
+    }
+    if offset == 0 {
+        buffer.header = Some(ip_data[..header_len].to_vec());
+    }
+    if !more {
+        buffer.total_len = Some(offset + total_len - header_len);
+    }
+    buffer
+        .fragments
+        .insert(offset, ip_data[header_len..total_len].to_vec());
+
+    let reassembled = reassemble_fragments(buffer)?;
+    cache.remove(&key);
+    Some(Cow::Owned(reassembled))
+}
+
+/// 分片齐全时拼接成完整的IP数据包（清除分片标志并更新总长度）
+fn reassemble_fragments(buffer: &FragmentBuffer) -> Option<Vec<u8>> {
+    let header = buffer.header.as_ref()?;
+    let payload_len = buffer.total_len?;
+
+    let mut payload = Vec::with_capacity(payload_len);
+    for (&offset, data) in &buffer.fragments {
+        if offset > payload.len() {
+            // 中间缺少分片
+            return None;
+        }
+        let skip = payload.len() - offset;
+        if skip < data.len() {
+            payload.extend_from_slice(&data[skip..]);
+        }
+    }
+    if payload.len() < payload_len {
+        return None;
+    }
+    payload.truncate(payload_len);
+
+    let mut packet = header.clone();
+    let total_len = (header.len() + payload.len()).min(u16::MAX as usize) as u16;
+    packet[2..4].copy_from_slice(&total_len.to_be_bytes());
+    packet[6] &= !0x3F;
+    packet[7] = 0;
+    packet.extend_from_slice(&payload);
+    Some(packet)
+}
+
+// 解析IP头部并返回TCP数据包（支持IPv4和IPv6）
 fn parse_ip_header(ip_data: &[u8]) -> Result<(&[u8], String, String, u16, u16)> {
-    if ip_data.len() < 20 {
+    if ip_data.is_empty() {
         return Err(MeterError::ParseError("IP数据包太小".to_string()));
     }
 
     // 检查IP版本
-    let ip_version = ip_data[0] >> 4;
-    if ip_version != 4 {
-        return Err(MeterError::ParseError("不是IPv4".to_string()));
+    match ip_data[0] >> 4 {
+        4 => parse_ipv4_header(ip_data),
+        6 => parse_ipv6_header(ip_data),
+        _ => Err(MeterError::ParseError("不是IPv4或IPv6".to_string())),
+    }
+}
+
+fn parse_ipv4_header(ip_data: &[u8]) -> Result<(&[u8], String, String, u16, u16)> {
+    if ip_data.len() < 20 {
+        return Err(MeterError::ParseError("IP数据包太小".to_string()));
     }
 
     // IP头部长度
@@ -106,51 +548,126 @@ fn parse_ip_header(ip_data: &[u8]) -> Result<(&[u8], String, String, u16, u16)>
     Ok((&ip_data[ip_header_len..], src_ip, dst_ip, 0, 0)) // 暂时返回0端口
 }
 
-// 解析TCP头部并返回payload
-fn parse_tcp_header(tcp_data: &[u8]) -> Result<(&[u8], u16, u16, u32)> {
-    if tcp_data.len() < 20 {
-        return Err(MeterError::ParseError("TCP数据包太小".to_string()));
+// IPv6扩展头类型
+const IPV6_HOP_BY_HOP: u8 = 0;
+const IPV6_ROUTING: u8 = 43;
+const IPV6_FRAGMENT: u8 = 44;
+const IPV6_AUTH: u8 = 51;
+const IPV6_DEST_OPTS: u8 = 60;
+
+fn parse_ipv6_header(ip_data: &[u8]) -> Result<(&[u8], String, String, u16, u16)> {
+    const IPV6_HEADER_LEN: usize = 40;
+    if ip_data.len() < IPV6_HEADER_LEN {
+        return Err(MeterError::ParseError("IPv6数据包太小".to_string()));
+    }
+
+    // 遍历扩展头，找到TCP
+    let mut next_header = ip_data[6];
+    let mut offset = IPV6_HEADER_LEN;
+    loop {
+        match next_header {
+            6 => break,
+            IPV6_HOP_BY_HOP | IPV6_ROUTING | IPV6_DEST_OPTS | IPV6_AUTH | IPV6_FRAGMENT => {
+                if ip_data.len() < offset + 8 {
+                    return Err(MeterError::ParseError("IPv6扩展头不完整".to_string()));
+                }
+                let header_len = match next_header {
+                    IPV6_FRAGMENT => {
+                        // 只接受未真正分片的数据包（偏移为0且没有后续分片）
+                        let frag = u16::from_be_bytes([ip_data[offset + 2], ip_data[offset + 3]]);
+                        if frag & 0xFFF9 != 0 {
+                            return Err(MeterError::ParseError("IPv6分片暂不支持".to_string()));
+                        }
+                        8
+                    }
+                    // AH长度以4字节为单位（不含前2个单位）
+                    IPV6_AUTH => (ip_data[offset + 1] as usize + 2) * 4,
+                    // 其余扩展头长度以8字节为单位（不含前8字节）
+                    _ => (ip_data[offset + 1] as usize + 1) * 8,
+                };
+                next_header = ip_data[offset];
+                offset += header_len;
+            }
+            _ => return Err(MeterError::ParseError("不是TCP协议".to_string())),
+        }
+    }
+
+    if ip_data.len() < offset + 20 {
+        return Err(MeterError::ParseError(
+            "数据包太小，没有TCP头部".to_string(),
+        ));
     }
 
-    // TCP头部长度
-    let tcp_header_len = ((tcp_data[12] >> 4) as usize) * 4;
+    let address = |start: usize| {
+        let mut octets = [0u8; 16];
+        octets.copy_from_slice(&ip_data[start..start + 16]);
+        // 加方括号，避免与端口号的冒号混淆
+        format!("[{}]", std::net::Ipv6Addr::from(octets))
+    };
 
-    // 提取端口
-    let src_port = u16::from_be_bytes([tcp_data[0], tcp_data[1]]);
-    let dst_port = u16::from_be_bytes([tcp_data[2], tcp_data[3]]);
+    Ok((&ip_data[offset..], address(8), address(24), 0, 0))
+}
 
-    // 提取序列号
-    let seq_no = u32::from_be_bytes([tcp_data[4], tcp_data[5], tcp_data[6], tcp_data[7]]);
+/// TCP头部中重组需要的字段
+#[derive(Debug, Clone, Copy)]
+struct TcpPacketInfo {
+    src_port: u16,
+    dst_port: u16,
+    seq_no: u32,
+    flags: u8,
+}
 
-    let payload_offset = tcp_header_len;
-    if tcp_data.len() <= payload_offset {
-        return Err(MeterError::ParseError("没有TCP payload".to_string()));
+impl TcpPacketInfo {
+    /// 解析TCP头部，返回头部信息和payload（payload可以为空，例如FIN/RST包）
+    fn parse(tcp_data: &[u8]) -> Result<(Self, &[u8])> {
+        if tcp_data.len() < 20 {
+            return Err(MeterError::ParseError("TCP数据包太小".to_string()));
+        }
+
+        // TCP头部长度
+        let tcp_header_len = ((tcp_data[12] >> 4) as usize) * 4;
+        if tcp_header_len < 20 || tcp_data.len() < tcp_header_len {
+            return Err(MeterError::ParseError("TCP头部长度无效".to_string()));
+        }
+
+        let info = Self {
+            src_port: u16::from_be_bytes([tcp_data[0], tcp_data[1]]),
+            dst_port: u16::from_be_bytes([tcp_data[2], tcp_data[3]]),
+            seq_no: u32::from_be_bytes([tcp_data[4], tcp_data[5], tcp_data[6], tcp_data[7]]),
+            flags: tcp_data[13],
+        };
+
+        Ok((info, &tcp_data[tcp_header_len..]))
     }
 
-    Ok((&tcp_data[payload_offset..], src_port, dst_port, seq_no))
+    fn has_flag(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
 }
 
 // 尝试通过小包识别服务器
-async fn try_identify_server_by_small_packet(buf: &[u8], src_server: &str) -> Result<()> {
+fn try_identify_server_by_small_packet(buf: &[u8], src_server: &str) -> bool {
     if buf.len() <= 10 {
         log::debug!("🔍 小包识别跳过 - 数据包太小: {} bytes", buf.len());
-        return Ok(());
+        return false;
     }
 
     // 检查buf[4] == 0
     if buf[4] != 0 {
         log::debug!("🔍 小包识别跳过 - buf[4] != 0: 0x{:02x}", buf[4]);
-        return Ok(());
+        return false;
     }
 
     let data = &buf[10..];
     if data.is_empty() {
         log::debug!("🔍 小包识别跳过 - 数据部分为空");
-        return Ok(());
+        return false;
     }
 
     log::debug!("🔍 开始小包识别 - 解析数据流，大小: {} bytes", data.len());
 
+    let ServerSignature { signature, offset: signature_offset, .. } = SERVER_SIGNATURE.read().clone();
+
     // 解析数据流
     let mut offset = 0;
     while offset + 4 <= data.len() {
@@ -168,31 +685,15 @@ async fn try_identify_server_by_small_packet(buf: &[u8], src_server: &str) -> Re
         }
 
         let packet_data = &data[offset + 4..offset + 4 + packet_len];
-        if packet_data.len() >= 11 {
-            // 检查签名 0x00, 0x63, 0x33, 0x53, 0x42, 0x00
-            let signature = [0x00, 0x63, 0x33, 0x53, 0x42, 0x00];
-            if packet_data[5..5 + signature.len()] == signature {
-                // 找到匹配的签名，更新服务器
-                let mut current_server = CURRENT_SERVER.lock().await;
-                if *current_server != src_server {
-                    log::info!("🎯 通过小包识别找到游戏服务器!");
-                    log::info!("🏠 服务器地址: {}", src_server);
-                    log::info!("🔍 匹配签名: {:02x?} (偏移量: 5)", signature);
-                    log::info!("📦 数据包大小: {} bytes", packet_len);
-                    log::info!("✅ 服务器识别完成，开始跟踪该连接的数据包");
-
-                    *current_server = src_server.to_string();
-
-                    // 设置服务器已识别状态
-                    let mut server_identified = SERVER_IDENTIFIED.lock().await;
-                    *server_identified = true;
-
-                    clear_tcp_cache().await;
-                    let mut tcp_next_seq = TCP_NEXT_SEQ.lock().await;
-                    *tcp_next_seq = -1;
-                    clear_data_on_server_change();
-                }
-                return Ok(());
+        if packet_data.len() >= signature_offset + signature.len() {
+            // 检查签名（默认 0x00, 0x63, 0x33, 0x53, 0x42, 0x00，偏移量5）
+            if packet_data[signature_offset..signature_offset + signature.len()] == signature[..] {
+                // 找到匹配的签名
+                log::info!("🎯 通过小包识别找到游戏服务器!");
+                log::info!("🏠 服务器地址: {}", src_server);
+                log::info!("🔍 匹配签名: {:02x?} (偏移量: {})", signature, signature_offset);
+                log::info!("📦 数据包大小: {} bytes", packet_len);
+                return true;
             }
         }
 
@@ -200,17 +701,19 @@ async fn try_identify_server_by_small_packet(buf: &[u8], src_server: &str) -> Re
     }
 
     log::debug!("🔍 小包识别完成 - 未找到匹配的签名");
-    Ok(())
+    false
 }
 
 // 尝试通过登录返回包识别服务器
-async fn try_identify_server_by_login_response(buf: &[u8], src_server: &str) -> Result<()> {
-    if buf.len() != 0x62 {
+fn try_identify_server_by_login_response(buf: &[u8], src_server: &str) -> bool {
+    let login_response_len = SERVER_SIGNATURE.read().login_response_len;
+    if buf.len() != login_response_len {
         log::debug!(
-            "🔍 登录返回包识别跳过 - 数据包大小不匹配: {} bytes (期望: 98 bytes)",
-            buf.len()
+            "🔍 登录返回包识别跳过 - 数据包大小不匹配: {} bytes (期望: {} bytes)",
+            buf.len(),
+            login_response_len
         );
-        return Ok(());
+        return false;
     }
 
     log::debug!("🔍 开始登录返回包识别 - 数据包大小: {} bytes", buf.len());
@@ -221,8 +724,10 @@ async fn try_identify_server_by_login_response(buf: &[u8], src_server: &str) ->
         0x00, 0x00, 0x00, 0x0a, 0x4e, 0x08, 0x01, 0x22, 0x24,
     ];
 
-    // 检查签名匹配
-    let signature1_match = buf.len() >= 10 && buf[0..10] == signature[0..10];
+    // 检查签名匹配（前4字节是长度前缀，随配置的包长度变化）
+    let signature1_match = buf.len() >= 10
+        && buf[0..4] == (login_response_len as u32).to_be_bytes()
+        && buf[4..10] == signature[4..10];
     let signature2_match = buf.len() >= 20 && buf[14..20] == signature[14..20];
 
     log::debug!(
@@ -232,37 +737,96 @@ async fn try_identify_server_by_login_response(buf: &[u8], src_server: &str) ->
     );
 
     if signature1_match && signature2_match {
-        let mut current_server = CURRENT_SERVER.lock().await;
-        if *current_server != src_server {
-            log::info!("🎯 通过登录返回包识别找到游戏服务器!");
-            log::info!("🏠 服务器地址: {}", src_server);
-            log::info!("🔍 匹配签名模式: 98字节登录返回包");
-            log::info!("📦 数据包大小: {} bytes", buf.len());
+        log::info!("🎯 通过登录返回包识别找到游戏服务器!");
+        log::info!("🏠 服务器地址: {}", src_server);
+        log::info!("🔍 匹配签名模式: {}字节登录返回包", login_response_len);
+        log::info!("📦 数据包大小: {} bytes", buf.len());
+        return true;
+    }
 
-            *current_server = src_server.to_string();
+    log::debug!("🔍 登录返回包识别完成 - 签名不匹配");
+    false
+}
 
-            // 设置服务器已识别状态
-            let mut server_identified = SERVER_IDENTIFIED.lock().await;
-            *server_identified = true;
+// 序列号比较（处理32位回绕）：返回 a - b 的有符号差值
+fn seq_diff(a: u32, b: u32) -> i32 {
+    a.wrapping_sub(b) as i32
+}
 
-            clear_tcp_cache().await;
-            let mut tcp_next_seq = TCP_NEXT_SEQ.lock().await;
-            *tcp_next_seq = -1;
-            clear_data_on_server_change();
+/// 将一个TCP分段放入缓存，并取出从 `next_seq` 开始的全部连续数据
+///
+/// `next_seq` 为 -1 表示尚未同步，此时只接受看起来像游戏包开头的分段。
+/// 序列号早于 `next_seq` 的重复分段会被丢弃，部分重叠的重传分段会被裁剪。
+fn reassemble_segment(
+    cache: &mut BTreeMap<u32, Vec<u8>>,
+    next_seq: &mut i64,
+    seq_no: u32,
+    payload: &[u8],
+) -> Vec<u8> {
+    if payload.is_empty() {
+        return Vec::new();
+    }
+
+    if *next_seq == -1 {
+        let looks_like_start = payload.len() > 4 && plausible_packet_size(payload, max_packet_size());
+        if !looks_like_start {
+            log::debug!("⏭️ 等待数据流起始包，跳过分段 - 序列号: {}", seq_no);
+            return Vec::new();
+        }
+        *next_seq = seq_no as i64;
+    }
+
+    let mut expected = *next_seq as u32;
 
-            log::info!("✅ 服务器识别完成，开始跟踪该连接的数据包");
+    // 丢弃重复分段，裁剪部分重叠的重传分段
+    let offset = seq_diff(seq_no, expected);
+    let (seq_no, payload) = if offset < 0 {
+        let overlap = offset.unsigned_abs() as usize;
+        if overlap >= payload.len() {
+            log::debug!("🔁 丢弃重复分段 - 序列号: {}, 期望: {}", seq_no, expected);
+            return Vec::new();
         }
+        (expected, &payload[overlap..])
     } else {
-        log::debug!("🔍 登录返回包识别完成 - 签名不匹配");
+        (seq_no, payload)
+    };
+
+    // 同一序列号保留较长的分段
+    let replace = cache.get(&seq_no).is_none_or(|cached| cached.len() < payload.len());
+    if replace {
+        cache.insert(seq_no, payload.to_vec());
     }
 
-    Ok(())
-}
+    let mut contiguous = Vec::new();
+    while let Some(segment) = cache.remove(&expected) {
+        expected = expected.wrapping_add(segment.len() as u32);
+        contiguous.extend_from_slice(&segment);
+    }
 
-// 清空TCP缓存
-async fn clear_tcp_cache() {
-    let mut cache = TCP_CACHE.lock().await;
-    cache.clear();
+    // 清理已被覆盖的旧分段，部分重叠的分段裁剪后放回
+    if !contiguous.is_empty() {
+        let stale: Vec<u32> = cache
+            .keys()
+            .copied()
+            .filter(|&seq| seq_diff(seq, expected) < 0)
+            .collect();
+        for seq in stale {
+            if let Some(segment) = cache.remove(&seq) {
+                let overlap = seq_diff(expected, seq) as usize;
+                if overlap < segment.len() {
+                    cache.insert(expected, segment[overlap..].to_vec());
+                }
+            }
+        }
+        // 裁剪放回的分段可能正好接上
+        while let Some(segment) = cache.remove(&expected) {
+            expected = expected.wrapping_add(segment.len() as u32);
+            contiguous.extend_from_slice(&segment);
+        }
+    }
+
+    *next_seq = expected as i64;
+    contiguous
 }
 
 // 服务器变更时清空数据
@@ -275,6 +839,7 @@ async fn process_data_buffer(
     data_buffer: &mut Vec<u8>,
     tx: &Sender<(u16, Vec<u8>)>,
 ) -> Result<usize> {
+    let max_packet_size = max_packet_size();
     log::debug!("🔄 进入数据缓冲区处理函数 - 缓冲区大小: {} bytes", data_buffer.len());
     let mut processed_count = 0;
 
@@ -286,19 +851,14 @@ async fn process_data_buffer(
             data_buffer[3],
         ]) as usize;
 
-        // 验证包长度是否合理（避免解析错误导致的巨大值）
-        if packet_size > 10 * 1024 * 1024 { // 10MB上限
-            log::warn!("⚠️ 检测到异常大的数据包长度: {} bytes，可能是解析错误", packet_size);
+        // 长度前缀不合理（解析错误或数据损坏）时向后查找下一个合理的包头，保留其后的有效数据
+        if !plausible_packet_size(data_buffer, max_packet_size) {
+            log::warn!("⚠️ 检测到异常的数据包长度: {} bytes，尝试重新同步", packet_size);
+            log::debug!("🔍 前16字节数据: {:02x?}", &data_buffer[..data_buffer.len().min(16)]);
 
-            // 调试：打印前16个字节的内容，帮助分析数据格式
-            if data_buffer.len() >= 16 {
-                log::debug!("🔍 前16字节数据: {:02x?}", &data_buffer[0..16]);
-            } else {
-                log::debug!("🔍 缓冲区数据: {:02x?}", data_buffer);
-            }
-
-            data_buffer.clear();
-            break;
+            let skipped = resync_data_buffer(data_buffer, max_packet_size);
+            log::warn!("⏭️ 丢弃 {} bytes 无法解析的数据，剩余 {} bytes", skipped, data_buffer.len());
+            continue;
         }
 
         log::debug!("🔍 解析数据包长度: {} bytes (缓冲区大小: {} bytes)", packet_size, data_buffer.len());
@@ -312,56 +872,135 @@ async fn process_data_buffer(
             break;
         }
 
-        if data_buffer.len() >= packet_size {
-            let packet = data_buffer[0..packet_size].to_vec();
-            *data_buffer = data_buffer[packet_size..].to_vec();
+        let packet = data_buffer[0..packet_size].to_vec();
+        *data_buffer = data_buffer[packet_size..].to_vec();
 
-            log::debug!("📦 提取完整数据包 - 大小: {} bytes", packet.len());
+        log::debug!("📦 提取完整数据包 - 大小: {} bytes", packet.len());
 
-            // 发送数据包
-            if packet.len() >= 6 {
-                let opcode = u16::from_le_bytes([packet[4], packet[5]]);
-                let data = packet[6..].to_vec();
+        // 发送数据包
+        let opcode = u16::from_le_bytes([packet[4], packet[5]]);
+        let data = packet[6..].to_vec();
 
-                log::debug!("🔍 数据包格式检查通过 - Opcode: 0x{:04x}, 数据大小: {} bytes", opcode, data.len());
+        log::debug!("🔍 数据包格式检查通过 - Opcode: 0x{:04x}, 数据大小: {} bytes", opcode, data.len());
 
-                // 记录服务器通信数据包的完整载荷（过滤掉4字节的小包）
-                if data.len() > 4 {
-                    log::info!(
-                        "📤 [服务器通信] Opcode: 0x{:04x} | 载荷大小: {} bytes",
-                        opcode,
-                        data.len()
-                    );
-                    if !data.is_empty() {
-                        let hex_dump = format_hex_dump(&data);
-                        log::info!("📦 载荷数据:\n{}", hex_dump);
-                    }
-                }
+        // 记录服务器通信数据包的完整载荷（过滤掉4字节的小包）
+        if data.len() > 4 {
+            log::info!(
+                "📤 [服务器通信] Opcode: 0x{:04x} | 载荷大小: {} bytes",
+                opcode,
+                data.len()
+            );
+            if !data.is_empty() {
+                let hex_dump = format_hex_dump(&data);
+                log::info!("📦 载荷数据:\n{}", hex_dump);
+            }
+        }
 
-                log::debug!(
-                    "📤 发送数据包 - Opcode: 0x{:04x}, 大小: {} bytes",
-                    opcode,
-                    data.len()
-                );
+        log::debug!(
+            "📤 发送数据包 - Opcode: 0x{:04x}, 大小: {} bytes",
+            opcode,
+            data.len()
+        );
 
-                if let Err(e) = tx.send((opcode, data)).await {
-                    log::error!("发送数据包失败: {:?}", e);
-                } else {
-                    processed_count += 1;
-                }
-            } else {
-                log::debug!("⚠️ 跳过数据包 - 大小不足: {} bytes (需要至少6字节)", packet.len());
-            }
-        } else if packet_size > 0x0fffff {
-            log::warn!("⚠️ 检测到无效数据包长度: {} bytes，清空缓冲区", packet_size);
-            data_buffer.clear();
-            break;
+        record_debug_packet(opcode, &data);
+        if let Err(e) = tx.send((opcode, data)).await {
+            log::error!("发送数据包失败: {:?}", e);
+        } else {
+            processed_count += 1;
+            PROCESSED_PACKETS.fetch_add(1, Ordering::Relaxed);
+            BYTES_PROCESSED.fetch_add(packet_size as u64, Ordering::Relaxed);
         }
     }
 
     Ok(processed_count)
 }
 
+/// 还原 `process_data_buffer` 拆出的完整帧（长度前缀 + 原始类型字段 + 载荷），供解析器使用
+fn rebuild_frame(opcode: u16, data: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(data.len() + 6);
+    frame.extend_from_slice(&((data.len() + 6) as u32).to_be_bytes());
+    frame.extend_from_slice(&opcode.to_le_bytes());
+    frame.extend_from_slice(data);
+    frame
+}
+
+/// 调试用的原始游戏包
+#[derive(Debug, Clone)]
+pub struct DebugPacket {
+    /// 捕获时的Unix时间戳（毫秒）
+    pub captured_at: u64,
+    pub opcode: u16,
+    pub payload: Vec<u8>,
+}
+
+/// 设置调试包缓冲区的容量，0表示不记录并清空已有数据；超过上限时按上限处理
+pub fn set_debug_packet_capacity(capacity: usize) {
+    let capacity = capacity.min(crate::config::MAX_DEBUG_PACKET_CAPACITY);
+    DEBUG_PACKET_CAPACITY.store(capacity, Ordering::Relaxed);
+    let mut packets = DEBUG_PACKETS.lock();
+    while packets.len() > capacity {
+        packets.pop_front();
+    }
+}
+
+fn record_debug_packet(opcode: u16, payload: &[u8]) {
+    let capacity = DEBUG_PACKET_CAPACITY.load(Ordering::Relaxed);
+    if capacity == 0 {
+        return;
+    }
+
+    let captured_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    let mut packets = DEBUG_PACKETS.lock();
+    while packets.len() >= capacity {
+        packets.pop_front();
+    }
+    packets.push_back(DebugPacket {
+        captured_at,
+        opcode,
+        payload: payload.to_vec(),
+    });
+}
+
+/// 最近 `limit` 个游戏包（从旧到新）；未启用调试包记录时返回 `None`
+pub fn recent_debug_packets(limit: usize) -> Option<Vec<DebugPacket>> {
+    if DEBUG_PACKET_CAPACITY.load(Ordering::Relaxed) == 0 {
+        return None;
+    }
+    let packets = DEBUG_PACKETS.lock();
+    Some(packets.iter().skip(packets.len().saturating_sub(limit)).cloned().collect())
+}
+
+// 数据包头部：4字节长度 + 2字节类型
+const PACKET_HEADER_LEN: usize = 6;
+
+fn max_packet_size() -> usize {
+    MAX_PACKET_SIZE.load(Ordering::Relaxed) as usize
+}
+
+/// 缓冲区开头的长度前缀是否像一个真实的游戏包（至少包含包头，且不超过上限）
+fn plausible_packet_size(buf: &[u8], max_packet_size: usize) -> bool {
+    if buf.len() < 4 {
+        return false;
+    }
+    let size = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+    (PACKET_HEADER_LEN..=max_packet_size).contains(&size)
+}
+
+/// 丢弃开头的损坏数据，直到下一个合理的长度前缀；返回丢弃的字节数
+///
+/// 找不到时只保留末尾3个字节，它们可能是下一个长度前缀的开头
+fn resync_data_buffer(data_buffer: &mut Vec<u8>, max_packet_size: usize) -> usize {
+    let skip = (1..data_buffer.len())
+        .find(|&start| plausible_packet_size(&data_buffer[start..], max_packet_size))
+        .unwrap_or_else(|| data_buffer.len().saturating_sub(3).max(1));
+    let skip = skip.min(data_buffer.len());
+    data_buffer.drain(..skip);
+    skip
+}
+
 // 格式化字节数组为十六进制字符串
 fn format_hex_dump(data: &[u8]) -> String {
     let mut result = String::new();
@@ -382,144 +1021,420 @@ fn format_hex_dump(data: &[u8]) -> String {
     result.trim_end().to_string()
 }
 
-// 重置服务器识别状态（用于重新开始服务器识别）
-pub async fn reset_server_identification() {
-    let mut server_identified = SERVER_IDENTIFIED.lock().await;
-    *server_identified = false;
+type ConnectionMap = DashMap<ConnectionKey, Arc<Mutex<ServerState>>>;
+
+/// 移除超过 `timeout_secs` 没有数据的连接，返回移除的数量
+///
+/// 正在被处理（锁被占用）的连接视为活跃连接，不会被移除
+fn remove_stale_connections(connections: &ConnectionMap, now: u64, timeout_secs: u64) -> usize {
+    let before = connections.len();
+    connections.retain(|key, connection| {
+        let Ok(state) = connection.try_lock() else {
+            return true;
+        };
+        let active = now.saturating_sub(state.last_seen) <= timeout_secs;
+        if !active {
+            log::info!("🧹 移除超时的服务器连接: {}", key);
+            adjust_tcp_cache_size(state.tcp_cache.len(), 0);
+        }
+        active
+    });
+    before.saturating_sub(connections.len())
+}
 
-    let mut current_server = CURRENT_SERVER.lock().await;
-    *current_server = String::new();
+/// 清理超时的服务器连接（由 `connection_timeout` 配置）
+pub async fn cleanup_stale_connections() -> usize {
+    remove_stale_connections(
+        &SERVER_CONNECTIONS,
+        unix_now_secs(),
+        CONNECTION_TIMEOUT_SECS.load(Ordering::Relaxed),
+    )
+}
 
-    // 重置不匹配计数器
-    let mut mismatched_packets = MISMATCHED_PACKETS.lock().await;
-    *mismatched_packets = 0;
+/// 清理超过 `FRAGMENT_TIMEOUT` 仍未到齐的IP分片
+pub async fn cleanup_stale_fragments() -> usize {
+    let mut cache = FRAGMENT_CACHE.lock().await;
+    let before = cache.len();
+    cache.retain(|_, buffer| buffer.first_seen.elapsed() < FRAGMENT_TIMEOUT);
+    let removed = before - cache.len();
+    if removed > 0 {
+        log::debug!("清理了{}个超时的IP分片", removed);
+    }
+    removed
+}
 
-    clear_tcp_cache().await;
+/// 按单个连接缓存段数的变化更新全局统计，避免为了统计去锁住所有连接
+fn adjust_tcp_cache_size(before: usize, after: usize) {
+    let _ = TCP_CACHE_SIZE.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |size| {
+        Some((size + after as u64).saturating_sub(before as u64))
+    });
+}
+
+/// 当前已识别的服务器连接，未识别时为空字符串
+pub async fn current_server() -> String {
+    let mut servers: Vec<String> = SERVER_CONNECTIONS.iter().map(|entry| entry.key().to_string()).collect();
+    servers.sort();
+    servers.join(", ")
+}
 
-    let mut tcp_next_seq = TCP_NEXT_SEQ.lock().await;
-    *tcp_next_seq = -1;
+// 重置服务器识别状态（用于重新开始服务器识别）
+pub async fn reset_server_identification() {
+    let _identify = IDENTIFY_LOCK.lock().await;
+    SERVER_CONNECTIONS.clear();
+    TCP_CACHE_SIZE.store(0, Ordering::Relaxed);
 
     clear_data_on_server_change();
 
     log::info!("🔄 服务器识别状态已重置，可以重新开始识别游戏服务器");
 }
 
-// 获取捕获统计信息
-#[derive(Debug, Clone)]
-pub struct CaptureStats {
+/// 只由原子计数器组成的捕获统计，读取时不加任何锁
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct CaptureCounters {
     pub packets_captured: u64,
     pub packets_processed: u64,
     pub packets_dropped: u64,
+    pub tcp_cache_size: u64,
     pub uptime_seconds: u64,
+    /// 最后一次捕获到数据包的Unix时间戳（秒），0表示尚未捕获
+    pub last_packet_at: u64,
+    /// 捕获到的IP数据包总字节数
+    pub bytes_captured: u64,
+    /// 转发给解析器的游戏数据包总字节数
+    pub bytes_processed: u64,
+}
+
+// 获取捕获统计信息
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CaptureStats {
+    #[serde(flatten)]
+    pub counters: CaptureCounters,
+    /// 最近 `RATE_WINDOW` 内的捕获速率
+    pub packets_per_sec: f64,
+    pub bytes_per_sec: f64,
+}
+
+// 计算速率所用的滑动窗口
+const RATE_WINDOW: Duration = Duration::from_secs(5);
+// 相邻采样的最小间隔
+const RATE_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy)]
+struct RateSample {
+    at: Instant,
+    packets: u64,
+    bytes: u64,
+}
+
+/// 根据历史采样计算速率（包/秒，字节/秒），并记录当前采样
+///
+/// 以窗口内最早的采样为基准；读取间隔超过窗口时退化为两次读取之间的平均速率
+fn rolling_rate(samples: &mut VecDeque<RateSample>, current: RateSample) -> (f64, f64) {
+    // 保留一个不晚于窗口起点的采样作为基准
+    while samples.len() >= 2 && current.at.duration_since(samples[1].at) >= RATE_WINDOW {
+        samples.pop_front();
+    }
+
+    let rates = match samples.front() {
+        Some(base) if current.at > base.at => {
+            let elapsed = current.at.duration_since(base.at).as_secs_f64();
+            (
+                current.packets.saturating_sub(base.packets) as f64 / elapsed,
+                current.bytes.saturating_sub(base.bytes) as f64 / elapsed,
+            )
+        }
+        _ => (0.0, 0.0),
+    };
+
+    if samples
+        .back()
+        .is_none_or(|last| current.at.duration_since(last.at) >= RATE_SAMPLE_INTERVAL)
+    {
+        samples.push_back(current);
+    }
+
+    rates
 }
 
+/// 读取捕获计数器快照，可在热路径之外随时廉价调用（健康检查等）
+pub fn snapshot_counters() -> CaptureCounters {
+    let started_at = CAPTURE_STARTED_AT.load(Ordering::Relaxed);
+    let uptime_seconds = if started_at == 0 {
+        0
+    } else {
+        unix_now_secs().saturating_sub(started_at)
+    };
+
+    CaptureCounters {
+        packets_captured: PACKET_COUNTER.load(Ordering::Relaxed),
+        packets_processed: PROCESSED_PACKETS.load(Ordering::Relaxed),
+        packets_dropped: FILTERED_PACKETS.load(Ordering::Relaxed),
+        tcp_cache_size: TCP_CACHE_SIZE.load(Ordering::Relaxed),
+        uptime_seconds,
+        last_packet_at: LAST_PACKET_AT.load(Ordering::Relaxed),
+        bytes_captured: BYTES_CAPTURED.load(Ordering::Relaxed),
+        bytes_processed: BYTES_PROCESSED.load(Ordering::Relaxed),
+    }
+}
+
+/// 计数器快照加上捕获速率；速率需要更新采样窗口
 pub fn get_capture_stats() -> CaptureStats {
-    // TODO: 实现实际的统计跟踪
+    let counters = snapshot_counters();
+    let (packets_per_sec, bytes_per_sec) = rolling_rate(
+        &mut RATE_SAMPLES.lock(),
+        RateSample {
+            at: Instant::now(),
+            packets: counters.packets_captured,
+            bytes: counters.bytes_captured,
+        },
+    );
+
     CaptureStats {
-        packets_captured: 0,
-        packets_processed: 0,
-        packets_dropped: 0,
-        uptime_seconds: 0,
+        counters,
+        packets_per_sec,
+        bytes_per_sec,
     }
 }
 
-/// 数据包捕获配置
-#[derive(Debug, Clone)]
-pub struct PacketCaptureConfig {
-    pub filter: String,
+pub(crate) fn unix_now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
 }
 
-/// 捕获的数据包数据
-#[derive(Debug, Clone)]
-pub struct PacketData {
-    pub opcode: u16,
-    pub data: Vec<u8>,
-    pub timestamp: std::time::SystemTime,
+/// 检查过滤器是否明显无效（WinDivert会在创建句柄时做完整校验）
+pub fn validate_filter(filter: &str) -> std::result::Result<(), String> {
+    const MAX_FILTER_LEN: usize = 1024;
+
+    let filter = filter.trim();
+    if filter.is_empty() {
+        return Err("过滤器不能为空".to_string());
+    }
+    if filter.len() > MAX_FILTER_LEN {
+        return Err(format!("过滤器过长（最多{}个字符）", MAX_FILTER_LEN));
+    }
+    if !filter.chars().all(|c| c.is_ascii_graphic() || c == ' ') {
+        return Err("过滤器包含非法字符".to_string());
+    }
+
+    let mut depth: i32 = 0;
+    for c in filter.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth < 0 {
+                    return Err("括号不匹配".to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return Err("括号不匹配".to_string());
+    }
+
+    Ok(())
+}
+
+// 网卡限定条件的后缀
+const INTERFACE_CLAUSE: &str = ") and ifIdx == ";
+
+/// 解析过滤器中限定的网卡索引（由 `filter_for_interface` 生成的形式）
+pub fn interface_from_filter(filter: &str) -> Option<u32> {
+    let (_, index) = filter.rsplit_once(INTERFACE_CLAUSE)?;
+    if filter.starts_with('(') {
+        index.trim().parse().ok()
+    } else {
+        None
+    }
+}
+
+/// 在过滤器上限定捕获网卡（替换已有的网卡条件），`None` 表示捕获所有网卡
+pub fn filter_for_interface(filter: &str, if_idx: Option<u32>) -> String {
+    let base = match interface_from_filter(filter) {
+        Some(_) => {
+            let (base, _) = filter.rsplit_once(INTERFACE_CLAUSE).unwrap_or((filter, ""));
+            &base[1..]
+        }
+        None => filter,
+    };
+
+    match if_idx {
+        Some(index) => format!("({}{}{}", base, INTERFACE_CLAUSE, index),
+        None => base.to_string(),
+    }
 }
 
+/// 游戏包接收端，以及在捕获循环退出并关闭句柄后完成的任务句柄
+pub type CaptureChannel = (Receiver<(u16, Vec<u8>)>, task::JoinHandle<()>);
+
 /// 在所有TCP端口启动数据包捕获
 pub fn start_capture(filter: String) -> Result<Receiver<(u16, Vec<u8>)>> {
+    let (_filter_tx, filter_rx) = watch::channel(filter);
+    start_capture_with_updates(filter_rx, Arc::new(AtomicBool::new(false)), None).map(|(rx, _)| rx)
+}
+
+/// 启动数据包捕获，过滤器变化时自动重建捕获句柄
+pub fn start_capture_with_updates(
+    mut filter_rx: watch::Receiver<String>,
+    shutdown: Arc<AtomicBool>,
+    record_pcap: Option<PathBuf>,
+) -> Result<CaptureChannel> {
     let (tx, rx) = async_channel::unbounded();
 
-    log::info!("使用WinDivert过滤器: {}", filter);
+    log::info!("使用捕获过滤器: {}", *filter_rx.borrow());
+
+    // 录制文件在启动时创建，失败时直接报错
+    let recorder = match record_pcap {
+        Some(path) => {
+            let writer = crate::pcap::PcapWriter::create(&path)?;
+            log::info!("录制捕获数据包到: {:?}", path);
+            Some(writer)
+        }
+        None => None,
+    };
 
     // 启动捕获任务
-    task::spawn(async move {
-        if let Err(e) = run_capture(filter, tx).await {
+    let capture_task = task::spawn(async move {
+        let filter = filter_rx.borrow_and_update().clone();
+        let backend = match crate::capture_backend::open_live_backend(&filter) {
+            Ok(backend) => backend,
+            Err(e) => {
+                log::error!("数据包捕获失败: {:?}", e);
+                return;
+            }
+        };
+
+        if let Err(e) = run_capture(backend, Some(filter_rx), shutdown, recorder, tx).await {
             log::error!("数据包捕获失败: {:?}", e);
         }
     });
 
-    Ok(rx)
+    Ok((rx, capture_task))
 }
 
-/// 内部捕获函数，具有完整的WinDivert实现
-async fn run_capture(filter: String, tx: Sender<(u16, Vec<u8>)>) -> Result<()> {
-    // 检查WinDivert是否可用
-    if !crate::utils::is_windivert_installed() {
-        return Err(MeterError::WinDivertError(
-            "未找到WinDivert驱动。请确保WinDivert64.sys已安装到应用程序目录。".to_string(),
-        ));
-    }
+/// 从pcap文件回放数据包（不需要WinDivert，可在任意平台运行）
+pub fn start_replay(
+    path: &std::path::Path,
+    realtime: bool,
+    shutdown: Arc<AtomicBool>,
+) -> Result<CaptureChannel> {
+    let backend = ReplayBackend::open(path, realtime)?;
+    let (tx, rx) = async_channel::unbounded();
 
-    // 检查管理员权限
-    if !crate::utils::is_admin() {
-        log::warn!("WinDivert需要管理员权限，但当前进程没有管理员权限");
-        return Err(MeterError::WinDivertError(
-            "WinDivert需要管理员权限。请以管理员身份运行应用程序。".to_string(),
-        ));
-    }
+    let replay_task = task::spawn(async move {
+        if let Err(e) = run_capture(Box::new(backend), None, shutdown, None, tx).await {
+            log::error!("pcap回放失败: {:?}", e);
+        }
+    });
 
-    log::info!("开始捕获所有TCP端口的数据包");
+    Ok((rx, replay_task))
+}
 
-    // 创建网络层的WinDivert句柄
-    let handle = WinDivert::<NetworkLayer>::network(&filter, 0, WinDivertFlags::new())
-        .map_err(|e| MeterError::WinDivertError(format!("创建WinDivert句柄失败: {}", e)))?;
+/// 内部捕获循环，与具体的抓包后端无关
+async fn run_capture(
+    mut backend: Box<dyn CaptureBackend>,
+    mut filter_rx: Option<watch::Receiver<String>>,
+    shutdown: Arc<AtomicBool>,
+    mut recorder: Option<crate::pcap::PcapWriter>,
+    tx: Sender<(u16, Vec<u8>)>,
+) -> Result<()> {
+    log::info!("开始捕获所有TCP端口的数据包");
+    CAPTURE_STARTED_AT.store(unix_now_secs(), Ordering::Relaxed);
+
+    while !shutdown.load(Ordering::SeqCst) {
+        // 过滤器已更新，使用新过滤器重建句柄（失败时保留旧句柄）
+        if let Some(filter_rx) = filter_rx.as_mut() {
+            if filter_rx.has_changed().unwrap_or(false) {
+                let new_filter = filter_rx.borrow_and_update().clone();
+                match backend.set_filter(&new_filter) {
+                    Ok(()) => log::info!("捕获句柄已使用新过滤器重建: {}", new_filter),
+                    Err(e) => log::error!("使用新过滤器重建捕获句柄失败，继续使用旧过滤器: {}", e),
+                }
+            }
+        }
 
-    log::info!("WinDivert句柄创建成功，过滤器: {}", filter);
+        // 接收数据包
+        match backend.recv() {
+            Ok(Some(packet_data)) => {
+                // 按原始间隔回放时分段等待，期间仍能及时响应停止
+                if let Some(delay) = backend.take_pacing_delay() {
+                    let deadline = tokio::time::Instant::now() + delay;
+                    while !shutdown.load(Ordering::SeqCst) && tokio::time::Instant::now() < deadline {
+                        tokio::time::sleep_until(deadline.min(tokio::time::Instant::now() + PACING_STEP)).await;
+                    }
+                    if shutdown.load(Ordering::SeqCst) {
+                        break;
+                    }
+                }
 
-    loop {
-        let mut buffer = vec![0u8; BUF_SIZE]; // 10MB缓冲区，用于容纳大型网络数据包
+                // 录制原始数据包（已通过捕获过滤器）
+                if let Some(writer) = recorder.as_mut() {
+                    if let Err(e) = writer.write_packet(&packet_data) {
+                        log::warn!("写入pcap录制文件失败，停止录制: {:?}", e);
+                        recorder = None;
+                    }
+                }
 
-        // 接收数据包
-        match handle.recv(Some(&mut buffer[..])) {
-            Ok(packet) => {
                 // 处理捕获的数据包
-                if let Err(e) = process_packet(&packet.data, &tx).await {
+                if let Err(e) = process_packet(&packet_data, &tx).await {
                     log::warn!("处理数据包失败: {:?}", e);
                 }
 
-                // 将数据包重新注入网络栈
-                if let Err(e) = handle.send(&packet) {
+                // 将数据包重新注入网络栈（只有WinDivert需要）
+                if let Err(e) = backend.send(&packet_data) {
                     log::warn!("重新注入数据包失败: {:?}", e);
                 }
             }
+            Ok(None) => {
+                // 超时或数据源已结束
+                if backend.is_finished() {
+                    break;
+                }
+            }
             Err(e) => {
                 log::error!("接收数据包失败: {:?}", e);
                 // 小延迟以防止错误时忙等待
                 tokio::time::sleep(Duration::from_millis(100)).await;
             }
         }
+    }
 
-        // 检查是否应该停止（生产环境中会通过关闭信号控制）
-        // 现在将无限运行直到任务被取消
+    // 收到停止信号或数据源结束，关闭录制文件和捕获句柄
+    if let Some(writer) = recorder {
+        if let Err(e) = writer.finish() {
+            log::warn!("关闭pcap录制文件失败: {:?}", e);
+        }
     }
+    backend.close();
+    log::info!("数据包捕获已停止，捕获句柄已关闭");
+
+    Ok(())
 }
 
 /// 处理捕获的数据包并提取相关数据
 async fn process_packet(packet_data: &[u8], tx: &Sender<(u16, Vec<u8>)>) -> Result<()> {
     // 数据包计数器
     let packet_count = PACKET_COUNTER.fetch_add(1, Ordering::SeqCst);
+    BYTES_CAPTURED.fetch_add(packet_data.len() as u64, Ordering::Relaxed);
+    LAST_PACKET_AT.store(unix_now_secs(), Ordering::Relaxed);
 
     // WinDivert NetworkLayer 返回的是IP数据包，直接解析IP头部
-    // 不需要解析以太网头部
-    let ip_data = packet_data;
+    // 不需要解析以太网头部；分片的数据包等到齐后再处理
+    let fragment_limits = *FRAGMENT_LIMITS.read();
+    let ip_data = match handle_ip_fragmentation(&mut *FRAGMENT_CACHE.lock().await, &fragment_limits, packet_data) {
+        Some(ip_data) => ip_data,
+        None => return Ok(()),
+    };
+    let ip_data = ip_data.as_ref();
 
     // 解析IP头部
     let (tcp_data, src_ip, dst_ip, _, _) = match parse_ip_header(ip_data) {
         Ok(result) => {
             // 排除本地回环地址的数据包
-            if result.2 == "127.0.0.1" {
+            if result.2 == "127.0.0.1" || result.2 == "[::1]" {
                 return Ok(());
             }
 
@@ -543,12 +1458,12 @@ async fn process_packet(packet_data: &[u8], tx: &Sender<(u16, Vec<u8>)>) -> Resu
     };
 
     // 解析TCP头部
-    let (payload, src_port, dst_port, seq_no) = match parse_tcp_header(tcp_data) {
+    let (tcp, payload) = match TcpPacketInfo::parse(tcp_data) {
         Ok(result) => result,
         Err(e) => {
             let filtered_count = FILTERED_PACKETS.fetch_add(1, Ordering::SeqCst);
             // log::debug!(
-            //     "❌ 跳过无payload数据包 #{}: {} (总过滤: {})",
+            //     "❌ 跳过无效TCP数据包 #{}: {} (总过滤: {})",
             //     packet_count,
             //     e,
             //     filtered_count
@@ -556,182 +1471,606 @@ async fn process_packet(packet_data: &[u8], tx: &Sender<(u16, Vec<u8>)>) -> Resu
             return Ok(());
         }
     };
+    let seq_no = tcp.seq_no;
+    let closing = tcp.has_flag(TCP_FIN) || tcp.has_flag(TCP_RST);
+
+    let key = ConnectionKey {
+        src_ip,
+        src_port: tcp.src_port,
+        dst_ip,
+        dst_port: tcp.dst_port,
+    };
 
-    let src_server = format!("{}:{} -> {}:{}", src_ip, src_port, dst_ip, dst_port);
-
-    // 获取TCP锁
-    let _lock = TCP_LOCK.lock().await;
-
-    // 检查服务器是否已经识别
-    let server_identified = SERVER_IDENTIFIED.lock().await.clone();
-    // log::debug!("🔍 服务器识别状态: {}", server_identified);
-
-    let mut current_server = CURRENT_SERVER.lock().await;
-    if *current_server != src_server {
-        if !server_identified {
-            // 服务器未识别，记录数据包并尝试识别
+    // 只在DashMap分片锁内克隆连接句柄，不跨越await持有分片锁
+    let existing = SERVER_CONNECTIONS.get(&key).map(|entry| Arc::clone(entry.value()));
+    let (connection, known) = match existing {
+        Some(connection) => {
+            // 是已识别的服务器，记录数据包
             log::debug!(
-                "📦 #{}: {}:{} -> {}:{} | 序列号: {} | Payload: {} bytes",
+                "📦 #{}: {} | 序列号: {} | Payload: {} bytes",
                 packet_count,
-                src_ip,
-                src_port,
-                dst_ip,
-                dst_port,
+                key,
                 seq_no,
                 payload.len()
             );
-
-            // 尝试识别
-            drop(current_server); // 释放锁
-
-            if let Err(e) = try_identify_server_by_small_packet(payload, &src_server).await {
-                log::warn!("小包识别失败: {:?}", e);
+            (connection, true)
+        }
+        None => {
+            // 已识别连接的客户端->服务器方向，序列号空间是独立的，不参与重组
+            let reverse_key = key.reversed();
+            let reverse = SERVER_CONNECTIONS.get(&reverse_key).map(|entry| Arc::clone(entry.value()));
+            if let Some(reverse) = reverse {
+                let mut state = reverse.lock().await;
+                state.last_seen = unix_now_secs();
+                if closing && SERVER_CONNECTIONS.remove(&reverse_key).is_some() {
+                    log::info!("🔌 客户端关闭了服务器连接: {}", reverse_key);
+                    adjust_tcp_cache_size(state.tcp_cache.len(), 0);
+                }
+                return Ok(());
             }
 
-            if let Err(e) = try_identify_server_by_login_response(payload, &src_server).await {
-                log::warn!("登录返回包识别失败: {:?}", e);
+            if payload.is_empty() {
+                FILTERED_PACKETS.fetch_add(1, Ordering::SeqCst);
+                return Ok(());
             }
 
-            // 尝试模拟服务器识别（用于调试）
-            // if let Err(e) = try_simulate_server_identification(&src_server).await {
-            //     log::warn!("模拟识别失败: {:?}", e);
-            // }
+            // 未识别的连接，记录数据包并尝试识别
+            log::debug!(
+                "📦 #{}: {} | 序列号: {} | Payload: {} bytes",
+                packet_count,
+                key,
+                seq_no,
+                payload.len()
+            );
 
-            // 重新获取锁
-            let current_server = CURRENT_SERVER.lock().await;
-            if *current_server != src_server {
+            let src_server = key.to_string();
+            let identified = try_identify_server_by_small_packet(payload, &src_server)
+                || try_identify_server_by_login_response(payload, &src_server);
+            if !identified {
                 // 识别失败，跳过该数据包
-                let filtered_count = FILTERED_PACKETS.fetch_add(1, Ordering::SeqCst);
-                // log::debug!(
-                //     "❌ 跳过未识别服务器数据包 #{}: {} (总过滤: {})",
-                //     packet_count,
-                //     src_server,
-                //     filtered_count
-                // );
-                drop(current_server);
-                drop(_lock);
+                FILTERED_PACKETS.fetch_add(1, Ordering::SeqCst);
                 return Ok(());
             }
-        } else {
-            // 服务器已识别，检查是否是已识别的服务器（双向匹配）
-            let reverse_server = format!("{}:{} -> {}:{}", dst_ip, dst_port, src_ip, src_port);
-            if *current_server != src_server && *current_server != reverse_server {
-                // 不是已识别的服务器，增加不匹配计数器
-                let mut mismatched_packets = MISMATCHED_PACKETS.lock().await;
-                *mismatched_packets += 1;
-
-                log::debug!(
-                    "⚠️ 检测到非目标服务器数据包 #{}: {} (当前服务器: {}, 不匹配计数: {})",
-                    packet_count,
-                    src_server,
-                    *current_server,
-                    *mismatched_packets
-                );
-
-                // 如果连续不匹配数据包数量超过阈值，触发服务器切换
-                const SWITCH_THRESHOLD: u32 = 5;
-                if *mismatched_packets >= SWITCH_THRESHOLD {
-                    log::warn!("🔄 检测到服务器切换！连续{}个数据包来自不同服务器", SWITCH_THRESHOLD);
-                    log::warn!("🔄 当前服务器: {}", *current_server);
-                    log::warn!("🔄 新服务器地址: {}", src_server);
-
-                    // 重置服务器识别状态
-                    drop(current_server); // 释放锁
-                    drop(mismatched_packets); // 释放锁
-
-                    reset_server_identification().await;
-
-                    log::info!("🔄 服务器切换处理完成，等待新数据包重新识别");
-
-                    drop(_lock);
-                    return Ok(());
-                } else {
-                    drop(current_server);
-                    drop(mismatched_packets);
-                    drop(_lock);
-                    return Ok(());
-                }
-            } else {
-                // 是已识别的服务器，重置不匹配计数器
-                let mut mismatched_packets = MISMATCHED_PACKETS.lock().await;
-                if *mismatched_packets > 0 {
-                    log::debug!("✅ 服务器匹配，重置不匹配计数器 (之前: {})", *mismatched_packets);
-                    *mismatched_packets = 0;
-                }
 
-                // 记录数据包
-                log::debug!(
-                    "📦 #{}: {}:{} -> {}:{} | 序列号: {} | Payload: {} bytes",
-                    packet_count,
-                    src_ip,
-                    src_port,
-                    dst_ip,
-                    dst_port,
-                    seq_no,
-                    payload.len()
-                );
+            let _identify = IDENTIFY_LOCK.lock().await;
+            // 移除长时间没有数据的旧连接（例如切换地图后断开的服务器）
+            remove_stale_connections(&SERVER_CONNECTIONS, unix_now_secs(), CONNECTION_TIMEOUT_SECS.load(Ordering::Relaxed));
+            if SERVER_CONNECTIONS.is_empty() {
+                clear_data_on_server_change();
             }
+
+            let connection = Arc::clone(
+                SERVER_CONNECTIONS
+                    .entry(key.clone())
+                    .or_insert_with(|| Arc::new(Mutex::new(ServerState::new())))
+                    .value(),
+            );
+            log::info!("✅ 服务器识别完成，开始跟踪该连接的数据包（共{}个连接）", SERVER_CONNECTIONS.len());
+            (connection, false)
         }
-    } else {
-        // 是已识别的服务器，记录数据包
+    };
+
+    // 只锁住当前连接，其他连接的数据包可以同时处理
+    let mut guard = connection.lock().await;
+    let state = &mut *guard;
+    let cached_before = state.tcp_cache.len();
+
+    // 已识别的连接重新握手，从SYN之后的序列号重新开始重组
+    if known && tcp.has_flag(TCP_SYN) {
+        log::info!("🔁 服务器连接重新建立: {}", key);
+        *state = ServerState::new();
+        state.next_seq = seq_no.wrapping_add(1) as i64;
+    }
+    state.last_seen = unix_now_secs();
+
+    // 按TCP序列号重组，只把连续的数据写入该连接的数据缓冲区
+    let contiguous = reassemble_segment(&mut state.tcp_cache, &mut state.next_seq, seq_no, payload);
+
+    let mut processed = Ok(0);
+    if !contiguous.is_empty() {
+        let buffer_before = state.data_buffer.len();
+        state.data_buffer.extend_from_slice(&contiguous);
         log::debug!(
-            "📦 #{}: {}:{} -> {}:{} | 序列号: {} | Payload: {} bytes",
-            packet_count,
-            src_ip,
-            src_port,
-            dst_ip,
-            dst_port,
-            seq_no,
-            payload.len()
+            "📊 数据缓冲区更新 - 之前: {} bytes, 之后: {} bytes",
+            buffer_before,
+            state.data_buffer.len()
         );
+
+        processed = process_data_buffer(&mut state.data_buffer, tx).await;
     }
+    state.enforce_limits(&TCP_CACHE_LIMITS.read());
 
-    // 处理识别的服务器数据包 - 简化TCP重组逻辑
-    let mut tcp_cache = TCP_CACHE.lock().await;
+    // 服务器关闭连接，剩余的数据已经处理完
+    let mut cached_after = state.tcp_cache.len();
+    if closing && SERVER_CONNECTIONS.remove(&key).is_some() {
+        log::info!("🔌 服务器关闭了连接: {}", key);
+        cached_after = 0;
+    }
+    adjust_tcp_cache_size(cached_before, cached_after);
 
-    // 对于识别的服务器，简单地将所有数据包加入缓存，不进行严格的序列号检查
-    // 因为双向通信的序列号是独立的
-    tcp_cache.insert(seq_no, payload.to_vec());
+    let processed_packets = processed?;
+    if processed_packets > 0 {
+        log::debug!("📤 已处理并发送 {} 个数据包到通道", processed_packets);
+    }
 
-    // 立即处理缓存中的数据包（简化逻辑）
-    let mut data_buffer = DATA_BUFFER.lock().await;
-    let mut processed_packets = 0;
+    Ok(())
+}
 
-    // 按序列号顺序处理所有缓存的数据包
-    let mut seq_keys: Vec<u32> = tcp_cache.keys().cloned().collect();
-    seq_keys.sort();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    for seq in seq_keys {
-        if let Some(cached_data) = tcp_cache.remove(&seq) {
-            let cached_len = cached_data.len() as u32;
-            log::debug!(
-                "🔄 处理缓存数据包 - 序列号: {}, 大小: {} bytes",
-                seq,
-                cached_len
-            );
+    #[test]
+    fn test_reassemble_out_of_order_and_duplicate_segments() {
+        let mut cache = BTreeMap::new();
+        let mut next_seq = -1;
+
+        // 数据流起始包：长度前缀 0x0000000a
+        let first = [0x00, 0x00, 0x00, 0x0a, 0x01, 0x02];
+        let second = [0x03, 0x04];
+        let third = [0x05, 0x06];
+
+        assert_eq!(reassemble_segment(&mut cache, &mut next_seq, 1000, &first), first);
+
+        // 乱序到达的第三段先缓存
+        assert!(reassemble_segment(&mut cache, &mut next_seq, 1008, &third).is_empty());
+
+        // 重复的第一段被丢弃
+        assert!(reassemble_segment(&mut cache, &mut next_seq, 1000, &first).is_empty());
+
+        // 第二段到达后连续刷新第二、三段
+        assert_eq!(
+            reassemble_segment(&mut cache, &mut next_seq, 1006, &second),
+            [0x03, 0x04, 0x05, 0x06]
+        );
+        assert_eq!(next_seq, 1010);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_reassemble_three_ip_fragments() {
+        let payload: Vec<u8> = (0u8..24).collect();
+
+        // 构造一个标识为0x1234、协议为TCP的IPv4分片
+        let fragment = |offset: usize, more: bool, data: &[u8]| {
+            let mut packet = vec![0u8; 20];
+            packet[0] = 0x45;
+            packet[2..4].copy_from_slice(&((20 + data.len()) as u16).to_be_bytes());
+            packet[4..6].copy_from_slice(&0x1234u16.to_be_bytes());
+            let flags_offset = ((offset / 8) as u16) | if more { 0x2000 } else { 0 };
+            packet[6..8].copy_from_slice(&flags_offset.to_be_bytes());
+            packet[8] = 64;
+            packet[9] = 6;
+            packet[12..16].copy_from_slice(&[10, 0, 0, 1]);
+            packet[16..20].copy_from_slice(&[10, 0, 0, 2]);
+            packet.extend_from_slice(data);
+            packet
+        };
+
+        let first = fragment(0, true, &payload[0..8]);
+        let second = fragment(8, true, &payload[8..16]);
+        let third = fragment(16, false, &payload[16..24]);
+        assert_eq!(fragment_offset(&third), 16);
+
+        // 乱序到达，最后一个分片到达前不返回数据
+        let limits = FragmentLimits::from(&crate::config::PacketCaptureConfig::default());
+        let mut cache = HashMap::new();
+        assert!(handle_ip_fragmentation(&mut cache, &limits, &third).is_none());
+        assert!(handle_ip_fragmentation(&mut cache, &limits, &first).is_none());
+        let packet = handle_ip_fragmentation(&mut cache, &limits, &second).unwrap();
+
+        assert_eq!(&packet[20..], &payload[..]);
+        assert_eq!(u16::from_be_bytes([packet[2], packet[3]]), 44);
+        assert_eq!(packet[6] & 0x3F, 0);
+        assert_eq!(packet[7], 0);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_ip_fragment_cache_limits() {
+        // 构造标识为 `id` 的IPv4分片（8字节载荷）
+        let fragment = |id: u16, offset: usize| {
+            let mut packet = vec![0u8; 28];
+            packet[0] = 0x45;
+            packet[2..4].copy_from_slice(&28u16.to_be_bytes());
+            packet[4..6].copy_from_slice(&id.to_be_bytes());
+            packet[6..8].copy_from_slice(&(((offset / 8) as u16) | 0x2000).to_be_bytes());
+            packet[9] = 6;
+            packet
+        };
+        let limits = FragmentLimits { max_entries: 2, max_fragments_per_packet: 3 };
+        let mut cache = HashMap::new();
+
+        // 缓存满后丢弃最早的数据包
+        for id in 1..=3 {
+            assert!(handle_ip_fragmentation(&mut cache, &limits, &fragment(id, 0)).is_none());
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        let mut ids: Vec<u16> = cache.keys().map(|key| key.3).collect();
+        ids.sort();
+        assert_eq!(ids, vec![2, 3]);
+
+        // 单个数据包分片数超过上限时整个丢弃
+        for offset in [8, 16] {
+            assert!(handle_ip_fragmentation(&mut cache, &limits, &fragment(3, offset)).is_none());
+        }
+        assert_eq!(cache.len(), 2);
+        assert!(handle_ip_fragmentation(&mut cache, &limits, &fragment(3, 24)).is_none());
+        assert_eq!(cache.keys().map(|key| key.3).collect::<Vec<_>>(), vec![2]);
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_length_resyncs_to_next_packet() {
+        let (tx, rx) = async_channel::unbounded();
+        let packet = [0x00, 0x00, 0x00, 0x08, 0x02, 0x00, 0xaa, 0xbb];
+
+        // 异常巨大的长度和长度为0的包头都会被跳过，之后的有效包照常解析
+        for corrupt in [[0xff, 0xff, 0xff, 0xff], [0x00, 0x00, 0x00, 0x00]] {
+            let mut buffer = corrupt.to_vec();
+            buffer.extend_from_slice(&packet);
+            buffer.extend_from_slice(&packet[..5]);
+
+            assert_eq!(process_data_buffer(&mut buffer, &tx).await.unwrap(), 1);
+            assert_eq!(rx.recv().await.unwrap(), (0x0002, vec![0xaa, 0xbb]));
+            // 不完整的下一个包保留在缓冲区中
+            assert_eq!(buffer, packet[..5]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rebuild_frame_restores_captured_packet() {
+        let (tx, rx) = async_channel::unbounded();
+        let packet = [0x00, 0x00, 0x00, 0x09, 0x80, 0x02, 0xaa, 0xbb, 0xcc];
+        let mut buffer = packet.to_vec();
+
+        assert_eq!(process_data_buffer(&mut buffer, &tx).await.unwrap(), 1);
+        let (opcode, data) = rx.recv().await.unwrap();
+        assert_eq!(rebuild_frame(opcode, &data), packet);
+    }
+
+    // 构造一个IPv4 TCP数据包
+    fn ip_packet(src: ([u8; 4], u16), dst: ([u8; 4], u16), seq: u32, flags: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0u8; 40];
+        packet[0] = 0x45;
+        packet[2..4].copy_from_slice(&((40 + payload.len()) as u16).to_be_bytes());
+        packet[8] = 64;
+        packet[9] = 6;
+        packet[12..16].copy_from_slice(&src.0);
+        packet[16..20].copy_from_slice(&dst.0);
+        packet[20..22].copy_from_slice(&src.1.to_be_bytes());
+        packet[22..24].copy_from_slice(&dst.1.to_be_bytes());
+        packet[24..28].copy_from_slice(&seq.to_be_bytes());
+        packet[32] = 0x50;
+        packet[33] = flags;
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    // 带服务器签名的小包，用于识别连接
+    fn identify_payload() -> Vec<u8> {
+        let mut identify = vec![0x00, 0x00, 0x00, 0x1a, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00];
+        identify.extend_from_slice(&[0x00, 0x00, 0x00, 0x0c, 0, 0, 0, 0, 0]);
+        identify.extend_from_slice(&[0x00, 0x63, 0x33, 0x53, 0x42, 0x00, 0x00]);
+        identify
+    }
+
+    #[tokio::test]
+    async fn test_tracks_multiple_server_connections() {
+        // 服务器->客户端方向
+        let client = ([10, 0, 0, 9], 50000);
+        let tcp_packet = |src: [u8; 4], src_port: u16, seq: u32, payload: &[u8]| {
+            ip_packet((src, src_port), client, seq, 0x18, payload)
+        };
+
+        let identify = identify_payload();
+        let game = [0x00, 0x00, 0x00, 0x08, 0x01, 0x00, 0xaa, 0xbb];
+
+        reset_server_identification().await;
+        let (tx, rx) = async_channel::unbounded();
+
+        // 两条连接的数据交替到达，都应被识别并独立重组
+        let server_a = [10, 0, 0, 1];
+        let server_b = [10, 0, 0, 2];
+        process_packet(&tcp_packet(server_a, 5003, 1000, &identify), &tx).await.unwrap();
+        process_packet(&tcp_packet(server_b, 5004, 9000, &identify), &tx).await.unwrap();
+        process_packet(&tcp_packet(server_a, 5003, 1026, &game), &tx).await.unwrap();
+        process_packet(&tcp_packet(server_b, 5004, 9026, &game), &tx).await.unwrap();
+
+        assert_eq!(SERVER_CONNECTIONS.len(), 2);
+
+        // 客户端发送RST后只移除对应的连接
+        let rst = ip_packet(client, (server_a, 5003), 1, TCP_RST, &[]);
+        process_packet(&rst, &tx).await.unwrap();
+        assert_eq!(SERVER_CONNECTIONS.len(), 1);
+        assert!(SERVER_CONNECTIONS.iter().all(|entry| entry.key().src_ip == "10.0.0.2"));
+
+        let opcodes: Vec<u16> = std::iter::from_fn(|| rx.try_recv().ok()).map(|(opcode, _)| opcode).collect();
+        assert_eq!(opcodes, vec![0x0200, 0x0200, 0x0001, 0x0001]);
+
+        reset_server_identification().await;
+    }
+
+    #[test]
+    fn test_remove_stale_connections() {
+        let key = |port: u16| ConnectionKey {
+            src_ip: "10.0.0.1".to_string(),
+            src_port: port,
+            dst_ip: "10.0.0.9".to_string(),
+            dst_port: 50000,
+        };
+        let state = |last_seen: u64| {
+            Arc::new(Mutex::new(ServerState {
+                last_seen,
+                ..ServerState::new()
+            }))
+        };
+
+        let connections = ConnectionMap::new();
+        connections.insert(key(1), state(100));
+        connections.insert(key(2), state(350));
+        connections.insert(key(3), state(0));
+
+        // 正在处理中的连接即使超时也不会被移除
+        let busy = Arc::clone(connections.get(&key(3)).unwrap().value());
+        let _guard = busy.try_lock().unwrap();
+
+        assert_eq!(remove_stale_connections(&connections, 400, 300), 0);
+        assert_eq!(remove_stale_connections(&connections, 401, 300), 1);
+        assert!(connections.contains_key(&key(2)));
+        assert!(connections.contains_key(&key(3)));
+    }
+
+    #[test]
+    fn test_enforce_limits_drops_furthest_segments() {
+        let limits = TcpCacheLimits {
+            max_segments: 2,
+            max_bytes: 1024,
+            max_data_buffer: 16,
+        };
+        let mut state = ServerState::new();
+        state.next_seq = 100;
+        state.data_buffer = vec![0; 8];
+        for seq in [110u32, 120, 130] {
+            state.tcp_cache.insert(seq, vec![0; 4]);
+        }
+
+        // 丢弃离期望序列号最远的分段，数据流保持不变
+        assert!(state.enforce_limits(&limits));
+        assert_eq!(state.tcp_cache.keys().copied().collect::<Vec<_>>(), vec![110, 120]);
+        assert_eq!(state.data_buffer.len(), 8);
+        assert_eq!(state.next_seq, 100);
+
+        // 序列号回绕后，回绕前的分段离期望序列号更近
+        state.next_seq = u32::MAX as i64 - 10;
+        state.tcp_cache = BTreeMap::from([(5, vec![0; 4]), (u32::MAX - 5, vec![0; 4]), (50, vec![0; 4])]);
+        assert!(state.enforce_limits(&limits));
+        assert_eq!(state.tcp_cache.keys().copied().collect::<Vec<_>>(), vec![5, u32::MAX - 5]);
+
+        // 未超过上限时不做任何处理
+        state.data_buffer = vec![0; 16];
+        assert!(!state.enforce_limits(&limits));
+        assert_eq!(state.data_buffer.len(), 16);
+
+        // 数据缓冲区超过上限时重置数据流，同时清空分段缓存
+        state.data_buffer.push(0);
+        assert!(state.enforce_limits(&limits));
+        assert!(state.data_buffer.is_empty());
+        assert!(state.tcp_cache.is_empty());
+        assert_eq!(state.next_seq, -1);
+    }
+
+    #[test]
+    fn test_rolling_rate_uses_window_baseline() {
+        let start = Instant::now();
+        let sample = |secs: u64, packets: u64, bytes: u64| RateSample {
+            at: start + Duration::from_secs(secs),
+            packets,
+            bytes,
+        };
+        let mut samples = VecDeque::new();
+
+        // 第一次读取没有基准
+        assert_eq!(rolling_rate(&mut samples, sample(0, 0, 0)), (0.0, 0.0));
+        assert_eq!(rolling_rate(&mut samples, sample(2, 20, 2000)), (10.0, 1000.0));
+        assert_eq!(rolling_rate(&mut samples, sample(4, 60, 6000)), (15.0, 1500.0));
+
+        // 超出窗口后以窗口内最早的采样为基准（t=2）
+        assert_eq!(rolling_rate(&mut samples, sample(8, 100, 10000)), (80.0 / 6.0, 8000.0 / 6.0));
+        assert_eq!(samples.front().unwrap().packets, 20);
+    }
+
+    #[test]
+    fn test_capture_stats_keep_flat_json_shape() {
+        let before = snapshot_counters();
+        FILTERED_PACKETS.fetch_add(1, Ordering::Relaxed);
+        assert!(snapshot_counters().packets_dropped > before.packets_dropped);
+
+        // 计数器字段与速率字段在同一层级
+        let json = serde_json::to_value(get_capture_stats()).unwrap();
+        assert!(json["packets_dropped"].is_u64());
+        assert!(json["packets_per_sec"].is_f64());
+        assert!(json.get("counters").is_none());
+    }
+
+    #[test]
+    fn test_filter_for_interface_round_trip() {
+        let base = "(ip or ipv6) and tcp";
+        let filter = filter_for_interface(base, Some(7));
+        assert_eq!(filter, "((ip or ipv6) and tcp) and ifIdx == 7");
+        assert_eq!(interface_from_filter(&filter), Some(7));
+
+        // 重新选择时替换而不是叠加条件
+        let filter = filter_for_interface(&filter, Some(3));
+        assert_eq!(interface_from_filter(&filter), Some(3));
+        assert_eq!(filter_for_interface(&filter, None), base);
+        assert_eq!(interface_from_filter(base), None);
+    }
+
+    /// 一直收不到数据包的后端，只记录收到的过滤器
+    struct IdleBackend {
+        filters: Arc<parking_lot::Mutex<Vec<String>>>,
+    }
+
+    impl CaptureBackend for IdleBackend {
+        fn recv(&mut self) -> Result<Option<Vec<u8>>> {
+            std::thread::sleep(Duration::from_millis(5));
+            Ok(None)
+        }
 
-            let buffer_before = data_buffer.len();
-            if data_buffer.is_empty() {
-                *data_buffer = cached_data;
-            } else {
-                data_buffer.extend_from_slice(&cached_data);
+        fn set_filter(&mut self, filter: &str) -> Result<()> {
+            self.filters.lock().push(filter.to_string());
+            Ok(())
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_filter_update_and_stop_apply_without_traffic() {
+        let filters = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let backend = Box::new(IdleBackend { filters: filters.clone() });
+        let (filter_tx, filter_rx) = watch::channel("tcp".to_string());
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let (tx, _rx) = async_channel::unbounded();
+        let capture_task = tokio::spawn(run_capture(backend, Some(filter_rx), shutdown.clone(), None, tx));
+
+        filter_tx.send("tcp port 5003".to_string()).unwrap();
+        let applied = tokio::time::timeout(Duration::from_secs(2), async {
+            while filters.lock().is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
             }
-            let buffer_after = data_buffer.len();
-            log::debug!(
-                "📊 数据缓冲区更新 - 之前: {} bytes, 之后: {} bytes",
-                buffer_before,
-                buffer_after
-            );
+        })
+        .await;
+        assert!(applied.is_ok());
+        assert_eq!(*filters.lock(), vec!["tcp port 5003".to_string()]);
+
+        shutdown.store(true, Ordering::SeqCst);
+        let stopped = tokio::time::timeout(Duration::from_secs(2), capture_task).await;
+        assert!(matches!(stopped, Ok(Ok(Ok(())))));
+    }
 
-            // 处理数据缓冲区
-            let packets_from_buffer = process_data_buffer(&mut data_buffer, tx).await?;
-            processed_packets += packets_from_buffer;
+    #[tokio::test(start_paused = true)]
+    async fn test_replay_pacing_is_async_and_capped() {
+        // 两个包相隔1000秒的pcap文件（原始IP链路类型）
+        let path = std::env::temp_dir().join(format!("meter-core-pacing-{}.pcap", std::process::id()));
+        let ip_packet = [0x45u8, 0x00, 0x00, 0x14];
+        let mut content = vec![0xd4, 0xc3, 0xb2, 0xa1, 0x02, 0x00, 0x04, 0x00];
+        content.extend_from_slice(&[0u8; 8]);
+        content.extend_from_slice(&65535u32.to_le_bytes());
+        content.extend_from_slice(&crate::pcap::LINKTYPE_RAW.to_le_bytes());
+        for secs in [10u32, 1010] {
+            content.extend_from_slice(&secs.to_le_bytes());
+            content.extend_from_slice(&0u32.to_le_bytes());
+            content.extend_from_slice(&(ip_packet.len() as u32).to_le_bytes());
+            content.extend_from_slice(&(ip_packet.len() as u32).to_le_bytes());
+            content.extend_from_slice(&ip_packet);
         }
+        std::fs::write(&path, content).unwrap();
+        let backend = ReplayBackend::open(&path, true).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let (tx, _rx) = async_channel::unbounded();
+        let started = tokio::time::Instant::now();
+        run_capture(Box::new(backend), None, Arc::new(AtomicBool::new(false)), None, tx).await.unwrap();
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_secs(60) && elapsed < Duration::from_secs(61), "{:?}", elapsed);
     }
 
-    if processed_packets > 0 {
-        log::debug!("📤 已处理并发送 {} 个数据包到通道", processed_packets);
+    #[test]
+    fn test_debug_packet_ring_buffer_evicts_oldest() {
+        set_debug_packet_capacity(3);
+        for opcode in 1..=5u16 {
+            record_debug_packet(opcode, &[opcode as u8]);
+        }
+
+        // 超出容量时丢弃最旧的包
+        let packets = recent_debug_packets(10).unwrap();
+        assert_eq!(packets.iter().map(|p| p.opcode).collect::<Vec<_>>(), vec![3, 4, 5]);
+        assert_eq!(packets[0].payload, vec![3]);
+        let latest = recent_debug_packets(1).unwrap();
+        assert_eq!(latest[0].opcode, 5);
+
+        // 缩小容量会立即裁剪，超大的容量按上限处理
+        set_debug_packet_capacity(1);
+        assert_eq!(recent_debug_packets(10).unwrap().len(), 1);
+        set_debug_packet_capacity(usize::MAX);
+        assert_eq!(DEBUG_PACKET_CAPACITY.load(Ordering::Relaxed), crate::config::MAX_DEBUG_PACKET_CAPACITY);
+
+        set_debug_packet_capacity(0);
+        assert!(recent_debug_packets(10).is_none());
     }
 
-    Ok(())
+    // 吞吐量基准：多条已识别连接分别由独立任务送包，对比串行送包的速度
+    // 运行：cargo test --release --lib bench_parallel_connection_throughput -- --ignored --nocapture
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    #[ignore]
+    async fn bench_parallel_connection_throughput() {
+        const CONNECTIONS: u8 = 4;
+        const PACKETS_PER_CONNECTION: u32 = 50_000;
+
+        let client = ([10, 0, 0, 9], 50000);
+        let identify = identify_payload();
+        let game = [0x00, 0x00, 0x00, 0x08, 0x01, 0x00, 0xaa, 0xbb];
+        let streams: Vec<Vec<Vec<u8>>> = (0..CONNECTIONS)
+            .map(|i| {
+                let server = ([10, 0, 1, i], 5003);
+                (0..PACKETS_PER_CONNECTION)
+                    .map(|n| ip_packet(server, client, 1026 + n * game.len() as u32, 0x18, &game))
+                    .collect()
+            })
+            .collect();
+
+        let run = |parallel: bool| {
+            let streams = streams.clone();
+            let identify = identify.clone();
+            async move {
+                reset_server_identification().await;
+                let (tx, rx) = async_channel::unbounded();
+                for i in 0..CONNECTIONS {
+                    process_packet(&ip_packet(([10, 0, 1, i], 5003), client, 1000, 0x18, &identify), &tx).await.unwrap();
+                }
+                assert_eq!(SERVER_CONNECTIONS.len(), CONNECTIONS as usize);
+                let drain = tokio::spawn(async move { while rx.recv().await.is_ok() {} });
+
+                let start = std::time::Instant::now();
+                if parallel {
+                    let tasks: Vec<_> = streams
+                        .into_iter()
+                        .map(|stream| {
+                            let tx = tx.clone();
+                            tokio::spawn(async move {
+                                for packet in stream {
+                                    process_packet(&packet, &tx).await.unwrap();
+                                }
+                            })
+                        })
+                        .collect();
+                    for task in tasks {
+                        task.await.unwrap();
+                    }
+                } else {
+                    for n in 0..PACKETS_PER_CONNECTION as usize {
+                        for stream in &streams {
+                            process_packet(&stream[n], &tx).await.unwrap();
+                        }
+                    }
+                }
+                let elapsed = start.elapsed();
+                drop(tx);
+                drain.await.unwrap();
+                (CONNECTIONS as u32 * PACKETS_PER_CONNECTION) as f64 / elapsed.as_secs_f64()
+            }
+        };
+
+        // 先预热一轮，避免首轮的分配开销计入串行结果
+        run(false).await;
+        let serial = run(false).await;
+        let parallel = run(true).await;
+        println!(
+            "{} connections: serial {:.0} packets/s, parallel {:.0} packets/s ({:.2}x)",
+            CONNECTIONS,
+            serial,
+            parallel,
+            parallel / serial
+        );
+        reset_server_identification().await;
+    }
 }
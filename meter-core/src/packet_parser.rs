@@ -2,7 +2,7 @@ use crate::models::*;
 use crate::data_manager::DataManager;
 use bytes::{Buf, Bytes};
 use prost::Message;
-use std::collections::HashMap;
+use std::collections::HashSet;
 use std::sync::Arc;
 
 // Protobuf message definitions (simplified for now)
@@ -24,6 +24,14 @@ pub struct SyncContainerData {
     pub v_data: Option<VData>,
 }
 
+#[derive(Clone, PartialEq, Message)]
+pub struct SyncServerTime {
+    #[prost(int64, optional, tag = "1")]
+    pub client_milliseconds: Option<i64>,
+    #[prost(int64, optional, tag = "2")]
+    pub server_milliseconds: Option<i64>,
+}
+
 #[derive(Clone, PartialEq, Message)]
 pub struct SyncContainerDirtyData {
     #[prost(message, optional, tag = "1")]
@@ -176,6 +184,33 @@ pub enum NotifyMethod {
     SyncToMeDeltaInfo = 0x0000002e,
 }
 
+impl MessageType {
+    pub fn from_u16(value: u16) -> Option<Self> {
+        match value {
+            2 => Some(Self::Notify),
+            3 => Some(Self::Return),
+            6 => Some(Self::FrameDown),
+            _ => None,
+        }
+    }
+}
+
+impl NotifyMethod {
+    /// Every method the parser has a handler for
+    pub const ALL: [NotifyMethod; 6] = [
+        NotifyMethod::SyncNearEntities,
+        NotifyMethod::SyncContainerData,
+        NotifyMethod::SyncContainerDirtyData,
+        NotifyMethod::SyncServerTime,
+        NotifyMethod::SyncNearDeltaInfo,
+        NotifyMethod::SyncToMeDeltaInfo,
+    ];
+
+    pub fn from_u32(value: u32) -> Option<Self> {
+        Self::ALL.into_iter().find(|method| *method as u32 == value)
+    }
+}
+
 // Damage type enum
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum EDamageType {
@@ -207,6 +242,9 @@ pub const ATTR_ENERGY_FLAG: u32 = 0x543cd3c6;
 pub struct PacketParser {
     data_manager: Arc<DataManager>,
     current_user_uuid: u64,
+    service_uuids: Vec<u64>,
+    // Unknown service ids already reported, so each is logged at info only once
+    unknown_service_uuids: HashSet<u64>,
 }
 
 impl PacketParser {
@@ -214,97 +252,123 @@ impl PacketParser {
         Self {
             data_manager,
             current_user_uuid: 0,
+            service_uuids: crate::config::PacketCaptureConfig::default().service_uuids,
+            unknown_service_uuids: HashSet::new(),
         }
     }
 
+    pub fn with_config(mut self, config: &crate::config::PacketCaptureConfig) -> Self {
+        self.service_uuids = config.service_uuids.clone();
+        self
+    }
+
+    /// Process one or more length-prefixed frames (a FrameDown payload can carry several)
     pub async fn process_packet(&mut self, packet_data: &[u8]) {
-        if packet_data.len() < 6 {
-            log::debug!("Received invalid packet: too short");
-            return;
+        let mut reader = BinaryReader::new(packet_data);
+        while reader.remaining() > 0 {
+            let Some(frame_size) = reader.peek_u32_be() else {
+                log::debug!("Received invalid packet: truncated size");
+                return;
+            };
+            if frame_size < 6 {
+                log::debug!("Received invalid packet: too short");
+                return;
+            }
+            let Some(frame) = reader.read_bytes(frame_size as usize) else {
+                log::debug!("Received invalid packet: size {} exceeds {} remaining bytes", frame_size, reader.remaining());
+                return;
+            };
+            self.process_frame(frame).await;
         }
+    }
 
-        let mut reader = BinaryReader::new(packet_data);
+    async fn process_frame(&mut self, frame: &[u8]) {
+        let mut reader = BinaryReader::new(frame);
 
         // Skip packet size (already handled)
-        let _packet_size = reader.read_u32_be();
-
-        let packet_type = reader.read_u16_be();
+        let (Some(_packet_size), Some(packet_type)) = (reader.read_u32_be(), reader.read_u16_be()) else {
+            log::debug!("Received invalid packet: truncated header");
+            return;
+        };
+        // The flag only covers this frame's own payload; nested frames carry their own flag
         let is_compressed = (packet_type & 0x8000) != 0;
         let msg_type_id = packet_type & 0x7fff;
-
-        let mut payload_data = reader.read_remaining();
-
-        // Decompress if needed
-        let payload = if is_compressed {
-            match zstd::decode_all(payload_data) {
-                Ok(data) => data,
-                Err(e) => {
-                    log::error!("Failed to decompress packet: {}", e);
-                    return;
-                }
-            }
-        } else {
-            payload_data.to_vec()
+        self.data_manager.record_message_type(msg_type_id);
+        let Some(msg_type) = MessageType::from_u16(msg_type_id) else {
+            log::debug!("Unknown message type: 0x{:04x}", msg_type_id);
+            return;
         };
 
-        match msg_type_id {
-            x if x == MessageType::Notify as u16 => {
-                self.process_notify_message(&payload).await;
+        match msg_type {
+            MessageType::Notify => {
+                self.process_notify_message(reader.read_remaining(), is_compressed).await;
             }
-            x if x == MessageType::Return as u16 => {
+            MessageType::Return => {
                 // Handle return messages if needed
                 log::debug!("Processing return message");
             }
-            x if x == MessageType::FrameDown as u16 => {
-                let _server_sequence_id = reader.read_u32_be();
-                if !payload.is_empty() {
-                    // Recursively process nested frame
-                    Box::pin(self.process_packet(&payload)).await;
+            MessageType::FrameDown => {
+                // FrameDown carries a server sequence id before the nested frames
+                if reader.read_u32_be().is_none() {
+                    log::debug!("Received invalid FrameDown packet: missing sequence id");
+                    return;
                 }
-            }
-            _ => {
-                log::debug!("Unknown message type: {}", msg_type_id);
+
+                let nested = reader.read_remaining();
+                if nested.is_empty() {
+                    return;
+                }
+                let Some(nested) = decompress_payload(nested, is_compressed) else {
+                    return;
+                };
+                // Recursively process nested frames
+                Box::pin(self.process_packet(&nested)).await;
             }
         }
     }
 
-    async fn process_notify_message(&mut self, payload: &[u8]) {
-        if payload.len() < 12 {
-            return;
-        }
-
+    async fn process_notify_message(&mut self, payload: &[u8], is_compressed: bool) {
         let mut reader = BinaryReader::new(payload);
-        let service_uuid = reader.read_u64_be();
-        let _stub_id = reader.read_u32_be();
-        let method_id = reader.read_u32_be();
+        let (Some(service_uuid), Some(_stub_id), Some(method_id)) =
+            (reader.read_u64_be(), reader.read_u32_be(), reader.read_u32_be())
+        else {
+            log::debug!("Received truncated notify message: {} bytes", payload.len());
+            return;
+        };
 
         // Check if it's our service
-        if service_uuid != 0x0000000063335342 {
-            log::debug!("Skipping message with service ID: {}", service_uuid);
+        if !self.service_uuids.contains(&service_uuid) {
+            if self.unknown_service_uuids.insert(service_uuid) {
+                log::info!(
+                    "Dropping notify messages from unknown service ID 0x{:016x} (expected one of {:x?}); add it to packet_capture.service_uuids if the game protocol changed",
+                    service_uuid,
+                    self.service_uuids
+                );
+            } else {
+                log::debug!("Skipping message with service ID: {}", service_uuid);
+            }
             return;
         }
 
-        let msg_payload = reader.read_remaining();
+        // Only the method payload is compressed, not the service header
+        let Some(msg_payload) = decompress_payload(reader.read_remaining(), is_compressed) else {
+            return;
+        };
+        let msg_payload: &[u8] = &msg_payload;
+        self.data_manager.record_notify_method(method_id);
 
-        match method_id {
-            x if x == NotifyMethod::SyncNearEntities as u32 => {
-                self.process_sync_near_entities(&msg_payload).await;
-            }
-            x if x == NotifyMethod::SyncContainerData as u32 => {
-                self.process_sync_container_data(&msg_payload).await;
-            }
-            x if x == NotifyMethod::SyncContainerDirtyData as u32 => {
-                self.process_sync_container_dirty_data(&msg_payload).await;
-            }
-            x if x == NotifyMethod::SyncToMeDeltaInfo as u32 => {
-                self.process_sync_to_me_delta_info(&msg_payload).await;
-            }
-            x if x == NotifyMethod::SyncNearDeltaInfo as u32 => {
-                self.process_sync_near_delta_info(&msg_payload).await;
-            }
-            _ => {
-                log::debug!("Unknown notify method: {}", method_id);
-            }
+        let Some(method) = NotifyMethod::from_u32(method_id) else {
+            log::debug!("Unknown notify method: 0x{:08x}", method_id);
+            return;
+        };
+
+        match method {
+            NotifyMethod::SyncNearEntities => self.process_sync_near_entities(msg_payload).await,
+            NotifyMethod::SyncContainerData => self.process_sync_container_data(msg_payload).await,
+            NotifyMethod::SyncContainerDirtyData => self.process_sync_container_dirty_data(msg_payload).await,
+            NotifyMethod::SyncServerTime => self.process_sync_server_time(msg_payload),
+            NotifyMethod::SyncToMeDeltaInfo => self.process_sync_to_me_delta_info(msg_payload).await,
+            NotifyMethod::SyncNearDeltaInfo => self.process_sync_near_delta_info(msg_payload).await,
         }
     }
 
@@ -374,7 +438,7 @@ impl PacketParser {
 
                 if let Some(profession_list) = v_data.profession_list {
                     if let Some(profession_id) = profession_list.cur_profession_id {
-                        if let Some(profession_name) = get_profession_name_from_id(profession_id) {
+                        if let Some(profession_name) = self.data_manager.profession_name(profession_id) {
                             self.data_manager.set_user_profession(char_id, profession_name);
                         }
                     }
@@ -404,81 +468,84 @@ impl PacketParser {
     }
 
     async fn process_dirty_data_buffer(&mut self, buffer: &[u8]) {
-        let mut reader = BinaryReader::new(buffer);
-
-        // Skip identifier check for now
-        if buffer.len() < 8 {
-            return;
+        if self.apply_dirty_data_buffer(buffer).is_none() {
+            log::debug!("Truncated dirty data buffer: {} bytes", buffer.len());
         }
+    }
+
+    // Returns None when the buffer ends before the expected field
+    fn apply_dirty_data_buffer(&mut self, buffer: &[u8]) -> Option<()> {
+        let mut reader = BinaryReader::new(buffer);
+        let user_uid = (self.current_user_uuid >> 16) as u32;
 
-        let field_index = reader.read_u32_le();
-        reader.read_u32_le(); // Skip padding
+        let field_index = reader.read_u32_le()?;
+        reader.read_u32_le()?; // Skip padding
 
         match field_index {
             2 => { // CharBase
-                if buffer.len() < 16 {
-                    return;
-                }
-                let sub_field_index = reader.read_u32_le();
-                reader.read_u32_le();
+                let sub_field_index = reader.read_u32_le()?;
+                reader.read_u32_le()?;
 
                 match sub_field_index {
                     5 => { // Name
-                        let name = self.read_string(&mut reader);
-                        let user_uid = (self.current_user_uuid >> 16) as u32;
+                        let name = self.read_string(&mut reader)?;
                         self.data_manager.set_user_name(user_uid, name);
                     }
                     35 => { // FightPoint
-                        let fight_point = reader.read_u32_le();
-                        reader.read_u32_le();
-                        let user_uid = (self.current_user_uuid >> 16) as u32;
+                        let fight_point = reader.read_u32_le()?;
+                        reader.read_u32_le()?;
                         self.data_manager.set_user_fight_point(user_uid, fight_point);
                     }
                     _ => {}
                 }
             }
             16 => { // UserFightAttr
-                if buffer.len() < 16 {
-                    return;
-                }
-                let sub_field_index = reader.read_u32_le();
-                reader.read_u32_le();
+                let sub_field_index = reader.read_u32_le()?;
+                reader.read_u32_le()?;
 
                 match sub_field_index {
                     1 => { // CurHp
-                        let cur_hp = reader.read_u32_le();
-                        let user_uid = (self.current_user_uuid >> 16) as u32;
+                        let cur_hp = reader.read_u32_le()?;
                         self.data_manager.set_user_attr(user_uid, "hp", cur_hp);
                     }
                     2 => { // MaxHp
-                        let max_hp = reader.read_u32_le();
-                        let user_uid = (self.current_user_uuid >> 16) as u32;
+                        let max_hp = reader.read_u32_le()?;
                         self.data_manager.set_user_attr(user_uid, "max_hp", max_hp);
                     }
                     _ => {}
                 }
             }
             61 => { // ProfessionList
-                if buffer.len() < 16 {
-                    return;
-                }
-                let sub_field_index = reader.read_u32_le();
-                reader.read_u32_le();
-
-                match sub_field_index {
-                    1 => { // CurProfessionId
-                        let profession_id = reader.read_u32_le();
-                        reader.read_u32_le();
-                        if let Some(profession_name) = get_profession_name_from_id(profession_id) {
-                            let user_uid = (self.current_user_uuid >> 16) as u32;
-                            self.data_manager.set_user_profession(user_uid, profession_name);
-                        }
+                let sub_field_index = reader.read_u32_le()?;
+                reader.read_u32_le()?;
+
+                if sub_field_index == 1 { // CurProfessionId
+                    let profession_id = reader.read_u32_le()?;
+                    reader.read_u32_le()?;
+                    if let Some(profession_name) = self.data_manager.profession_name(profession_id) {
+                        self.data_manager.set_user_profession(user_uid, profession_name);
                     }
-                    _ => {}
                 }
             }
             _ => {}
         }
+
+        Some(())
+    }
+
+    fn process_sync_server_time(&mut self, payload: &[u8]) {
+        let sync_server_time = match SyncServerTime::decode(payload) {
+            Ok(msg) => msg,
+            Err(e) => {
+                log::error!("Failed to decode SyncServerTime: {}", e);
+                return;
+            }
+        };
+
+        match sync_server_time.server_milliseconds {
+            Some(server_millis) if server_millis > 0 => self.data_manager.sync_server_time(server_millis),
+            _ => log::debug!("SyncServerTime without server time, keeping local clock"),
+        }
     }
 
     async fn process_sync_to_me_delta_info(&mut self, payload: &[u8]) {
@@ -552,20 +619,34 @@ impl PacketParser {
             return;
         }
 
-        let mut attacker_uuid = damage_info.top_summoner_id
-            .or(damage_info.attacker_uuid)
+        // Summons credit their top-level owner; a zero summoner id means "no summoner"
+        let summon_uuid = damage_info.attacker_uuid.filter(|&uuid| uuid != 0);
+        let attacker_uuid = damage_info.top_summoner_id
+            .filter(|&uuid| uuid != 0)
+            .or(summon_uuid)
             .unwrap_or(0);
         if attacker_uuid == 0 {
             return;
         }
+        // The entity that actually hit, when it isn't the owner itself
+        let summon_uuid = summon_uuid.filter(|&uuid| uuid != attacker_uuid);
 
         let is_attacker_player = is_uuid_player(attacker_uuid);
         let attacker_uid = (attacker_uuid >> 16) as u32;
 
+        let target_uid = (target_uuid >> 16) as u32;
+        let is_miss = damage_info.is_miss.unwrap_or(false);
+        let is_heal = damage_info.r#type == Some(EDamageType::Heal as u32);
+
         let value = damage_info.value.unwrap_or(0);
         let lucky_value = damage_info.lucky_value.unwrap_or(0);
         let damage = if value > 0 { value } else { lucky_value };
         if damage == 0 {
+            // 未命中的攻击没有伤害值，只记录次数
+            if is_miss && !is_heal && is_attacker_player && !is_target_player {
+                let element = self.data_manager.element_name(damage_info.property.unwrap_or(0));
+                self.data_manager.add_miss(attacker_uid, skill_id, element, target_uid).await;
+            }
             return;
         }
 
@@ -574,14 +655,10 @@ impl PacketParser {
         let is_cause_lucky = (type_flag & 0b100) == 0b100;
         let is_lucky = lucky_value > 0;
 
-        let is_miss = damage_info.is_miss.unwrap_or(false);
-        let is_heal = damage_info.r#type == Some(EDamageType::Heal as u32);
         let is_dead = damage_info.is_dead.unwrap_or(false);
         let hp_lessen_value = damage_info.hp_lessen_value.unwrap_or(0);
         let damage_property = damage_info.property.unwrap_or(0);
-        let element = get_damage_element_name(damage_property);
-
-        let target_uid = (target_uuid >> 16) as u32;
+        let element = self.data_manager.element_name(damage_property);
 
         if is_target_player {
             // 玩家目标
@@ -599,7 +676,7 @@ impl PacketParser {
                 ).await;
             } else {
                 // 玩家受到伤害
-                self.data_manager.add_taken_damage(target_uid, damage as u32, is_dead).await;
+                self.data_manager.add_taken_damage(target_uid, attacker_uid, damage as u32, is_dead).await;
             }
 
             if is_dead {
@@ -624,6 +701,10 @@ impl PacketParser {
                         hp_lessen_value,
                         target_uid,
                     ).await;
+
+                    if let Some(summon_uuid) = summon_uuid {
+                        self.data_manager.add_summon_damage(attacker_uid, summon_uuid, damage, target_uid).await;
+                    }
                 }
             }
 
@@ -706,7 +787,7 @@ impl PacketParser {
             ATTR_PROFESSION_ID => {
                 if is_player {
                     let profession_id = u32::from_be_bytes(raw_data.try_into().unwrap_or_default());
-                    if let Some(profession_name) = get_profession_name_from_id(profession_id) {
+                    if let Some(profession_name) = self.data_manager.profession_name(profession_id) {
                         self.data_manager.set_user_profession(uid, profession_name);
                     }
                 }
@@ -747,12 +828,12 @@ impl PacketParser {
         }
     }
 
-    fn read_string(&self, reader: &mut BinaryReader) -> String {
-        let length = reader.read_u32_le();
-        reader.read_u32_le(); // Skip padding
-        let string_data = reader.read_bytes(length as usize).to_vec();
-        reader.read_u32_le(); // Skip padding
-        String::from_utf8_lossy(&string_data).to_string()
+    fn read_string(&self, reader: &mut BinaryReader) -> Option<String> {
+        let length = reader.read_u32_le()?;
+        reader.read_u32_le()?; // Skip padding
+        let string_data = reader.read_bytes(length as usize)?;
+        reader.read_u32_le()?; // Skip padding
+        Some(String::from_utf8_lossy(string_data).to_string())
     }
 }
 
@@ -765,38 +846,6 @@ fn is_uuid_monster(uuid: u64) -> bool {
     (uuid & 0xffff) == 64
 }
 
-fn get_damage_element_name(property: u32) -> String {
-    match property {
-        0 => "⚔️物".to_string(),
-        1 => "🔥火".to_string(),
-        2 => "❄️冰".to_string(),
-        3 => "⚡雷".to_string(),
-        4 => "🍀森".to_string(),
-        5 => "💨风".to_string(),
-        6 => "⛰️岩".to_string(),
-        7 => "🌟光".to_string(),
-        8 => "🌑暗".to_string(),
-        _ => "⚔️物".to_string(),
-    }
-}
-
-fn get_profession_name_from_id(profession_id: u32) -> Option<String> {
-    match profession_id {
-        1 => Some("雷影剑士".to_string()),
-        2 => Some("冰魔导师".to_string()),
-        3 => Some("涤罪恶火·战斧".to_string()),
-        4 => Some("青岚骑士".to_string()),
-        5 => Some("森语者".to_string()),
-        8 => Some("雷霆一闪·手炮".to_string()),
-        9 => Some("巨刃守护者".to_string()),
-        10 => Some("暗灵祈舞·仪刀/仪仗".to_string()),
-        11 => Some("神射手".to_string()),
-        12 => Some("神盾骑士".to_string()),
-        13 => Some("灵魂乐手".to_string()),
-        _ => None,
-    }
-}
-
 // Binary reader helper
 pub struct BinaryReader<'a> {
     data: &'a [u8],
@@ -808,39 +857,172 @@ impl<'a> BinaryReader<'a> {
         Self { data, position: 0 }
     }
 
-    pub fn read_u64_be(&mut self) -> u64 {
-        let value = u64::from_be_bytes(self.data[self.position..self.position + 8].try_into().unwrap());
-        self.position += 8;
-        value
+    // Read a fixed-size array, returning None (without advancing) if the buffer is too short
+    fn read_array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let end = self.position.checked_add(N)?;
+        let bytes = self.data.get(self.position..end)?.try_into().ok()?;
+        self.position = end;
+        Some(bytes)
     }
 
-    pub fn read_u32_be(&mut self) -> u32 {
-        let value = u32::from_be_bytes(self.data[self.position..self.position + 4].try_into().unwrap());
-        self.position += 4;
-        value
+    pub fn read_u64_be(&mut self) -> Option<u64> {
+        self.read_array().map(u64::from_be_bytes)
     }
 
-    pub fn read_u32_le(&mut self) -> u32 {
-        let value = u32::from_le_bytes(self.data[self.position..self.position + 4].try_into().unwrap());
-        self.position += 4;
-        value
+    pub fn read_u32_be(&mut self) -> Option<u32> {
+        self.read_array().map(u32::from_be_bytes)
     }
 
-    pub fn read_u16_be(&mut self) -> u16 {
-        let value = u16::from_be_bytes(self.data[self.position..self.position + 2].try_into().unwrap());
-        self.position += 2;
-        value
+    pub fn read_u32_le(&mut self) -> Option<u32> {
+        self.read_array().map(u32::from_le_bytes)
     }
 
-    pub fn read_bytes(&mut self, length: usize) -> &[u8] {
-        let start = self.position;
-        self.position += length;
-        &self.data[start..self.position]
+    pub fn read_u16_be(&mut self) -> Option<u16> {
+        self.read_array().map(u16::from_be_bytes)
+    }
+
+    pub fn read_bytes(&mut self, length: usize) -> Option<&'a [u8]> {
+        let end = self.position.checked_add(length)?;
+        let bytes = self.data.get(self.position..end)?;
+        self.position = end;
+        Some(bytes)
     }
 
-    pub fn read_remaining(&mut self) -> &[u8] {
+    pub fn read_remaining(&mut self) -> &'a [u8] {
         let start = self.position;
         self.position = self.data.len();
         &self.data[start..]
     }
+
+    pub fn peek_u32_be(&self) -> Option<u32> {
+        let bytes = self.data.get(self.position..self.position.checked_add(4)?)?;
+        Some(u32::from_be_bytes(bytes.try_into().ok()?))
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.position
+    }
+}
+
+// Magic number at the start of every zstd frame
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Decompress a payload whose frame had the compression flag set. Data without a zstd
+/// frame header is passed through unchanged rather than decompressed a second time.
+fn decompress_payload(data: &[u8], is_compressed: bool) -> Option<std::borrow::Cow<'_, [u8]>> {
+    if !is_compressed {
+        return Some(std::borrow::Cow::Borrowed(data));
+    }
+    if !data.starts_with(&ZSTD_MAGIC) {
+        log::debug!("Payload flagged as compressed has no zstd header, using it as is");
+        return Some(std::borrow::Cow::Borrowed(data));
+    }
+
+    match zstd::decode_all(data) {
+        Ok(decompressed) => Some(std::borrow::Cow::Owned(decompressed)),
+        Err(e) => {
+            log::error!("Failed to decompress packet: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notify_method_from_u32() {
+        for method in NotifyMethod::ALL {
+            assert_eq!(NotifyMethod::from_u32(method as u32), Some(method));
+        }
+        assert_eq!(NotifyMethod::from_u32(0xdead), None);
+        assert_eq!(MessageType::from_u16(6), Some(MessageType::FrameDown));
+        assert_eq!(MessageType::from_u16(7), None);
+    }
+
+    #[test]
+    fn test_binary_reader_rejects_short_buffer() {
+        let data = [0x01, 0x02, 0x03];
+
+        assert_eq!(BinaryReader::new(&data).read_u64_be(), None);
+        assert_eq!(BinaryReader::new(&data).read_u32_be(), None);
+        assert_eq!(BinaryReader::new(&data).read_u32_le(), None);
+        assert_eq!(BinaryReader::new(&data).read_bytes(4), None);
+        assert_eq!(BinaryReader::new(&data).read_bytes(usize::MAX), None);
+
+        // A failed read does not advance the reader
+        let mut reader = BinaryReader::new(&data);
+        assert_eq!(reader.read_u32_be(), None);
+        assert_eq!(reader.read_u16_be(), Some(0x0102));
+        assert_eq!(reader.read_u16_be(), None);
+        assert_eq!(reader.read_bytes(1), Some(&[0x03][..]));
+        assert_eq!(reader.read_remaining(), &[] as &[u8]);
+    }
+
+    fn frame(packet_type: u16, body: &[u8]) -> Vec<u8> {
+        let mut frame = ((body.len() + 6) as u32).to_be_bytes().to_vec();
+        frame.extend_from_slice(&packet_type.to_be_bytes());
+        frame.extend_from_slice(body);
+        frame
+    }
+
+    #[tokio::test]
+    async fn test_compressed_frame_down_wraps_plain_notifies() {
+        let data_manager = Arc::new(DataManager::new());
+        let mut parser = PacketParser::new(data_manager.clone());
+        let service_uuid = crate::config::PacketCaptureConfig::default().service_uuids[0];
+
+        // An uncompressed notify with an unknown method id, so no handler runs
+        let mut notify_body = service_uuid.to_be_bytes().to_vec();
+        notify_body.extend_from_slice(&0u32.to_be_bytes());
+        notify_body.extend_from_slice(&0xdeadu32.to_be_bytes());
+        let notify = frame(MessageType::Notify as u16, &notify_body);
+
+        // Two nested notifies in one compressed FrameDown
+        let nested = [notify.clone(), notify].concat();
+        let mut frame_down_body = 1u32.to_be_bytes().to_vec();
+        frame_down_body.extend_from_slice(&zstd::encode_all(&nested[..], 0).unwrap());
+        let frame_down = frame(MessageType::FrameDown as u16 | 0x8000, &frame_down_body);
+
+        parser.process_packet(&frame_down).await;
+
+        assert_eq!(data_manager.message_type_counts.get(&(MessageType::FrameDown as u16)).map(|c| *c), Some(1));
+        assert_eq!(data_manager.message_type_counts.get(&(MessageType::Notify as u16)).map(|c| *c), Some(2));
+        assert_eq!(data_manager.notify_method_counts.get(&0xdead).map(|c| *c), Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_summon_damage_credited_to_owner() {
+        let data_manager = Arc::new(DataManager::new());
+        let mut parser = PacketParser::new(data_manager.clone());
+        let player_uuid = (42u64 << 16) | 640;
+        let summon_uuid = (9001u64 << 16) | 64;
+        let target_uuid = (75u64 << 16) | 64;
+
+        // 炮台的伤害计入主人，并按召唤物uuid单独统计
+        let summon_hit = SyncDamageInfo {
+            owner_id: Some(1001),
+            attacker_uuid: Some(summon_uuid),
+            top_summoner_id: Some(player_uuid),
+            value: Some(500),
+            ..Default::default()
+        };
+        parser.process_damage_info(&summon_hit, target_uuid, false).await;
+
+        // 主人自己的攻击不算召唤物伤害；召唤者id为0表示没有召唤者
+        let own_hit = SyncDamageInfo {
+            owner_id: Some(1002),
+            attacker_uuid: Some(player_uuid),
+            top_summoner_id: Some(0),
+            value: Some(300),
+            ..Default::default()
+        };
+        parser.process_damage_info(&own_hit, target_uuid, false).await;
+
+        let user = data_manager.users.get(&42).unwrap().read().clone();
+        assert_eq!(user.damage_stats.total_damage, 800);
+        assert_eq!(user.summon_damage, std::collections::HashMap::from([(summon_uuid, 500)]));
+        assert!(!data_manager.users.contains_key(&9001));
+    }
 }
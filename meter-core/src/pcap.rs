@@ -0,0 +1,316 @@
+//! pcap / pcapng 文件读取，用于离线回放捕获数据（不依赖WinDivert）
+
+use crate::{MeterError, Result};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// 链路层类型
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+pub(crate) const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_IPV4: u32 = 228;
+const LINKTYPE_IPV6: u32 = 229;
+
+// pcapng 块类型
+const PCAPNG_SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+const PCAPNG_SIMPLE_PACKET: u32 = 0x0000_0003;
+const PCAPNG_ENHANCED_PACKET: u32 = 0x0000_0006;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+
+// 写入时缓冲区的最长刷新间隔
+const WRITER_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 文件中的一个数据包，`data` 为去掉链路层头部后的IP数据包
+#[derive(Debug, Clone)]
+pub struct PcapPacket {
+    /// 相对于Unix纪元的捕获时间
+    pub timestamp: Duration,
+    pub data: Vec<u8>,
+}
+
+/// 读取pcap或pcapng文件中的全部IP数据包
+pub fn read_packets<P: AsRef<Path>>(path: P) -> Result<Vec<PcapPacket>> {
+    let content = std::fs::read(path)?;
+    parse_packets(&content)
+}
+
+/// 解析pcap或pcapng格式的字节流
+pub fn parse_packets(content: &[u8]) -> Result<Vec<PcapPacket>> {
+    if content.len() < 4 {
+        return Err(MeterError::ParseError("pcap文件太小".to_string()));
+    }
+
+    let magic = u32::from_le_bytes([content[0], content[1], content[2], content[3]]);
+    if magic == PCAPNG_SECTION_HEADER {
+        parse_pcapng(content)
+    } else {
+        parse_pcap(content)
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize, big_endian: bool) -> Option<u16> {
+    let bytes: [u8; 2] = data.get(offset..offset + 2)?.try_into().ok()?;
+    Some(if big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+}
+
+fn read_u32(data: &[u8], offset: usize, big_endian: bool) -> Option<u32> {
+    let bytes: [u8; 4] = data.get(offset..offset + 4)?.try_into().ok()?;
+    Some(if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+}
+
+fn truncated() -> MeterError {
+    MeterError::ParseError("pcap文件被截断".to_string())
+}
+
+// 经典pcap格式：24字节全局头 + 每个包16字节记录头
+fn parse_pcap(content: &[u8]) -> Result<Vec<PcapPacket>> {
+    let (big_endian, nanos) = match content[0..4] {
+        [0xd4, 0xc3, 0xb2, 0xa1] => (false, false),
+        [0xa1, 0xb2, 0xc3, 0xd4] => (true, false),
+        [0x4d, 0x3c, 0xb2, 0xa1] => (false, true),
+        [0xa1, 0xb2, 0x3c, 0x4d] => (true, true),
+        _ => return Err(MeterError::ParseError("不是pcap/pcapng文件".to_string())),
+    };
+
+    let link_type = read_u32(content, 20, big_endian).ok_or_else(truncated)?;
+    let mut packets = Vec::new();
+    let mut offset = 24;
+
+    while offset + 16 <= content.len() {
+        let ts_sec = read_u32(content, offset, big_endian).ok_or_else(truncated)?;
+        let ts_frac = read_u32(content, offset + 4, big_endian).ok_or_else(truncated)?;
+        let incl_len = read_u32(content, offset + 8, big_endian).ok_or_else(truncated)? as usize;
+        offset += 16;
+
+        let frame = content.get(offset..offset + incl_len).ok_or_else(truncated)?;
+        offset += incl_len;
+
+        let timestamp = if nanos {
+            Duration::new(ts_sec as u64, ts_frac)
+        } else {
+            Duration::new(ts_sec as u64, ts_frac.saturating_mul(1000))
+        };
+        if let Some(data) = strip_link_layer(frame, link_type) {
+            packets.push(PcapPacket { timestamp, data: data.to_vec() });
+        }
+    }
+
+    Ok(packets)
+}
+
+// pcapng格式：按块解析，只关心接口描述块和数据包块
+fn parse_pcapng(content: &[u8]) -> Result<Vec<PcapPacket>> {
+    let mut packets = Vec::new();
+    let mut big_endian = false;
+    // 每个接口的 (链路层类型, 时间戳单位/秒)
+    let mut interfaces: Vec<(u32, u64)> = Vec::new();
+    let mut offset = 0;
+
+    while offset + 12 <= content.len() {
+        let block_type = read_u32(content, offset, big_endian).ok_or_else(truncated)?;
+
+        // 节头块决定后续块的字节序
+        if block_type == PCAPNG_SECTION_HEADER {
+            let magic = read_u32(content, offset + 8, false).ok_or_else(truncated)?;
+            big_endian = magic != PCAPNG_BYTE_ORDER_MAGIC;
+            interfaces.clear();
+        }
+
+        let block_len = read_u32(content, offset + 4, big_endian).ok_or_else(truncated)? as usize;
+        if block_len < 12 || offset + block_len > content.len() {
+            return Err(truncated());
+        }
+        let body = &content[offset + 8..offset + block_len - 4];
+
+        match block_type {
+            PCAPNG_INTERFACE_DESCRIPTION => {
+                let link_type = read_u16(body, 0, big_endian).ok_or_else(truncated)? as u32;
+                interfaces.push((link_type, parse_if_tsresol(body, big_endian)));
+            }
+            PCAPNG_ENHANCED_PACKET => {
+                let interface_id = read_u32(body, 0, big_endian).ok_or_else(truncated)? as usize;
+                let ts_high = read_u32(body, 4, big_endian).ok_or_else(truncated)? as u64;
+                let ts_low = read_u32(body, 8, big_endian).ok_or_else(truncated)? as u64;
+                let captured_len = read_u32(body, 12, big_endian).ok_or_else(truncated)? as usize;
+                let frame = body.get(20..20 + captured_len).ok_or_else(truncated)?;
+
+                let (link_type, units_per_sec) = interfaces
+                    .get(interface_id)
+                    .copied()
+                    .unwrap_or((LINKTYPE_ETHERNET, 1_000_000));
+                let timestamp = ticks_to_duration((ts_high << 32) | ts_low, units_per_sec);
+
+                if let Some(data) = strip_link_layer(frame, link_type) {
+                    packets.push(PcapPacket { timestamp, data: data.to_vec() });
+                }
+            }
+            PCAPNG_SIMPLE_PACKET => {
+                let link_type = interfaces.first().map(|(t, _)| *t).unwrap_or(LINKTYPE_ETHERNET);
+                let frame = body.get(4..).ok_or_else(truncated)?;
+                if let Some(data) = strip_link_layer(frame, link_type) {
+                    packets.push(PcapPacket { timestamp: Duration::ZERO, data: data.to_vec() });
+                }
+            }
+            _ => {}
+        }
+
+        offset += block_len;
+    }
+
+    Ok(packets)
+}
+
+// 按接口精度把时间戳换算为时长；余数用u128计算，精度高于纳秒（如2^63）时也不会溢出
+fn ticks_to_duration(ticks: u64, units_per_sec: u64) -> Duration {
+    let nanos = (ticks % units_per_sec) as u128 * 1_000_000_000 / units_per_sec as u128;
+    Duration::from_secs(ticks / units_per_sec) + Duration::from_nanos(nanos as u64)
+}
+
+// 读取接口描述块的 if_tsresol 选项，默认微秒
+fn parse_if_tsresol(body: &[u8], big_endian: bool) -> u64 {
+    let mut offset = 8;
+    while let (Some(code), Some(len)) = (read_u16(body, offset, big_endian), read_u16(body, offset + 2, big_endian)) {
+        if code == 0 {
+            break;
+        }
+        if code == 9 && len == 1 {
+            if let Some(&resol) = body.get(offset + 4) {
+                let exp = (resol & 0x7F) as u32;
+                let value = if resol & 0x80 != 0 { 2u64.checked_pow(exp) } else { 10u64.checked_pow(exp) };
+                return value.unwrap_or(1_000_000).max(1);
+            }
+        }
+        offset += 4 + (len as usize).div_ceil(4) * 4;
+    }
+    1_000_000
+}
+
+/// 将原始IP数据包写入pcap文件（LINKTYPE_RAW），带缓冲并定期刷新
+pub struct PcapWriter {
+    writer: BufWriter<File>,
+    last_flush: Instant,
+}
+
+impl PcapWriter {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        // 全局头：小端、微秒精度、v2.4
+        writer.write_all(&0xa1b2_c3d4u32.to_le_bytes())?;
+        writer.write_all(&2u16.to_le_bytes())?;
+        writer.write_all(&4u16.to_le_bytes())?;
+        writer.write_all(&0i32.to_le_bytes())?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(&65535u32.to_le_bytes())?;
+        writer.write_all(&LINKTYPE_RAW.to_le_bytes())?;
+
+        Ok(Self {
+            writer,
+            last_flush: Instant::now(),
+        })
+    }
+
+    /// 写入一个IP数据包，时间戳为当前时间
+    pub fn write_packet(&mut self, data: &[u8]) -> Result<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+
+        self.writer.write_all(&(now.as_secs() as u32).to_le_bytes())?;
+        self.writer.write_all(&now.subsec_micros().to_le_bytes())?;
+        self.writer.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.writer.write_all(data)?;
+
+        if self.last_flush.elapsed() >= WRITER_FLUSH_INTERVAL {
+            self.writer.flush()?;
+            self.last_flush = Instant::now();
+        }
+        Ok(())
+    }
+
+    /// 刷新缓冲区并关闭文件
+    pub fn finish(mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+// 去掉链路层头部，返回IP数据包；不支持的链路类型或非IP帧返回None
+pub(crate) fn strip_link_layer(frame: &[u8], link_type: u32) -> Option<&[u8]> {
+    match link_type {
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => Some(frame),
+        LINKTYPE_NULL => frame.get(4..),
+        LINKTYPE_LINUX_SLL => frame.get(16..),
+        LINKTYPE_ETHERNET => {
+            let mut offset = 12;
+            let mut ether_type = u16::from_be_bytes([*frame.get(offset)?, *frame.get(offset + 1)?]);
+            // 跳过VLAN标签
+            while ether_type == 0x8100 || ether_type == 0x88A8 {
+                offset += 4;
+                ether_type = u16::from_be_bytes([*frame.get(offset)?, *frame.get(offset + 1)?]);
+            }
+            match ether_type {
+                0x0800 | 0x86DD => frame.get(offset + 2..),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_classic_pcap_ethernet() {
+        let ip_packet = [0x45u8, 0x00, 0x00, 0x14];
+        let mut frame = vec![0u8; 12];
+        frame.extend_from_slice(&[0x08, 0x00]);
+        frame.extend_from_slice(&ip_packet);
+
+        let mut content = vec![0xd4, 0xc3, 0xb2, 0xa1, 0x02, 0x00, 0x04, 0x00];
+        content.extend_from_slice(&[0u8; 8]);
+        content.extend_from_slice(&65535u32.to_le_bytes());
+        content.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+        content.extend_from_slice(&10u32.to_le_bytes());
+        content.extend_from_slice(&500u32.to_le_bytes());
+        content.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        content.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        content.extend_from_slice(&frame);
+
+        let packets = parse_packets(&content).unwrap();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].data, ip_packet);
+        assert_eq!(packets[0].timestamp, Duration::new(10, 500_000));
+    }
+
+    #[test]
+    fn test_writer_round_trip() {
+        let path = std::env::temp_dir().join(format!("meter-core-test-{}.pcap", std::process::id()));
+        let ip_packet = vec![0x45u8, 0x00, 0x00, 0x14, 0x01, 0x02];
+
+        let mut writer = PcapWriter::create(&path).unwrap();
+        writer.write_packet(&ip_packet).unwrap();
+        writer.finish().unwrap();
+
+        let packets = read_packets(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].data, ip_packet);
+    }
+
+    #[test]
+    fn test_ticks_to_duration_high_resolution() {
+        assert_eq!(ticks_to_duration(1_500_000, 1_000_000), Duration::from_millis(1500));
+        assert_eq!(ticks_to_duration(1_000_000_001, 1_000_000_000), Duration::new(1, 1));
+
+        // 2^63 单位/秒，余数乘以1e9会超出u64
+        let units_per_sec = 1u64 << 63;
+        assert_eq!(ticks_to_duration(units_per_sec + units_per_sec / 2, units_per_sec), Duration::from_millis(1500));
+        assert_eq!(ticks_to_duration(u64::MAX, units_per_sec), Duration::new(1, 999_999_999));
+    }
+}
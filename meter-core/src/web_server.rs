@@ -1,21 +1,64 @@
-use crate::data_manager::DataManager;
+use crate::data_manager::encounter_store::EncounterQuery;
+use crate::data_manager::{DataManager, RankingMetric, SkillKind, UsersSnapshot, MAX_ENCOUNTER_IDLE_GAP_SECS, MAX_WINDOW_DPS_SECS};
+use crate::models::{SkillConfig, SkillStats, User};
+use crate::packet_capture::{self, PacketCapture};
+use crate::packet_parser::{MessageType, NotifyMethod};
 use axum::{
-    extract::Path,
-    http::StatusCode,
-    response::Json,
+    extract::{Path, Request},
+    Extension,
+    http::{header, Extensions, HeaderMap, StatusCode, Version},
+    middleware::{self, Next},
+    response::{IntoResponse, Json, Response},
     routing::{get, post},
     Router,
 };
+use serde::Deserialize;
 use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::Component;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use tower_http::cors::{Any, CorsLayer};
+use std::time::{Duration, Instant};
+use tower_http::compression::{
+    predicate::{DefaultPredicate, Predicate, SizeAbove},
+    CompressionLayer,
+};
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, Any, CorsLayer};
 use tokio::sync::broadcast;
 
+// Session imports carry complete user stats, so allow more than axum's 2 MB default
+const SESSION_IMPORT_MAX_BYTES: usize = 64 * 1024 * 1024;
+
+// Responses smaller than this are sent uncompressed
+const COMPRESSION_MIN_SIZE: u16 = 1024;
+
+// Health turns "degraded" when no packet was captured for this long
+const CAPTURE_IDLE_THRESHOLD_SECS: u64 = 60;
+
+// How long open HTTPS connections get to finish once shutdown starts
+const TLS_SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+// Interval between full WebSocket snapshots (deltas are sent in between)
+const WS_FULL_SYNC_INTERVAL: Duration = Duration::from_secs(5);
+
+// Later seconds tried when a snapshot for the requested second already exists
+const MAX_SNAPSHOT_SLOT_ATTEMPTS: u32 = 60;
+
 // Web server configuration
 pub struct WebServerConfig {
     pub host: String,
     pub port: u16,
     pub enable_cors: bool,
+    /// Explicit CORS origins; any origin is allowed when empty
+    pub cors_allowed_origins: Vec<String>,
+    pub enable_compression: bool,
+    pub api_token: Option<String>,
+    pub ws_keepalive: WsKeepalive,
+    /// 0 means no limit
+    pub max_ws_connections: usize,
+    /// PEM certificate chain and private key; HTTPS/WSS is served when both are set
+    pub tls_cert_path: Option<String>,
+    pub tls_key_path: Option<String>,
 }
 
 impl Default for WebServerConfig {
@@ -24,6 +67,86 @@ impl Default for WebServerConfig {
             host: "127.0.0.1".to_string(),
             port: 8989,
             enable_cors: true,
+            cors_allowed_origins: Vec::new(),
+            enable_compression: true,
+            api_token: None,
+            ws_keepalive: WsKeepalive::default(),
+            max_ws_connections: 32,
+            tls_cert_path: None,
+            tls_key_path: None,
+        }
+    }
+}
+
+impl From<&crate::config::WebServerConfig> for WebServerConfig {
+    fn from(config: &crate::config::WebServerConfig) -> Self {
+        Self {
+            host: config.host.clone(),
+            port: config.port,
+            enable_cors: config.enable_cors,
+            cors_allowed_origins: config.cors_allowed_origins.clone(),
+            enable_compression: config.enable_compression,
+            api_token: config.api_token.clone().filter(|token| !token.is_empty()),
+            ws_keepalive: WsKeepalive {
+                ping_interval: (config.ws_ping_interval > 0)
+                    .then(|| Duration::from_secs(config.ws_ping_interval.min(crate::config::MAX_WS_KEEPALIVE_SECS))),
+                pong_timeout: Duration::from_secs(config.ws_pong_timeout.min(crate::config::MAX_WS_KEEPALIVE_SECS)),
+            },
+            max_ws_connections: config.max_ws_connections,
+            tls_cert_path: config.tls_cert_path.clone().filter(|path| !path.is_empty()),
+            tls_key_path: config.tls_key_path.clone().filter(|path| !path.is_empty()),
+        }
+    }
+}
+
+/// How often WebSocket clients are pinged and how long a pong may take
+#[derive(Debug, Clone, Copy)]
+pub struct WsKeepalive {
+    /// `None` disables pings
+    pub ping_interval: Option<Duration>,
+    pub pong_timeout: Duration,
+}
+
+/// Counts open WebSocket connections against the configured limit
+#[derive(Debug, Clone)]
+pub struct WsConnectionLimit {
+    active: Arc<AtomicUsize>,
+    max: usize,
+}
+
+/// Holds one connection slot, released on drop
+pub struct WsConnectionSlot(Arc<AtomicUsize>);
+
+impl WsConnectionLimit {
+    pub fn new(max: usize) -> Self {
+        Self { active: Arc::new(AtomicUsize::new(0)), max }
+    }
+
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    fn try_acquire(&self) -> Option<WsConnectionSlot> {
+        self.active
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |active| {
+                (self.max == 0 || active < self.max).then_some(active + 1)
+            })
+            .ok()
+            .map(|_| WsConnectionSlot(self.active.clone()))
+    }
+}
+
+impl Drop for WsConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl Default for WsKeepalive {
+    fn default() -> Self {
+        Self {
+            ping_interval: Some(Duration::from_secs(30)),
+            pong_timeout: Duration::from_secs(10),
         }
     }
 }
@@ -54,22 +177,67 @@ impl WebServer {
 
         let app = self.create_router();
 
+        // Load the certificate before binding so a bad path fails startup with a clear error
+        let tls_config = self.load_tls_config().await?;
+        let scheme = if tls_config.is_some() { "https" } else { "http" };
+
         let addr = format!("{}:{}", self.config.host, self.config.port);
-        log::info!("Starting web server at http://{}", addr);
+        log::info!("Starting web server at {}://{}", scheme, addr);
 
         let listener = tokio::net::TcpListener::bind(&addr).await?;
         log::info!("Web server listening on {}", addr);
 
-        axum::serve(listener, app)
-            .with_graceful_shutdown(async move {
-                let _ = shutdown_rx.recv().await;
-                log::info!("Web server shutting down gracefully");
-            })
-            .await?;
+        let data_manager = self.data_manager.clone();
+        let shutdown_signal = async move {
+            tokio::select! {
+                _ = shutdown_rx.recv() => {}
+                _ = data_manager.shutdown_requested() => {}
+            }
+            log::info!("Web server shutting down gracefully");
+        };
+
+        match tls_config {
+            Some(tls_config) => {
+                let handle = axum_server::Handle::new();
+                let shutdown_handle = handle.clone();
+                tokio::spawn(async move {
+                    shutdown_signal.await;
+                    shutdown_handle.graceful_shutdown(Some(TLS_SHUTDOWN_GRACE));
+                });
+
+                axum_server::from_tcp_rustls(listener.into_std()?, tls_config)
+                    .handle(handle)
+                    .serve(app.into_make_service())
+                    .await?;
+            }
+            None => {
+                axum::serve(listener, app)
+                    .with_graceful_shutdown(shutdown_signal)
+                    .await?;
+            }
+        }
 
         Ok(())
     }
 
+    /// Read the configured PEM certificate and key, or `None` when TLS isn't configured
+    async fn load_tls_config(
+        &self,
+    ) -> Result<Option<axum_server::tls_rustls::RustlsConfig>, Box<dyn std::error::Error + Send + Sync>> {
+        let (Some(cert_path), Some(key_path)) = (&self.config.tls_cert_path, &self.config.tls_key_path) else {
+            return Ok(None);
+        };
+
+        // Several crates link rustls; pick ring explicitly (fails harmlessly if already installed)
+        let _ = rustls::crypto::ring::default_provider().install_default();
+
+        let config = axum_server::tls_rustls::RustlsConfig::from_pem_file(cert_path, key_path)
+            .await
+            .map_err(|e| format!("Failed to load TLS certificate {} / key {}: {}", cert_path, key_path, e))?;
+        log::info!("TLS enabled with certificate {}", cert_path);
+        Ok(Some(config))
+    }
+
     pub fn shutdown(&self) {
         if let Some(tx) = &self.shutdown_tx {
             let _ = tx.send(());
@@ -77,36 +245,147 @@ impl WebServer {
     }
 
     fn create_router(&self) -> Router {
-        let cors_layer = if self.config.enable_cors {
+        let cors_layer = if !self.config.enable_cors {
+            CorsLayer::new()
+        } else if self.config.cors_allowed_origins.is_empty() {
             CorsLayer::new()
                 .allow_origin(Any)
                 .allow_methods(Any)
                 .allow_headers(Any)
         } else {
+            // Credentials can't be combined with wildcards, so methods/headers mirror the request
+            let origins = self
+                .config
+                .cors_allowed_origins
+                .iter()
+                .filter_map(|origin| origin.trim_end_matches('/').parse().ok())
+                .collect::<Vec<axum::http::HeaderValue>>();
             CorsLayer::new()
+                .allow_origin(AllowOrigin::list(origins))
+                .allow_methods(AllowMethods::mirror_request())
+                .allow_headers(AllowHeaders::mirror_request())
+                .allow_credentials(true)
         };
 
         let data_manager = self.data_manager.clone();
         let data_manager_ws = self.data_manager.clone();
         let data_manager_static = self.data_manager.clone();
 
-        Router::new()
+        let api_token: Option<Arc<str>> = self.config.api_token.as_deref().map(Arc::from);
+
+        let router = Router::new()
             .route("/api/data", get(get_user_data))
+            .route("/api/party", get(get_party_data))
+            .route("/api/ranking", get(get_ranking))
             .route("/api/enemies", get(get_enemy_data))
+            .route("/api/enemies/:id/damage", get(get_enemy_damage))
+            .route("/api/export/csv", get(export_users_csv))
             .route("/api/clear", get(clear_data))
             .route("/api/pause", get(get_pause_status).post(set_pause_status))
             .route("/api/skill/:uid", get(get_user_skill_data))
+            .route("/api/skill/:uid/:skill_id/histogram", get(get_skill_histogram))
+            .route("/api/user/:uid/reset", post(reset_user))
+            .route(
+                "/api/session/import",
+                post(import_session).layer(axum::extract::DefaultBodyLimit::max(SESSION_IMPORT_MAX_BYTES)),
+            )
+            .route("/api/timeline/:uid", get(get_user_timeline))
+            .route("/api/combatlog", get(get_combat_log))
+            .route("/api/encounters", get(get_encounters))
             .route("/api/settings", get(get_settings).post(update_settings))
             .route("/api/health", get(health_check))
+            .route("/api/version", get(get_version))
+            .route("/api/professions", get(get_professions))
+            .route("/api/capture/filter", get(get_capture_filter).post(set_capture_filter))
+            .route("/api/capture/restart", post(restart_capture))
+            .route("/api/capture/reidentify", post(reidentify_server))
+            .route("/api/interfaces", get(list_interfaces))
+            .route("/api/interfaces/select", post(select_interface))
+            .route("/api/debug/opcodes", get(get_opcode_stats))
+            .route("/api/debug/opcodes/reset", post(reset_opcode_stats))
+            .route("/api/debug/packets", get(get_debug_packets))
+            .route("/metrics", get(metrics))
             .route("/api/history/list", get(list_history_snapshots))
-            .route("/api/history/:timestamp", get(get_history_snapshot))
+            .route("/api/history/save", post(save_history_snapshot))
+            .route("/api/history/query", get(query_history))
+            .route("/api/history/:timestamp", get(get_history_snapshot).delete(delete_history_snapshot))
             .route("/ws", get(ws_handler))
-            .route("/files/*path", get(serve_static_file))
+            .route("/files/*path", get(serve_static_file));
+
+        // Only reachable with a token, otherwise any local page could stop the meter
+        let router = if api_token.is_some() {
+            router.route("/api/shutdown", post(shutdown))
+        } else {
+            router
+        };
+
+        let router = if self.config.enable_compression {
+            // Never touch WebSocket upgrade responses
+            let predicate = DefaultPredicate::new()
+                .and(SizeAbove::new(COMPRESSION_MIN_SIZE))
+                .and(|status: StatusCode, _: Version, _: &HeaderMap, _: &Extensions| {
+                    status != StatusCode::SWITCHING_PROTOCOLS
+                });
+            router.layer(CompressionLayer::new().gzip(true).br(true).compress_when(predicate))
+        } else {
+            router
+        };
+
+        router
+            .layer(Extension(self.config.ws_keepalive))
+            .layer(Extension(WsConnectionLimit::new(self.config.max_ws_connections)))
+            .layer(middleware::from_fn_with_state(api_token, require_api_token))
             .layer(cors_layer)
             .with_state(data_manager)
     }
 }
 
+// Compare tokens without returning early on the first mismatching byte, so response
+// timing doesn't reveal how much of a guess was right (the length may still leak)
+fn tokens_match(provided: &str, expected: &str) -> bool {
+    provided.len() == expected.len()
+        && provided
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+// Reject `/api/*`, `/ws` and `/metrics` requests without the configured bearer token
+async fn require_api_token(
+    axum::extract::State(api_token): axum::extract::State<Option<Arc<str>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(expected) = api_token else {
+        return next.run(request).await;
+    };
+
+    let path = request.uri().path();
+    if !(path.starts_with("/api/") || path == "/ws" || path == "/metrics") {
+        return next.run(request).await;
+    }
+
+    let header_token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    // Percent-decoded, so tokens containing reserved characters work when URL-encoded
+    let query_token = axum::extract::Query::<HashMap<String, String>>::try_from_uri(request.uri())
+        .ok()
+        .and_then(|axum::extract::Query(mut query)| query.remove("token"));
+
+    if header_token.is_some_and(|token| tokens_match(token, &expected))
+        || query_token.is_some_and(|token| tokens_match(&token, &expected))
+    {
+        next.run(request).await
+    } else {
+        log::warn!("Rejected unauthenticated request to {}", path);
+        StatusCode::UNAUTHORIZED.into_response()
+    }
+}
+
 // API handlers
 async fn get_user_data(
     axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
@@ -118,6 +397,32 @@ async fn get_user_data(
     }))
 }
 
+async fn get_party_data(
+    axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
+) -> Json<Value> {
+    Json(json!({
+        "code": 0,
+        "data": data_manager.get_party_stats()
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct RankingQuery {
+    #[serde(default)]
+    by: RankingMetric,
+    limit: Option<usize>,
+}
+
+async fn get_ranking(
+    axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
+    axum::extract::Query(query): axum::extract::Query<RankingQuery>,
+) -> Json<Value> {
+    Json(json!({
+        "code": 0,
+        "data": data_manager.get_ranking(query.by, query.limit)
+    }))
+}
+
 async fn get_enemy_data(
     axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
 ) -> Json<Value> {
@@ -128,17 +433,147 @@ async fn get_enemy_data(
     }))
 }
 
+async fn get_enemy_damage(
+    axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
+    Path(id): Path<u32>,
+) -> Result<Json<Value>, StatusCode> {
+    let (total, attackers) = data_manager
+        .get_enemy_damage_breakdown(id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let attackers: Vec<Value> = attackers
+        .into_iter()
+        .map(|(uid, damage)| {
+            let name = data_manager
+                .users
+                .get(&uid)
+                .map(|user| user.read().name.clone())
+                .unwrap_or_default();
+            let share = if total > 0 { damage as f64 / total as f64 } else { 0.0 };
+            json!({
+                "uid": uid,
+                "name": name,
+                "damage": damage,
+                "share": share
+            })
+        })
+        .collect();
+
+    Ok(Json(json!({
+        "code": 0,
+        "data": {
+            "id": id,
+            "total_damage_received": total,
+            "attackers": attackers
+        }
+    })))
+}
+
+async fn export_users_csv(
+    axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
+) -> impl IntoResponse {
+    let user_data = data_manager.get_all_users_data();
+
+    let mut uids: Vec<&u32> = user_data.keys().collect();
+    uids.sort();
+
+    let mut csv = String::from(
+        "uid,name,profession,total_damage,dps,dps_max,crit_count,lucky_count,taken_damage,dead_count\n",
+    );
+    for uid in uids {
+        let user = &user_data[uid];
+        let row = [
+            uid.to_string(),
+            csv_escape(user["name"].as_str().unwrap_or_default()),
+            csv_escape(user["profession"].as_str().unwrap_or_default()),
+            user["total_damage"]["total"].to_string(),
+            user["realtime_dps"].to_string(),
+            user["realtime_dps_max"].to_string(),
+            user["total_count"]["critical"].to_string(),
+            user["total_count"]["lucky"].to_string(),
+            user["taken_damage"].to_string(),
+            user["dead_count"].to_string(),
+        ];
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+
+    let filename = format!("ren-logs-{}.csv", chrono::Utc::now().format("%Y%m%d-%H%M%S"));
+    (
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+        ],
+        csv,
+    )
+}
+
+// Quote a CSV field if it contains a delimiter, quote or line break
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ClearScope {
+    Users,
+    Enemies,
+    #[default]
+    All,
+}
+
+/// Control commands accepted on the WebSocket, e.g. `{"cmd":"pause","value":true}`
+#[derive(Debug, Deserialize)]
+#[serde(tag = "cmd", rename_all = "lowercase")]
+enum WsCommand {
+    Pause { value: bool },
+    Clear {
+        #[serde(default)]
+        scope: ClearScope,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct ClearQuery {
+    #[serde(default)]
+    scope: ClearScope,
+}
+
 async fn clear_data(
     axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
+    axum::extract::Query(query): axum::extract::Query<ClearQuery>,
 ) -> Json<Value> {
-    data_manager.clear_all();
-    log::info!("Statistics have been cleared via API");
+    match query.scope {
+        ClearScope::Users => data_manager.clear_users(),
+        ClearScope::Enemies => data_manager.clear_enemies(),
+        ClearScope::All => data_manager.clear_all(),
+    }
+    log::info!("Statistics have been cleared via API (scope: {:?})", query.scope);
     Json(json!({
         "code": 0,
         "msg": "Statistics have been cleared!"
     }))
 }
 
+async fn reset_user(
+    axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
+    Path(uid): Path<u32>,
+) -> Result<Json<Value>, StatusCode> {
+    if !data_manager.reset_user(uid) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    log::info!("Statistics for user {} have been reset via API", uid);
+    Ok(Json(json!({
+        "code": 0,
+        "msg": format!("Statistics for user {} have been reset!", uid)
+    })))
+}
+
 async fn get_pause_status(
     axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
 ) -> Json<Value> {
@@ -166,6 +601,38 @@ async fn set_pause_status(
     }
 }
 
+/// Resolve skill names and sort by total amount (highest first)
+fn skill_list(skills: HashMap<u32, SkillStats>, skill_config: &SkillConfig) -> Vec<Value> {
+    let mut skill_stats: Vec<SkillStats> = skills
+        .into_values()
+        .map(|mut skill| {
+            skill.display_name = skill_config.get_skill_name(skill.skill_id);
+            skill
+        })
+        .collect();
+    skill_stats.sort_by_key(|skill| std::cmp::Reverse(skill.total_damage));
+
+    // Average hit, miss rate and DPS are derived on demand rather than stored on SkillStats
+    skill_stats
+        .iter()
+        .map(|skill| {
+            let mut value = serde_json::to_value(skill).unwrap_or_default();
+            value["avg_hit"] = json!(skill.avg_hit());
+            value["miss_rate"] = json!(skill.miss_rate());
+            value["dps"] = json!(skill.dps());
+            // A skill with only misses still holds the u64::MAX sentinel, which JS can't represent
+            if skill.total_count == 0 {
+                value["min_hit"] = json!(0);
+            }
+            // Served separately by /api/skill/:uid/:skill_id/histogram
+            if let Some(object) = value.as_object_mut() {
+                object.remove("hit_histogram");
+            }
+            value
+        })
+        .collect()
+}
+
 async fn get_user_skill_data(
     axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
     Path(uid): Path<u32>,
@@ -174,15 +641,12 @@ async fn get_user_skill_data(
     let user_data = data_manager.get_all_users_data();
     let user_info = user_data.get(&uid).ok_or(StatusCode::NOT_FOUND)?;
 
-    // Get skill configuration for name mapping
-    let skill_config = data_manager.skill_config.read();
-
-    // Build skill statistics from user data
-    let mut skill_stats = serde_json::Map::new();
+    let (damage_skills, healing_skills) = data_manager.get_user_skills(uid).ok_or(StatusCode::NOT_FOUND)?;
+    let summon_damage = data_manager.get_user_summon_damage(uid).unwrap_or_default();
 
-    // Extract skill information from user data if available
-    // This is a placeholder - in a real implementation, you would track skill usage
-    // and return actual skill statistics with proper name mapping
+    let skill_config = data_manager.skill_config.read();
+    let damage_skills = skill_list(damage_skills, &skill_config);
+    let healing_skills = skill_list(healing_skills, &skill_config);
 
     let response = json!({
         "code": 0,
@@ -190,73 +654,543 @@ async fn get_user_skill_data(
             "uid": uid,
             "name": user_info.get("name").and_then(|v| v.as_str()).unwrap_or("Unknown"),
             "profession": user_info.get("profession").and_then(|v| v.as_str()).unwrap_or("Unknown"),
-            "skill_count": skill_stats.len(),
-            "skills": skill_stats
+            "skill_count": damage_skills.len() + healing_skills.len(),
+            "damage_skills": damage_skills,
+            "healing_skills": healing_skills,
+            "summon_damage": summon_damage
         }
     });
 
     Ok(Json(response))
 }
 
+#[derive(Debug, Deserialize)]
+struct SkillHistogramQuery {
+    #[serde(default)]
+    kind: SkillKind,
+}
+
+async fn get_skill_histogram(
+    axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
+    Path((uid, skill_id)): Path<(u32, u32)>,
+    axum::extract::Query(query): axum::extract::Query<SkillHistogramQuery>,
+) -> Result<Json<Value>, StatusCode> {
+    let skill = data_manager.get_user_skill(uid, skill_id, query.kind).ok_or(StatusCode::NOT_FOUND)?;
+    let display_name = data_manager.skill_config.read().get_skill_name(skill_id);
+
+    Ok(Json(json!({
+        "code": 0,
+        "data": {
+            "uid": uid,
+            "skill_id": skill_id,
+            "display_name": display_name,
+            "skill_type": skill.skill_type,
+            "total_count": skill.total_count,
+            "min_hit": if skill.total_count > 0 { skill.min_hit } else { 0 },
+            "max_hit": skill.max_hit,
+            "buckets": skill.hit_histogram.buckets()
+        }
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+struct TimelineQuery {
+    bucket_ms: Option<u64>,
+}
+
+async fn get_user_timeline(
+    axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
+    Path(uid): Path<u32>,
+    axum::extract::Query(query): axum::extract::Query<TimelineQuery>,
+) -> Result<Json<Value>, StatusCode> {
+    // Buckets finer than the 100ms update tick would only contain gaps
+    let bucket_ms = query.bucket_ms.unwrap_or(1000).max(100);
+    let timeline = data_manager
+        .get_user_timeline(uid, bucket_ms)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let points: Vec<Value> = timeline
+        .into_iter()
+        .map(|(t, dps)| json!({ "t": t, "dps": dps }))
+        .collect();
+
+    Ok(Json(json!({
+        "code": 0,
+        "data": {
+            "uid": uid,
+            "bucket_ms": bucket_ms,
+            "points": points
+        }
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+struct CombatLogQuery {
+    limit: Option<usize>,
+    since: Option<i64>, // unix timestamp in milliseconds
+}
+
+async fn get_combat_log(
+    axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
+    axum::extract::Query(query): axum::extract::Query<CombatLogQuery>,
+) -> Json<Value> {
+    let limit = query.limit.unwrap_or(100);
+    let since = query.since.and_then(chrono::DateTime::from_timestamp_millis);
+    let records = data_manager.get_combat_log(limit, since);
+
+    Json(json!({
+        "code": 0,
+        "count": records.len(),
+        "data": records
+    }))
+}
+
 async fn get_settings(
     axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
 ) -> Json<Value> {
     let settings = data_manager.settings.read().clone();
     Json(json!({
         "code": 0,
-        "data": settings
-    }))
+        "data": settings
+    }))
+}
+
+// Monster ids are u32; anything else is skipped rather than truncated onto another id
+fn parse_target_id(value: &Value) -> Option<u32> {
+    let id = value.as_u64().and_then(|id| u32::try_from(id).ok());
+    if id.is_none() {
+        log::warn!("Ignoring invalid elite dummy target id: {}", value);
+    }
+    id
+}
+
+async fn update_settings(
+    axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
+    axum::extract::Json(payload): axum::extract::Json<Value>,
+) -> Result<Json<Value>, StatusCode> {
+    let mut settings = data_manager.settings.write();
+
+    if let Some(auto_clear_server) = payload.get("auto_clear_on_server_change").and_then(|v| v.as_bool()) {
+        settings.auto_clear_on_server_change = auto_clear_server;
+    }
+    if let Some(auto_clear_timeout) = payload.get("auto_clear_on_timeout").and_then(|v| v.as_bool()) {
+        settings.auto_clear_on_timeout = auto_clear_timeout;
+    }
+    if let Some(only_elite) = payload.get("only_record_elite_dummy").and_then(|v| v.as_bool()) {
+        settings.only_record_elite_dummy = only_elite;
+    }
+    if let Some(id) = payload.get("elite_dummy_target_id") {
+        if let Some(id) = parse_target_id(id) {
+            settings.elite_dummy_target_ids = vec![id];
+        }
+    }
+    if let Some(ids) = payload.get("elite_dummy_target_ids").and_then(|v| v.as_array()) {
+        settings.elite_dummy_target_ids = ids.iter().filter_map(parse_target_id).collect();
+    }
+    if let Some(window_secs) = payload.get("window_dps_secs").and_then(|v| v.as_u64()) {
+        settings.window_dps_secs = window_secs.clamp(1, MAX_WINDOW_DPS_SECS);
+    }
+    if let Some(enabled) = payload.get("segment_encounters").and_then(|v| v.as_bool()) {
+        settings.segment_encounters = enabled;
+    }
+    if let Some(gap_secs) = payload.get("encounter_idle_gap_secs").and_then(|v| v.as_u64()) {
+        settings.encounter_idle_gap_secs = gap_secs.clamp(1, MAX_ENCOUNTER_IDLE_GAP_SECS);
+    }
+    if let Some(idle_secs) = payload.get("idle_threshold_secs").and_then(|v| v.as_u64()) {
+        settings.idle_threshold_secs = idle_secs.max(1);
+    }
+
+    // Save settings asynchronously
+    let data_manager_clone = data_manager.clone();
+    tokio::spawn(async move {
+        if let Err(e) = data_manager_clone.save_settings().await {
+            log::error!("Failed to save settings: {}", e);
+        }
+    });
+
+    Ok(Json(json!({
+        "code": 0,
+        "data": settings.clone()
+    })))
+}
+
+/// Name, version and build info of the running core; build fields are null when vergen didn't provide them
+async fn get_version() -> Json<Value> {
+    Json(json!({
+        "code": 0,
+        "data": {
+            "name": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": env!("CARGO_PKG_DESCRIPTION"),
+            "build": {
+                "git_sha": option_env!("VERGEN_GIT_SHA"),
+                "build_timestamp": option_env!("VERGEN_BUILD_TIMESTAMP"),
+                "arch": std::env::consts::ARCH,
+                "os": std::env::consts::OS
+            }
+        }
+    }))
+}
+
+/// Profession names in the configured language, plus the skills that identify each sub-profession
+async fn get_professions(
+    axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
+) -> Json<Value> {
+    let professions = data_manager.names.read().professions.clone();
+
+    Json(json!({
+        "code": 0,
+        "data": {
+            "professions": professions,
+            "sub_profession_skills": crate::models::sub_profession_skill_map()
+        }
+    }))
+}
+
+async fn health_check(
+    axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
+) -> Json<Value> {
+    let now = chrono::Utc::now();
+    let uptime = now.signed_duration_since(data_manager.start_time).num_seconds();
+    let capture = PacketCapture::snapshot_counters();
+
+    // A capture that hasn't seen a packet recently is most likely dead
+    let seconds_since_last_packet = (capture.last_packet_at > 0)
+        .then(|| crate::packet_capture::unix_now_secs().saturating_sub(capture.last_packet_at));
+    let status = match seconds_since_last_packet {
+        Some(secs) if secs <= CAPTURE_IDLE_THRESHOLD_SECS => "healthy",
+        _ => "degraded",
+    };
+
+    Json(json!({
+        "code": 0,
+        "status": status,
+        "version": env!("CARGO_PKG_VERSION"),
+        "uptime_seconds": uptime,
+        "users_count": data_manager.users.len(),
+        "enemies_count": data_manager.enemies.len(),
+        "is_paused": data_manager.is_paused(),
+        "capture": capture,
+        "seconds_since_last_packet": seconds_since_last_packet,
+        "timestamp": now.to_rfc3339()
+    }))
+}
+
+async fn get_encounters(
+    axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
+) -> Json<Value> {
+    Json(json!({
+        "code": 0,
+        "data": data_manager.get_encounters()
+    }))
+}
+
+async fn get_opcode_stats(
+    axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
+) -> Json<Value> {
+    let mut message_types: Vec<(u16, u64)> = data_manager
+        .message_type_counts
+        .iter()
+        .map(|entry| (*entry.key(), *entry.value()))
+        .collect();
+    message_types.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    let mut notify_methods: Vec<(u32, u64)> = data_manager
+        .notify_method_counts
+        .iter()
+        .map(|entry| (*entry.key(), *entry.value()))
+        .collect();
+    notify_methods.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    // Unknown ids are listed too (with a null name) since those are what a patch adds
+    Json(json!({
+        "code": 0,
+        "message_types": message_types.into_iter().map(|(id, count)| json!({
+            "id": format!("0x{:04x}", id),
+            "name": MessageType::from_u16(id).map(|t| format!("{:?}", t)),
+            "count": count
+        })).collect::<Vec<_>>(),
+        "notify_methods": notify_methods.into_iter().map(|(id, count)| json!({
+            "id": format!("0x{:08x}", id),
+            "name": NotifyMethod::from_u32(id).map(|m| format!("{:?}", m)),
+            "count": count
+        })).collect::<Vec<_>>()
+    }))
+}
+
+async fn reset_opcode_stats(
+    axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
+) -> Json<Value> {
+    data_manager.reset_opcode_counts();
+    log::info!("Opcode statistics have been reset via API");
+    Json(json!({
+        "code": 0,
+        "msg": "Opcode statistics have been reset!"
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct DebugPacketsQuery {
+    limit: Option<usize>,
+}
+
+/// Raw payloads of the most recent game packets; 404 unless `debug_packet_capacity` is configured
+async fn get_debug_packets(
+    axum::extract::Query(query): axum::extract::Query<DebugPacketsQuery>,
+) -> Result<Json<Value>, StatusCode> {
+    let limit = query.limit.unwrap_or(50);
+    let packets = packet_capture::recent_debug_packets(limit).ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(json!({
+        "code": 0,
+        "data": packets.iter().map(|packet| json!({
+            "captured_at": packet.captured_at,
+            "opcode": format!("0x{:04x}", packet.opcode),
+            "size": packet.payload.len(),
+            "hex": packet.payload.iter().map(|byte| format!("{:02x}", byte)).collect::<String>()
+        })).collect::<Vec<_>>()
+    })))
+}
+
+async fn get_capture_filter(
+    axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
+) -> Json<Value> {
+    Json(json!({
+        "code": 0,
+        "filter": data_manager.get_capture_filter()
+    }))
+}
+
+async fn set_capture_filter(
+    axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
+    axum::extract::Json(payload): axum::extract::Json<Value>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let filter = payload
+        .get("filter")
+        .and_then(|v| v.as_str())
+        .map(|s| s.trim().to_string())
+        .unwrap_or_default();
+
+    if let Err(e) = packet_capture::validate_filter(&filter) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "code": 1,
+                "error": format!("Invalid capture filter: {}", e)
+            })),
+        ));
+    }
+
+    data_manager.set_capture_filter(filter.clone());
+    log::info!("Capture filter updated via API: {}", filter);
+
+    Ok(Json(json!({
+        "code": 0,
+        "filter": filter
+    })))
+}
+
+// Replace all users with a full-session JSON (uid -> user), backing up the current state first
+async fn import_session(
+    axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
+    body: axum::body::Bytes,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let bad_request = |error: String| (StatusCode::BAD_REQUEST, Json(json!({ "code": 1, "error": error })));
+
+    let session: HashMap<String, User> =
+        serde_json::from_slice(&body).map_err(|e| bad_request(format!("Invalid session JSON: {}", e)))?;
+    let (user_count, backup_path) = tokio::task::spawn_blocking(move || data_manager.import_session(session))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "code": 1, "error": e.to_string() }))))?
+        .map_err(bad_request)?;
+
+    Ok(Json(json!({
+        "code": 0,
+        "msg": format!("Imported {} users", user_count),
+        "user_count": user_count,
+        "backup_path": backup_path
+    })))
+}
+
+async fn restart_capture(
+    axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    match data_manager.restart_capture().await {
+        Ok(()) => {
+            log::info!("Packet capture restarted via API");
+            Ok(Json(json!({
+                "code": 0,
+                "msg": "Packet capture restarted"
+            })))
+        }
+        Err(e) => Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({
+                "code": 1,
+                "error": e
+            })),
+        )),
+    }
+}
+
+// Save everything before signalling; graceful shutdown still lets this response go out
+async fn shutdown(
+    axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
+) -> Json<Value> {
+    log::info!("Shutdown requested via API");
+    data_manager.persist_all().await;
+    data_manager.request_shutdown();
+    Json(json!({
+        "code": 0,
+        "msg": "Shutting down"
+    }))
+}
+
+async fn reidentify_server() -> Json<Value> {
+    let previous = packet_capture::current_server().await;
+    packet_capture::reset_server_identification().await;
+    let current = packet_capture::current_server().await;
+    log::info!("Server identification reset via API (was: {})", if previous.is_empty() { "none" } else { &previous });
+
+    Json(json!({
+        "code": 0,
+        "previous_server": previous,
+        "current_server": current
+    }))
+}
+
+async fn list_interfaces(
+    axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let interfaces = crate::network::list_network_interfaces().map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({
+                "code": 1,
+                "error": format!("Failed to list network interfaces: {}", e)
+            })),
+        )
+    })?;
+
+    Ok(Json(json!({
+        "code": 0,
+        "selected": packet_capture::interface_from_filter(&data_manager.get_capture_filter()),
+        "data": interfaces
+    })))
 }
 
-async fn update_settings(
+// Restrict capture to one adapter (`{"index": N}`), or all adapters with `{"index": null}`
+async fn select_interface(
     axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
     axum::extract::Json(payload): axum::extract::Json<Value>,
-) -> Result<Json<Value>, StatusCode> {
-    let mut settings = data_manager.settings.write();
-
-    if let Some(auto_clear_server) = payload.get("auto_clear_on_server_change").and_then(|v| v.as_bool()) {
-        settings.auto_clear_on_server_change = auto_clear_server;
-    }
-    if let Some(auto_clear_timeout) = payload.get("auto_clear_on_timeout").and_then(|v| v.as_bool()) {
-        settings.auto_clear_on_timeout = auto_clear_timeout;
-    }
-    if let Some(only_elite) = payload.get("only_record_elite_dummy").and_then(|v| v.as_bool()) {
-        settings.only_record_elite_dummy = only_elite;
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let bad_request = |error: String| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "code": 1,
+                "error": error
+            })),
+        )
+    };
+
+    let index = match payload.get("index") {
+        None | Some(Value::Null) => None,
+        Some(value) => Some(
+            value
+                .as_u64()
+                .and_then(|v| u32::try_from(v).ok())
+                .ok_or_else(|| bad_request("Interface index must be a number".to_string()))?,
+        ),
+    };
+
+    if let Some(index) = index {
+        let known = crate::network::list_network_interfaces()
+            .map(|interfaces| interfaces.iter().any(|iface| iface.index == index))
+            .unwrap_or(false);
+        if !known {
+            return Err(bad_request(format!("Unknown network interface: {}", index)));
+        }
     }
 
-    // Save settings asynchronously
-    let data_manager_clone = data_manager.clone();
-    tokio::spawn(async move {
-        if let Err(e) = data_manager_clone.save_settings().await {
-            log::error!("Failed to save settings: {}", e);
-        }
-    });
+    let filter = packet_capture::filter_for_interface(&data_manager.get_capture_filter(), index);
+    data_manager.set_capture_filter(filter.clone());
+    log::info!("Capture interface set via API: {:?}", index);
 
     Ok(Json(json!({
         "code": 0,
-        "data": settings.clone()
+        "selected": index,
+        "filter": filter
     })))
 }
 
-async fn health_check() -> Json<Value> {
-    Json(json!({
-        "code": 0,
-        "status": "healthy",
-        "timestamp": chrono::Utc::now().to_rfc3339()
-    }))
+async fn metrics(
+    axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
+) -> impl IntoResponse {
+    // Counters only: Prometheus derives rates itself, so scrapes skip the rate sampler lock
+    let counters = PacketCapture::snapshot_counters();
+
+    let metrics: [(&str, &str, &str, String); 10] = [
+        ("meter_packets_captured_total", "counter", "Packets received from the capture driver", counters.packets_captured.to_string()),
+        ("meter_packets_filtered_total", "counter", "Packets skipped by the capture filter", counters.packets_dropped.to_string()),
+        ("meter_packets_processed_total", "counter", "Game packets forwarded to the parser", counters.packets_processed.to_string()),
+        ("meter_bytes_captured_total", "counter", "Bytes received from the capture driver", counters.bytes_captured.to_string()),
+        ("meter_bytes_processed_total", "counter", "Game packet bytes forwarded to the parser", counters.bytes_processed.to_string()),
+        ("meter_tcp_cache_size", "gauge", "Segments waiting in the TCP reassembly cache", counters.tcp_cache_size.to_string()),
+        ("meter_users_count", "gauge", "Tracked users", data_manager.users.len().to_string()),
+        ("meter_enemies_count", "gauge", "Tracked enemies", data_manager.enemies.len().to_string()),
+        ("meter_paused", "gauge", "Whether statistics are paused (1) or not (0)", u8::from(data_manager.is_paused()).to_string()),
+        ("meter_party_dps", "gauge", "Combined realtime DPS of all users", data_manager.total_dps().to_string()),
+    ];
+
+    let mut body = String::new();
+    for (name, kind, help, value) in metrics {
+        body.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n{} {}\n", name, help, name, kind, name, value));
+    }
+
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        body,
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryListQuery {
+    limit: Option<usize>,
+    offset: Option<usize>,
 }
 
 async fn list_history_snapshots(
     axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
+    axum::extract::Query(query): axum::extract::Query<HistoryListQuery>,
 ) -> Json<Value> {
     let history_manager = HistoryManager::new(data_manager);
+    let limit = query.limit.unwrap_or(50);
+    let offset = query.offset.unwrap_or(0);
 
     match history_manager.list_snapshots().await {
-        Ok(snapshots) => Json(json!({
-            "code": 0,
-            "snapshots": snapshots,
-            "count": snapshots.len()
-        })),
+        Ok(snapshots) => {
+            let total = snapshots.len();
+            let mut entries = Vec::new();
+            for timestamp in snapshots.into_iter().skip(offset).take(limit) {
+                // Snapshots without a readable summary are still listed
+                let summary = history_manager.load_summary(timestamp).await.ok();
+                entries.push(json!({
+                    "timestamp": timestamp,
+                    "user_count": summary.as_ref().and_then(|s| s.get("user_count")).cloned(),
+                    "enemy_count": summary.as_ref().and_then(|s| s.get("enemy_count")).cloned()
+                }));
+            }
+
+            Json(json!({
+                "code": 0,
+                "snapshots": entries,
+                "count": entries.len(),
+                "total": total,
+                "limit": limit,
+                "offset": offset
+            }))
+        }
         Err(e) => Json(json!({
             "code": 1,
             "error": format!("Failed to list snapshots: {}", e)
@@ -264,36 +1198,220 @@ async fn list_history_snapshots(
     }
 }
 
+async fn save_history_snapshot(
+    axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
+    payload: Option<axum::extract::Json<Value>>,
+) -> Result<Json<Value>, StatusCode> {
+    let history_manager = HistoryManager::new(data_manager);
+    let timestamp = chrono::Utc::now().timestamp();
+    let label = payload
+        .as_ref()
+        .and_then(|p| p.get("label"))
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string());
+
+    match history_manager.save_snapshot_with_label(timestamp, label).await {
+        Ok(timestamp) => Ok(Json(json!({
+            "code": 0,
+            "timestamp": timestamp
+        }))),
+        Err(e) => Ok(Json(json!({
+            "code": 1,
+            "error": format!("Failed to save snapshot: {}", e)
+        }))),
+    }
+}
+
+async fn query_history(
+    axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
+    axum::extract::Query(query): axum::extract::Query<EncounterQuery>,
+) -> Json<Value> {
+    if data_manager.encounter_store.is_none() {
+        return Json(json!({
+            "code": 1,
+            "error": "SQLite history is not enabled (set data_manager.sqlite_path)"
+        }));
+    }
+
+    // rusqlite is blocking, keep it off the async workers
+    let result = tokio::task::spawn_blocking(move || {
+        data_manager
+            .encounter_store
+            .as_ref()
+            .map(|store| store.query(&query).map_err(|e| e.to_string()))
+            .unwrap_or_else(|| Ok(Vec::new()))
+    })
+    .await
+    .unwrap_or_else(|e| Err(e.to_string()));
+
+    match result {
+        Ok(rows) => Json(json!({
+            "code": 0,
+            "data": rows
+        })),
+        Err(e) => Json(json!({
+            "code": 1,
+            "error": format!("Failed to query history: {}", e)
+        })),
+    }
+}
+
+// Snapshots don't change once written, so clients may revalidate with If-None-Match
 async fn get_history_snapshot(
     axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
     Path(timestamp): Path<i64>,
-) -> Json<Value> {
+    headers: HeaderMap,
+) -> Response {
     let history_manager = HistoryManager::new(data_manager);
 
+    let validator = history_manager.snapshot_validator(timestamp).await.ok();
+    let cache_headers = validator.as_ref().map(|(etag, last_modified)| {
+        let last_modified = chrono::DateTime::<chrono::Utc>::from(*last_modified)
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string();
+        [
+            (header::ETAG, etag.clone()),
+            (header::LAST_MODIFIED, last_modified),
+            (header::CACHE_CONTROL, "no-cache".to_string()),
+        ]
+    });
+
+    if let (Some((etag, _)), Some(cache_headers)) = (&validator, &cache_headers) {
+        if etag_matches(&headers, etag) {
+            return (StatusCode::NOT_MODIFIED, cache_headers.clone()).into_response();
+        }
+    }
+
     match history_manager.load_snapshot(timestamp).await {
-        Ok(data) => Json(data),
+        Ok(data) => match cache_headers {
+            Some(cache_headers) => (cache_headers, Json(data)).into_response(),
+            None => Json(data).into_response(),
+        },
         Err(e) => Json(json!({
             "code": 1,
             "error": format!("Failed to load snapshot {}: {}", timestamp, e)
         }))
+        .into_response(),
+    }
+}
+
+// Whether `If-None-Match` lists `etag` (or is `*`)
+fn etag_matches(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|candidate| candidate.trim().trim_start_matches("W/"))
+        .any(|candidate| candidate == "*" || candidate == etag)
+}
+
+async fn delete_history_snapshot(
+    axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
+    Path(timestamp): Path<i64>,
+) -> Result<Json<Value>, StatusCode> {
+    let history_manager = HistoryManager::new(data_manager);
+
+    match history_manager.delete_snapshot(timestamp).await {
+        Ok(true) => {
+            log::info!("History snapshot {} deleted via API", timestamp);
+            Ok(Json(json!({ "code": 0 })))
+        }
+        Ok(false) => Err(StatusCode::NOT_FOUND),
+        Err(e) => Ok(Json(json!({
+            "code": 1,
+            "error": format!("Failed to delete snapshot {}: {}", timestamp, e)
+        }))),
     }
 }
 
 async fn ws_handler(
     axum::extract::State(data_manager): axum::extract::State<Arc<DataManager>>,
+    Extension(keepalive): Extension<WsKeepalive>,
+    Extension(limit): Extension<WsConnectionLimit>,
     ws: axum::extract::ws::WebSocketUpgrade,
 ) -> axum::response::Response {
-    WebSocketHandler::handle_connection(data_manager, ws).await
+    let Some(slot) = limit.try_acquire() else {
+        log::warn!("Rejected WebSocket connection: limit of {} connections reached", limit.max);
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    };
+    WebSocketHandler::handle_connection(data_manager, ws, keepalive, slot).await
 }
 
 async fn serve_static_file(
     Path(path): Path<String>,
-) -> Result<Vec<u8>, StatusCode> {
+) -> Result<Response, StatusCode> {
     let static_server = StaticFileServer::new("public".to_string());
     static_server.serve_file(&path).await
 }
 
 // WebSocket support for real-time updates
+// Next keepalive tick, or never when pings are disabled
+async fn next_tick(timer: &mut Option<tokio::time::Interval>) {
+    match timer {
+        Some(timer) => {
+            timer.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+// Resolves at the pong deadline, or never when no ping is outstanding
+async fn sleep_until_deadline(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum KeepaliveEvent {
+    SendPing,
+    PongTimedOut,
+}
+
+/// Ping schedule and pending pong deadline of one WebSocket connection
+struct KeepaliveTimer {
+    ping_timer: Option<tokio::time::Interval>,
+    pong_timeout: Duration,
+    pong_deadline: Option<tokio::time::Instant>,
+}
+
+impl KeepaliveTimer {
+    // Pings start one interval after connecting
+    fn new(keepalive: WsKeepalive) -> Self {
+        let ping_timer = keepalive.ping_interval.map(|interval| {
+            let mut timer = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+            timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            timer
+        });
+        Self {
+            ping_timer,
+            pong_timeout: keepalive.pong_timeout,
+            pong_deadline: None,
+        }
+    }
+
+    /// Wait until a ping is due or the outstanding pong is overdue; no new ping is sent while one is pending
+    async fn next_event(&mut self) -> KeepaliveEvent {
+        loop {
+            tokio::select! {
+                _ = next_tick(&mut self.ping_timer) => {
+                    if self.pong_deadline.is_none() {
+                        self.pong_deadline = Some(tokio::time::Instant::now() + self.pong_timeout);
+                        return KeepaliveEvent::SendPing;
+                    }
+                }
+                _ = sleep_until_deadline(self.pong_deadline) => return KeepaliveEvent::PongTimedOut,
+            }
+        }
+    }
+
+    fn pong_received(&mut self) {
+        self.pong_deadline = None;
+    }
+}
+
 pub struct WebSocketHandler {
     data_manager: Arc<DataManager>,
 }
@@ -306,54 +1424,100 @@ impl WebSocketHandler {
     pub async fn handle_connection(
         data_manager: Arc<DataManager>,
         ws: axum::extract::ws::WebSocketUpgrade,
+        keepalive: WsKeepalive,
+        slot: WsConnectionSlot,
     ) -> axum::response::Response {
-        ws.on_upgrade(move |socket| Self::handle_socket_static(data_manager, socket))
+        ws.on_upgrade(move |socket| async move {
+            Self::handle_socket_static(data_manager, socket, keepalive).await;
+            drop(slot);
+        })
     }
 
-    async fn handle_socket_static(data_manager: Arc<DataManager>, mut socket: axum::extract::ws::WebSocket) {
+    async fn handle_socket_static(
+        data_manager: Arc<DataManager>,
+        mut socket: axum::extract::ws::WebSocket,
+        keepalive: WsKeepalive,
+    ) {
         log::info!("WebSocket client connected");
 
-        // Send initial data
-        let user_data = data_manager.get_all_users_data();
-        let initial_msg = json!({
-            "code": 0,
-            "user": user_data
-        });
+        let mut keepalive_timer = KeepaliveTimer::new(keepalive);
+
+        // Subscribe before the initial snapshot so no tick is missed in between
+        let mut updates = data_manager.subscribe_updates();
 
-        if let Ok(msg) = serde_json::to_string(&initial_msg) {
+        // Hash of the last user payload sent to this client, keyed by uid
+        let mut last_sent: HashMap<u32, u64> = HashMap::new();
+        let mut last_full = Instant::now();
+
+        // The first message is always a full snapshot
+        let snapshot = data_manager.users_snapshot();
+        if let Some(msg) = Self::build_update_message(&snapshot, &mut last_sent, true) {
             if socket.send(axum::extract::ws::Message::Text(msg)).await.is_err() {
                 log::warn!("Failed to send initial WebSocket message");
                 return;
             }
         }
 
-        // Real-time updates loop
-        let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(100));
-
+        // Forward updates produced by the shared update task
         loop {
             tokio::select! {
-                _ = interval.tick() => {
-                    if !data_manager.is_paused() {
-                        let user_data = data_manager.get_all_users_data();
-                        let msg = json!({
-                            "code": 0,
-                            "user": user_data
-                        });
-
-                        if let Ok(msg_str) = serde_json::to_string(&msg) {
-                            if socket.send(axum::extract::ws::Message::Text(msg_str)).await.is_err() {
-                                log::warn!("Failed to send WebSocket update");
-                                break;
-                            }
+                update = updates.recv() => {
+                    let (snapshot, force_full) = match update {
+                        Ok(snapshot) => (snapshot, false),
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            // Slow client: skip the backlog and resync with the latest snapshot
+                            log::debug!("WebSocket client lagged behind by {} updates", skipped);
+                            (Arc::new(data_manager.users_snapshot()), true)
                         }
+                        Err(broadcast::error::RecvError::Closed) => {
+                            log::info!("WebSocket update channel closed");
+                            break;
+                        }
+                    };
+
+                    let full = force_full || last_full.elapsed() >= WS_FULL_SYNC_INTERVAL;
+                    if full {
+                        last_full = Instant::now();
+                    }
+
+                    let Some(msg) = Self::build_update_message(&snapshot, &mut last_sent, full) else {
+                        continue;
+                    };
+
+                    if socket.send(axum::extract::ws::Message::Text(msg)).await.is_err() {
+                        log::warn!("Failed to send WebSocket update");
+                        break;
                     }
                 }
+                event = keepalive_timer.next_event() => match event {
+                    KeepaliveEvent::SendPing => {
+                        if socket.send(axum::extract::ws::Message::Ping(Vec::new())).await.is_err() {
+                            log::warn!("Failed to send WebSocket ping");
+                            break;
+                        }
+                    }
+                    KeepaliveEvent::PongTimedOut => {
+                        log::info!("WebSocket client did not answer ping within {:?}, closing", keepalive.pong_timeout);
+                        let _ = socket.send(axum::extract::ws::Message::Close(None)).await;
+                        break;
+                    }
+                },
                 msg = socket.recv() => {
                     match msg {
+                        Some(Ok(axum::extract::ws::Message::Pong(_))) => {
+                            keepalive_timer.pong_received();
+                        }
                         Some(Ok(axum::extract::ws::Message::Close(_))) => {
                             log::info!("WebSocket client disconnected");
                             break;
                         }
+                        Some(Ok(axum::extract::ws::Message::Text(text))) => {
+                            let ack = Self::handle_ws_command(&data_manager, &text);
+                            if socket.send(axum::extract::ws::Message::Text(ack)).await.is_err() {
+                                log::warn!("Failed to send WebSocket command ack");
+                                break;
+                            }
+                        }
                         Some(Ok(_)) => {
                             // Handle other messages if needed
                         }
@@ -370,6 +1534,75 @@ impl WebSocketHandler {
             }
         }
     }
+
+    /// Run a control command sent by the client and build the ack frame
+    fn handle_ws_command(data_manager: &DataManager, text: &str) -> String {
+        let ack = match serde_json::from_str::<WsCommand>(text) {
+            Ok(WsCommand::Pause { value }) => {
+                data_manager.pause(value);
+                log::info!("Statistics {} via WebSocket", if value { "paused" } else { "resumed" });
+                json!({ "code": 0, "type": "ack", "cmd": "pause", "paused": value })
+            }
+            Ok(WsCommand::Clear { scope }) => {
+                match scope {
+                    ClearScope::Users => data_manager.clear_users(),
+                    ClearScope::Enemies => data_manager.clear_enemies(),
+                    ClearScope::All => data_manager.clear_all(),
+                }
+                log::info!("Statistics have been cleared via WebSocket (scope: {:?})", scope);
+                json!({ "code": 0, "type": "ack", "cmd": "clear" })
+            }
+            Err(e) => json!({ "code": 1, "type": "ack", "error": format!("Invalid command: {}", e) }),
+        };
+        ack.to_string()
+    }
+
+    /// Build a full snapshot or a delta of users changed or removed since `last_sent`.
+    /// Returns `None` when a delta would be empty.
+    fn build_update_message(
+        snapshot: &UsersSnapshot,
+        last_sent: &mut HashMap<u32, u64>,
+        full: bool,
+    ) -> Option<String> {
+        let msg = if full {
+            last_sent.clone_from(&snapshot.hashes);
+            json!({
+                "code": 0,
+                "type": "full",
+                "user": snapshot.users
+            })
+        } else {
+            let mut changed = serde_json::Map::new();
+            for (uid, hash) in &snapshot.hashes {
+                if last_sent.get(uid) != Some(hash) {
+                    last_sent.insert(*uid, *hash);
+                    if let Some(user) = snapshot.users.get(uid) {
+                        changed.insert(uid.to_string(), user.clone());
+                    }
+                }
+            }
+
+            // Users cleared or reset since the last message, so clients can drop them
+            let mut removed: Vec<u32> = last_sent.keys().filter(|uid| !snapshot.hashes.contains_key(uid)).copied().collect();
+            removed.sort_unstable();
+            for uid in &removed {
+                last_sent.remove(uid);
+            }
+
+            if changed.is_empty() && removed.is_empty() {
+                return None;
+            }
+
+            json!({
+                "code": 0,
+                "type": "delta",
+                "users": changed,
+                "removed": removed
+            })
+        };
+
+        serde_json::to_string(&msg).ok()
+    }
 }
 
 // Static file serving (simplified)
@@ -382,16 +1615,66 @@ impl StaticFileServer {
         Self { web_root }
     }
 
-    pub async fn serve_file(&self, path: &str) -> Result<Vec<u8>, StatusCode> {
-        let file_path = format!("{}/{}", self.web_root, path.trim_start_matches('/'));
+    pub async fn serve_file(&self, path: &str) -> Result<Response, StatusCode> {
+        let relative = std::path::Path::new(path.trim_start_matches('/'));
+
+        // Only plain relative components are allowed (no `..`, roots or drive prefixes)
+        if !relative.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+            log::warn!("Rejected static file request outside web root: {}", path);
+            return Err(StatusCode::FORBIDDEN);
+        }
+
+        let root = tokio::fs::canonicalize(&self.web_root)
+            .await
+            .map_err(|_| StatusCode::NOT_FOUND)?;
+        let file_path = tokio::fs::canonicalize(root.join(relative))
+            .await
+            .map_err(|_| StatusCode::NOT_FOUND)?;
+
+        // Symlinks may still point outside the web root
+        if !file_path.starts_with(&root) {
+            log::warn!("Rejected static file request outside web root: {}", path);
+            return Err(StatusCode::FORBIDDEN);
+        }
 
         match tokio::fs::read(&file_path).await {
-            Ok(content) => Ok(content),
+            Ok(content) => Ok((
+                [(header::CONTENT_TYPE, content_type_for(&file_path))],
+                content,
+            )
+                .into_response()),
             Err(_) => Err(StatusCode::NOT_FOUND),
         }
     }
 }
 
+// MIME type for the overlay's static assets, by file extension
+fn content_type_for(path: &std::path::Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+
+    match extension.as_deref() {
+        Some("html") | Some("htm") => "text/html; charset=utf-8",
+        Some("js") | Some("mjs") => "text/javascript; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("json") | Some("map") => "application/json",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("wasm") => "application/wasm",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("ico") => "image/x-icon",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("ttf") => "font/ttf",
+        _ => "application/octet-stream",
+    }
+}
+
 // History data management
 pub struct HistoryManager {
     data_manager: Arc<DataManager>,
@@ -411,47 +1694,93 @@ impl HistoryManager {
         self
     }
 
-    pub async fn save_snapshot(&self, timestamp: i64) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    pub async fn save_snapshot(&self, timestamp: i64) -> Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+        self.save_snapshot_with_label(timestamp, None).await
+    }
+
+    /// Save a snapshot, optionally naming it. If a snapshot for the same second already
+    /// exists the next free second is used; returns the timestamp the snapshot was saved under.
+    pub async fn save_snapshot_with_label(
+        &self,
+        timestamp: i64,
+        label: Option<String>,
+    ) -> Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+        use crate::utils::write_atomic;
         use std::fs;
-        use tokio::fs as async_fs;
 
         // Create logs directory if it doesn't exist
         if !fs::metadata(&self.history_dir).is_ok() {
             fs::create_dir_all(&self.history_dir)?;
         }
 
-        // Create timestamp directory
-        let timestamp_dir = format!("{}/{}", self.history_dir, timestamp);
-        if !fs::metadata(&timestamp_dir).is_ok() {
-            fs::create_dir_all(&timestamp_dir)?;
-        }
+        // Create timestamp directory; create_dir is atomic, so concurrent saves
+        // within the same second each claim their own snapshot
+        let mut timestamp = timestamp;
+        let mut attempts = 0;
+        let timestamp_dir = loop {
+            let dir = format!("{}/{}", self.history_dir, timestamp);
+            match fs::create_dir(&dir) {
+                Ok(()) => break dir,
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists && attempts < MAX_SNAPSHOT_SLOT_ATTEMPTS => {
+                    attempts += 1;
+                    timestamp += 1;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        };
 
         // Save user data
         let users_file = format!("{}/users.json", timestamp_dir);
         let user_data = self.data_manager.get_all_users_data();
         let users_content = serde_json::to_string_pretty(&user_data)?;
-        async_fs::write(&users_file, users_content).await?;
 
         // Save enemy data
         let enemies_file = format!("{}/enemies.json", timestamp_dir);
         let enemy_data = self.data_manager.get_all_enemies_data();
         let enemies_content = serde_json::to_string_pretty(&enemy_data)?;
-        async_fs::write(&enemies_file, enemies_content).await?;
 
         // Save summary
         let summary_file = format!("{}/summary.json", timestamp_dir);
         let summary = json!({
             "timestamp": timestamp,
+            "label": label,
             "user_count": user_data.len(),
             "enemy_count": enemy_data.len(),
             "total_users": user_data.keys().collect::<Vec<_>>(),
             "total_enemies": enemy_data.keys().collect::<Vec<_>>()
         });
         let summary_content = serde_json::to_string_pretty(&summary)?;
-        async_fs::write(&summary_file, summary_content).await?;
+        tokio::task::spawn_blocking(move || {
+            write_atomic(&users_file, users_content)?;
+            write_atomic(&enemies_file, enemies_content)?;
+            write_atomic(&summary_file, summary_content)
+        })
+        .await??;
 
         log::info!("History snapshot saved for timestamp: {}", timestamp);
-        Ok(())
+
+        if self.data_manager.encounter_store.is_some() {
+            let data_manager = self.data_manager.clone();
+            let label = label.clone();
+            let recorded = tokio::task::spawn_blocking(move || {
+                data_manager
+                    .encounter_store
+                    .as_ref()
+                    .map_or(Ok(()), |store| store.record_encounter(timestamp, label.as_deref(), &data_manager))
+            })
+            .await;
+            match recorded {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => log::error!("Failed to record encounter {} to SQLite: {}", timestamp, e),
+                Err(e) => log::error!("Failed to record encounter {} to SQLite: {}", timestamp, e),
+            }
+        }
+
+        if let Some(discord) = &self.data_manager.discord {
+            let title = label.unwrap_or_else(|| "Encounter summary".to_string());
+            discord.notify_encounter(&self.data_manager, &title);
+        }
+        Ok(timestamp)
     }
 
     pub async fn load_snapshot(&self, timestamp: i64) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
@@ -482,6 +1811,42 @@ impl HistoryManager {
         }))
     }
 
+    /// Validator for a saved snapshot, derived from the size and mtime of its files
+    /// so that re-saving the same timestamp changes it. Also returns the newest mtime.
+    pub async fn snapshot_validator(&self, timestamp: i64) -> std::io::Result<(String, std::time::SystemTime)> {
+        use std::hash::{DefaultHasher, Hash, Hasher};
+
+        let mut hasher = DefaultHasher::new();
+        let mut last_modified = std::time::SystemTime::UNIX_EPOCH;
+        for file in ["users.json", "enemies.json", "summary.json"] {
+            let metadata = tokio::fs::metadata(format!("{}/{}/{}", self.history_dir, timestamp, file)).await?;
+            let modified = metadata.modified()?;
+            metadata.len().hash(&mut hasher);
+            modified.hash(&mut hasher);
+            last_modified = last_modified.max(modified);
+        }
+
+        Ok((format!("\"{}-{:x}\"", timestamp, hasher.finish()), last_modified))
+    }
+
+    /// Remove a snapshot directory. Returns `Ok(false)` if it doesn't exist.
+    pub async fn delete_snapshot(&self, timestamp: i64) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        // Built from a parsed i64, so the path can't escape the history directory
+        let snapshot_dir = format!("{}/{}", self.history_dir, timestamp);
+
+        match tokio::fs::remove_dir_all(&snapshot_dir).await {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub async fn load_summary(&self, timestamp: i64) -> Result<Value, Box<dyn std::error::Error + Send + Sync>> {
+        let summary_file = format!("{}/{}/summary.json", self.history_dir, timestamp);
+        let content = tokio::fs::read_to_string(&summary_file).await?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
     pub async fn list_snapshots(&self) -> Result<Vec<i64>, Box<dyn std::error::Error + Send + Sync>> {
         use std::fs;
 
@@ -531,17 +1896,19 @@ impl HistoryManager {
         let cutoff = now - (keep_days * 24 * 60 * 60);
 
         if let Ok(entries) = fs::read_dir(&self.history_dir) {
-            for entry in entries {
-                if let Ok(entry) = entry {
-                    if let Ok(metadata) = entry.metadata() {
-                        if let Ok(modified) = metadata.modified()?.duration_since(UNIX_EPOCH) {
-                            let modified_secs = modified.as_secs() as i64;
-                            if modified_secs < cutoff {
-                                if let Err(e) = fs::remove_dir_all(entry.path()) {
-                                    log::warn!("Failed to remove old snapshot: {:?}", e);
-                                } else {
-                                    log::info!("Removed old snapshot: {:?}", entry.file_name());
-                                }
+            for entry in entries.flatten() {
+                let Some(timestamp) = entry.file_name().to_str().and_then(|name| name.parse::<i64>().ok()) else {
+                    continue;
+                };
+
+                if let Ok(metadata) = entry.metadata() {
+                    if let Ok(modified) = metadata.modified()?.duration_since(UNIX_EPOCH) {
+                        let modified_secs = modified.as_secs() as i64;
+                        if modified_secs < cutoff {
+                            if let Err(e) = self.delete_snapshot(timestamp).await {
+                                log::warn!("Failed to remove old snapshot: {:?}", e);
+                            } else {
+                                log::info!("Removed old snapshot: {:?}", entry.file_name());
                             }
                         }
                     }
@@ -552,3 +1919,357 @@ impl HistoryManager {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_escape() {
+        assert_eq!(csv_escape("Alice"), "Alice");
+        assert_eq!(csv_escape("a,b"), "\"a,b\"");
+        assert_eq!(csv_escape("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn test_ws_commands() {
+        let data_manager = DataManager::new();
+
+        let ack: Value = serde_json::from_str(&WebSocketHandler::handle_ws_command(&data_manager, r#"{"cmd":"pause","value":true}"#)).unwrap();
+        assert_eq!(ack["code"], 0);
+        assert_eq!(ack["paused"], true);
+        assert!(data_manager.is_paused());
+
+        data_manager.get_or_create_enemy(75);
+        let ack: Value = serde_json::from_str(&WebSocketHandler::handle_ws_command(&data_manager, r#"{"cmd":"clear","scope":"enemies"}"#)).unwrap();
+        assert_eq!(ack["cmd"], "clear");
+        assert!(data_manager.enemies.is_empty());
+
+        let ack: Value = serde_json::from_str(&WebSocketHandler::handle_ws_command(&data_manager, r#"{"cmd":"explode"}"#)).unwrap();
+        assert_eq!(ack["code"], 1);
+    }
+
+    #[test]
+    fn test_content_type_for() {
+        use std::path::Path;
+        assert_eq!(content_type_for(Path::new("app.js")), "text/javascript; charset=utf-8");
+        assert_eq!(content_type_for(Path::new("style.CSS")), "text/css; charset=utf-8");
+        assert_eq!(content_type_for(Path::new("core.wasm")), "application/wasm");
+        assert_eq!(content_type_for(Path::new("icon.svg")), "image/svg+xml");
+        assert_eq!(content_type_for(Path::new("blob.bin")), "application/octet-stream");
+        assert_eq!(content_type_for(Path::new("README")), "application/octet-stream");
+    }
+
+    // Creates `<tmp>/<name>/public/index.html` and `<tmp>/<name>/secret.json`
+    fn static_fixture(name: &str) -> std::path::PathBuf {
+        let base = std::env::temp_dir().join(format!("meter-core-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        std::fs::create_dir_all(base.join("public")).unwrap();
+        std::fs::write(base.join("public/index.html"), "ok").unwrap();
+        std::fs::write(base.join("secret.json"), "secret").unwrap();
+        base
+    }
+
+    #[tokio::test]
+    async fn test_serve_file_within_root() {
+        let base = static_fixture("static-ok");
+        let server = StaticFileServer::new(base.join("public").to_string_lossy().to_string());
+
+        let response = server.serve_file("index.html").await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/html; charset=utf-8");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"ok");
+
+        assert!(server.serve_file("/index.html").await.is_ok());
+        assert_eq!(server.serve_file("missing.html").await.unwrap_err(), StatusCode::NOT_FOUND);
+
+        std::fs::remove_dir_all(base).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_serve_file_rejects_traversal() {
+        let base = static_fixture("static-traversal");
+        let server = StaticFileServer::new(base.join("public").to_string_lossy().to_string());
+
+        assert_eq!(server.serve_file("../secret.json").await.unwrap_err(), StatusCode::FORBIDDEN);
+        assert_eq!(server.serve_file("a/../../secret.json").await.unwrap_err(), StatusCode::FORBIDDEN);
+
+        let absolute = base.join("secret.json").to_string_lossy().to_string();
+        assert!(server.serve_file(&absolute).await.is_err());
+
+        std::fs::remove_dir_all(base).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_serve_file_rejects_symlink_escape() {
+        let base = static_fixture("static-symlink");
+        std::os::unix::fs::symlink(base.join("secret.json"), base.join("public/link.json")).unwrap();
+        let server = StaticFileServer::new(base.join("public").to_string_lossy().to_string());
+
+        assert_eq!(server.serve_file("link.json").await.unwrap_err(), StatusCode::FORBIDDEN);
+
+        std::fs::remove_dir_all(base).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_requires_token_and_saves_first() {
+        use tower::Service;
+
+        let dir = std::env::temp_dir().join(format!("meter-core-shutdown-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut data_manager = DataManager::new();
+        data_manager.cache_file_path = dir.join("users.json").to_string_lossy().to_string();
+        data_manager.settings_file_path = dir.join("settings.json").to_string_lossy().to_string();
+        let data_manager = Arc::new(data_manager);
+
+        let shutdown_request = || {
+            Request::builder()
+                .method("POST")
+                .uri("/api/shutdown")
+                .header(header::AUTHORIZATION, "Bearer secret")
+                .body(axum::body::Body::empty())
+                .unwrap()
+        };
+
+        let mut open = WebServer::new(data_manager.clone()).create_router();
+        let response = open.call(shutdown_request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(!*data_manager.subscribe_shutdown().borrow());
+
+        let config = WebServerConfig { api_token: Some("secret".to_string()), ..Default::default() };
+        let mut guarded = WebServer::new(data_manager.clone()).with_config(config).create_router();
+        let response = guarded.call(shutdown_request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(*data_manager.subscribe_shutdown().borrow());
+        assert!(dir.join("settings.json").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_api_token_checks_header_query_and_metrics() {
+        use tower::Service;
+
+        let config = WebServerConfig { api_token: Some("a b&c".to_string()), ..Default::default() };
+        let mut router = WebServer::new(Arc::new(DataManager::new())).with_config(config).create_router();
+        let mut status = |uri: &str, bearer: Option<&str>| {
+            let mut request = Request::builder().uri(uri);
+            if let Some(token) = bearer {
+                request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+            }
+            let response = router.call(request.body(axum::body::Body::empty()).unwrap());
+            async move { response.await.unwrap().status() }
+        };
+
+        assert_eq!(status("/metrics", None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status("/metrics", Some("a b&c")).await, StatusCode::OK);
+        assert_eq!(status("/api/health", Some("a b&d")).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status("/api/health", Some("a b&c ")).await, StatusCode::UNAUTHORIZED);
+
+        // The query token is percent-decoded
+        assert_eq!(status("/api/health?token=a%20b%26c", None).await, StatusCode::OK);
+        assert_eq!(status("/api/health?token=a%20b", None).await, StatusCode::UNAUTHORIZED);
+
+        assert!(tokens_match("secret", "secret"));
+        assert!(!tokens_match("secreT", "secret"));
+        assert!(!tokens_match("secret1", "secret"));
+    }
+
+    #[tokio::test]
+    async fn test_update_settings_skips_invalid_target_ids() {
+        use tower::Service;
+
+        let dir = std::env::temp_dir().join(format!("meter-core-target-ids-{}", std::process::id()));
+        let mut data_manager = DataManager::new();
+        data_manager.settings_file_path = dir.join("settings.json").to_string_lossy().to_string();
+        let data_manager = Arc::new(data_manager);
+        let mut router = WebServer::new(data_manager.clone()).create_router();
+        let mut post = |body: Value| {
+            let request = Request::builder()
+                .method("POST")
+                .uri("/api/settings")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(axum::body::Body::from(body.to_string()))
+                .unwrap();
+            router.call(request)
+        };
+
+        let response = post(json!({ "elite_dummy_target_ids": [75, 4_294_967_296u64, -1, "76", 77] })).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(data_manager.settings.read().elite_dummy_target_ids, vec![75, 77]);
+
+        // An out-of-range single id leaves the configured ids alone
+        post(json!({ "elite_dummy_target_id": 4_294_967_371u64 })).await.unwrap();
+        assert_eq!(data_manager.settings.read().elite_dummy_target_ids, vec![75, 77]);
+        post(json!({ "elite_dummy_target_id": 80 })).await.unwrap();
+        assert_eq!(data_manager.settings.read().elite_dummy_target_ids, vec![80]);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_update_delta_lists_removed_users() {
+        let snapshot = |users: &[(u32, u64)]| UsersSnapshot {
+            users: users.iter().map(|(uid, hash)| (*uid, json!({ "hash": hash }))).collect(),
+            hashes: users.iter().copied().collect(),
+        };
+        let parse = |msg: Option<String>| serde_json::from_str::<Value>(&msg.unwrap()).unwrap();
+        let mut last_sent = HashMap::new();
+
+        let full = parse(WebSocketHandler::build_update_message(&snapshot(&[(1, 10), (2, 20)]), &mut last_sent, true));
+        assert_eq!(full["type"], "full");
+        assert!(WebSocketHandler::build_update_message(&snapshot(&[(1, 10), (2, 20)]), &mut last_sent, false).is_none());
+
+        // User 2 was cleared and user 1 changed
+        let delta = parse(WebSocketHandler::build_update_message(&snapshot(&[(1, 11)]), &mut last_sent, false));
+        assert_eq!(delta["type"], "delta");
+        assert_eq!(delta["users"]["1"]["hash"], 11);
+        assert_eq!(delta["removed"], json!([2]));
+
+        // A removal alone still produces a delta, and is only reported once
+        let delta = parse(WebSocketHandler::build_update_message(&snapshot(&[]), &mut last_sent, false));
+        assert_eq!(delta["users"], json!({}));
+        assert_eq!(delta["removed"], json!([1]));
+        assert!(WebSocketHandler::build_update_message(&snapshot(&[]), &mut last_sent, false).is_none());
+    }
+
+    #[test]
+    fn test_skill_list_hides_min_hit_sentinel() {
+        let now = chrono::Utc::now();
+        let mut user = User::new(1);
+        user.add_miss(100, "fire".to_string(), now);
+        user.add_damage(200, "fire".to_string(), 50, false, false, false, 50, now);
+
+        let skills = skill_list(user.damage_skills, &SkillConfig::new());
+        let by_id = |id: u32| skills.iter().find(|skill| skill["skill_id"] == id).unwrap();
+        assert_eq!(by_id(100)["min_hit"], 0);
+        assert_eq!(by_id(200)["min_hit"], 50);
+        assert!(by_id(100).get("hit_histogram").is_none());
+    }
+
+    #[test]
+    fn test_etag_matches() {
+        let mut headers = HeaderMap::new();
+        assert!(!etag_matches(&headers, "\"1-a\""));
+
+        headers.insert(header::IF_NONE_MATCH, "\"0-b\", W/\"1-a\"".parse().unwrap());
+        assert!(etag_matches(&headers, "\"1-a\""));
+        assert!(!etag_matches(&headers, "\"1-c\""));
+
+        headers.insert(header::IF_NONE_MATCH, "*".parse().unwrap());
+        assert!(etag_matches(&headers, "\"1-c\""));
+    }
+
+    #[test]
+    fn test_ws_connection_limit() {
+        let limit = WsConnectionLimit::new(2);
+        let first = limit.try_acquire().unwrap();
+        let _second = limit.try_acquire().unwrap();
+        assert!(limit.try_acquire().is_none());
+        assert_eq!(limit.active(), 2);
+
+        drop(first);
+        assert_eq!(limit.active(), 1);
+        assert!(limit.try_acquire().is_some());
+
+        let unlimited = WsConnectionLimit::new(0);
+        let slots: Vec<_> = (0..100).filter_map(|_| unlimited.try_acquire()).collect();
+        assert_eq!(slots.len(), 100);
+    }
+
+    #[tokio::test]
+    async fn test_snapshots_in_same_second_get_separate_slots() {
+        let dir = std::env::temp_dir().join(format!("meter-core-history-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let history = HistoryManager::new(Arc::new(DataManager::new())).with_history_dir(dir.to_string_lossy().to_string());
+
+        assert_eq!(history.save_snapshot(1000).await.unwrap(), 1000);
+        assert_eq!(history.save_snapshot_with_label(1000, Some("second".to_string())).await.unwrap(), 1001);
+        assert_eq!(history.list_snapshots().await.unwrap(), vec![1001, 1000]);
+        assert_eq!(history.load_summary(1001).await.unwrap()["label"], "second");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keepalive_pings_and_times_out() {
+        let keepalive = WsKeepalive {
+            ping_interval: Some(Duration::from_secs(30)),
+            pong_timeout: Duration::from_secs(10),
+        };
+        let start = tokio::time::Instant::now();
+        let mut timer = KeepaliveTimer::new(keepalive);
+
+        // A client that answers keeps getting pinged every interval
+        assert_eq!(timer.next_event().await, KeepaliveEvent::SendPing);
+        assert_eq!(start.elapsed(), Duration::from_secs(30));
+        timer.pong_received();
+        assert_eq!(timer.next_event().await, KeepaliveEvent::SendPing);
+        assert_eq!(start.elapsed(), Duration::from_secs(60));
+
+        // Without a pong the connection times out before the next ping is due
+        assert_eq!(timer.next_event().await, KeepaliveEvent::PongTimedOut);
+        assert_eq!(start.elapsed(), Duration::from_secs(70));
+
+        // Disabled pings never fire
+        let mut disabled = KeepaliveTimer::new(WsKeepalive { ping_interval: None, ..keepalive });
+        assert!(tokio::time::timeout(Duration::from_secs(3600), disabled.next_event()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_server_starts_with_tls() {
+        let dir = std::env::temp_dir().join(format!("meter-core-tls-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let cert_path = dir.join("cert.pem");
+        let key_path = dir.join("key.pem");
+        std::fs::write(&cert_path, cert.cert.pem()).unwrap();
+        std::fs::write(&key_path, cert.key_pair.serialize_pem()).unwrap();
+
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let config = WebServerConfig {
+            port,
+            tls_cert_path: Some(cert_path.to_string_lossy().to_string()),
+            tls_key_path: Some(key_path.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let data_manager = Arc::new(DataManager::new());
+        let mut server = WebServer::new(data_manager.clone()).with_config(config);
+        let server_task = tokio::spawn(async move { server.start().await.map_err(|e| e.to_string()) });
+
+        let client = reqwest::Client::builder().danger_accept_invalid_certs(true).build().unwrap();
+        let url = format!("https://127.0.0.1:{}/api/health", port);
+        let mut response = None;
+        for _ in 0..50 {
+            if let Ok(ok) = client.get(&url).send().await {
+                response = Some(ok);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(response.expect("TLS server did not come up").status(), reqwest::StatusCode::OK);
+
+        // Plain HTTP is not served on a TLS port
+        let plain = client.get(format!("http://127.0.0.1:{}/api/health", port)).send().await;
+        assert!(!matches!(plain, Ok(r) if r.status().is_success()));
+
+        data_manager.request_shutdown();
+        server_task.await.unwrap().unwrap();
+
+        // A missing key fails startup and names the offending files
+        let config = WebServerConfig {
+            port,
+            tls_cert_path: Some(cert_path.to_string_lossy().to_string()),
+            tls_key_path: Some(dir.join("missing-key.pem").to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let err = WebServer::new(Arc::new(DataManager::new())).with_config(config).start().await.unwrap_err().to_string();
+        assert!(err.contains("Failed to load TLS certificate"), "{}", err);
+        assert!(err.contains("missing-key.pem"), "{}", err);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -4,16 +4,17 @@ use anyhow::Result;
 
 use log::{error, info, warn};
 use meter_core::MeterCore;
+use meter_core::data_manager::EncounterEvent;
 
 use std::sync::Arc;
 
 use std::time::Duration;
-use tauri::AppHandle;
+use tauri::{AppHandle, Emitter};
 
 static METER_CORE_INSTANCE: std::sync::OnceLock<Arc<tokio::sync::Mutex<Option<MeterCore>>>> =
     std::sync::OnceLock::new();
 
-pub async fn start_with_retry(_app: AppHandle, max_retries: u32) -> Result<()> {
+pub async fn start_with_retry(app: AppHandle, max_retries: u32) -> Result<()> {
     let instance = METER_CORE_INSTANCE.get_or_init(|| Arc::new(tokio::sync::Mutex::new(None)));
 
     for attempt in 1..=max_retries {
@@ -26,6 +27,7 @@ pub async fn start_with_retry(_app: AppHandle, max_retries: u32) -> Result<()> {
         match MeterCore::new_with_config().await {
             Ok(mut meter_core) => match meter_core.start().await {
                 Ok(_) => {
+                    forward_encounter_events(app.clone(), &meter_core);
                     *instance.lock().await = Some(meter_core);
                     info!("Meter Core started successfully");
                     return Ok(());
@@ -61,6 +63,27 @@ pub async fn start_with_retry(_app: AppHandle, max_retries: u32) -> Result<()> {
     Ok(())
 }
 
+/// Emit `on-combat-start` / `on-combat-end` (with the encounter summary) to the frontend
+fn forward_encounter_events(app: AppHandle, meter_core: &MeterCore) {
+    let mut events = meter_core.get_data_manager().subscribe_encounter_events();
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(EncounterEvent::Started { start }) => {
+                    let _ = app.emit("on-combat-start", start);
+                }
+                Ok(EncounterEvent::Ended(segment)) => {
+                    let _ = app.emit("on-combat-end", segment);
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("Missed {} encounter events", skipped);
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
 pub async fn stop() -> Result<()> {
     let instance = METER_CORE_INSTANCE.get_or_init(|| Arc::new(tokio::sync::Mutex::new(None)));
 
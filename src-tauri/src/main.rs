@@ -6,9 +6,15 @@ mod live;
 
 use anyhow::Result;
 use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
 use std::sync::atomic::{AtomicBool, Ordering};
-use tauri::{Emitter, Manager, State, menu::MenuBuilder};
-use tauri_plugin_window_state::{StateFlags, WindowExt};
+use tauri::{
+    Emitter, Manager, State,
+    menu::{MenuBuilder, SubmenuBuilder},
+};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutState};
+use tauri_plugin_window_state::{AppHandleExt, StateFlags, WindowExt};
 
 use crate::app::autostart::AutoLaunchManager;
 
@@ -22,9 +28,45 @@ const WINDOW_STATE_FLAGS: StateFlags = StateFlags::from_bits_truncate(
         | StateFlags::VISIBLE.bits(),
 );
 
+// Lower bound keeps the meter from becoming fully invisible
+const MIN_OPACITY: f64 = 0.2;
+const MAX_OPACITY: f64 = 1.0;
+const WINDOW_SETTINGS_FILE: &str = "window_settings.json";
+
 struct AlwaysOnTop(AtomicBool);
 struct ClickThrough(AtomicBool);
 struct DebugMode(AtomicBool);
+struct Opacity(Mutex<f64>);
+struct Hotkeys(Mutex<HotkeyConfig>);
+
+/// Meter window settings not covered by the window-state plugin
+#[derive(Debug, Serialize, Deserialize)]
+struct WindowSettings {
+    #[serde(default = "default_opacity")]
+    opacity: f64,
+    #[serde(default)]
+    hotkeys: HotkeyConfig,
+}
+
+fn default_opacity() -> f64 {
+    MAX_OPACITY
+}
+
+/// Global shortcuts in accelerator syntax (e.g. "Ctrl+Alt+C"); an empty string disables one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct HotkeyConfig {
+    clickthrough: String,
+    always_on_top: String,
+}
+
+impl Default for HotkeyConfig {
+    fn default() -> Self {
+        Self {
+            clickthrough: "Ctrl+Alt+C".to_string(),
+            always_on_top: "Ctrl+Alt+P".to_string(),
+        }
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -38,7 +80,15 @@ async fn main() -> Result<()> {
 
     tauri::Builder::default()
         .plugin(tauri_plugin_clipboard_manager::init())
-        .plugin(tauri_plugin_global_shortcut::Builder::new().build())
+        .plugin(
+            tauri_plugin_global_shortcut::Builder::new()
+                .with_handler(|app, shortcut, event| {
+                    if event.state() == ShortcutState::Pressed {
+                        handle_hotkey(app, shortcut);
+                    }
+                })
+                .build(),
+        )
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_process::init())
         .plugin(tauri_plugin_single_instance::init(|_app, _argv, _cwd| {}))
@@ -53,6 +103,10 @@ async fn main() -> Result<()> {
         .invoke_handler(tauri::generate_handler![
             toggle_always_on_top,
             toggle_clickthrough,
+            set_opacity,
+            get_opacity,
+            get_hotkeys,
+            set_hotkeys,
         ])
         .setup(|app| {
             info!("starting app v{}", app.package_info().version);
@@ -65,6 +119,10 @@ async fn main() -> Result<()> {
             app.manage(AutoLaunchManager::new(&app.package_info().name, &app_path));
 
             // let settings = read_settings(app.handle()).ok();
+            let window_settings = load_window_settings(app.handle());
+            app.manage(Opacity(Mutex::new(window_settings.opacity)));
+            app.manage(Hotkeys(Mutex::new(window_settings.hotkeys)));
+            register_hotkeys(app.handle());
 
             let meter_window = app.get_webview_window(METER_WINDOW_LABEL).unwrap();
             meter_window
@@ -156,29 +214,190 @@ async fn cleanup_on_shutdown() {
 }
 
 #[tauri::command]
-fn toggle_always_on_top(window: tauri::Window, state: State<AlwaysOnTop>) {
-    let always_on_top = &state.0;
-    let new_state = !always_on_top.load(Ordering::Acquire);
-    always_on_top.store(new_state, Ordering::Release);
-    window.set_always_on_top(new_state).unwrap();
-    let _ = window.emit("on-pinned", new_state);
+fn toggle_always_on_top(app: tauri::AppHandle) {
+    toggle_always_on_top_state(&app);
 }
 
 #[tauri::command]
-fn toggle_clickthrough(app: tauri::AppHandle, state: State<ClickThrough>) {
-    let clickthrough = &state.0;
-    let new_state = !clickthrough.load(Ordering::Acquire);
-    clickthrough.store(new_state, Ordering::Release);
+fn toggle_clickthrough(app: tauri::AppHandle) {
+    toggle_clickthrough_state(&app);
+}
+
+fn toggle_always_on_top_state(app: &tauri::AppHandle) {
+    let always_on_top_state = app.state::<AlwaysOnTop>();
+    let new_state = !always_on_top_state.0.load(Ordering::Acquire);
+    always_on_top_state.0.store(new_state, Ordering::Release);
+
+    // Update main window
+    if let Some(window) = app.get_webview_window(METER_WINDOW_LABEL) {
+        let _ = window.set_always_on_top(new_state);
+        let _ = window.emit("on-pinned", new_state);
+    }
+
+    refresh_tray_menu(app);
+    info!("Always on top toggled to: {}", new_state);
+}
+
+fn toggle_clickthrough_state(app: &tauri::AppHandle) {
+    let clickthrough_state = app.state::<ClickThrough>();
+    let new_state = !clickthrough_state.0.load(Ordering::Acquire);
+    clickthrough_state.0.store(new_state, Ordering::Release);
 
     // Update main window
     if let Some(meter_window) = app.get_webview_window(METER_WINDOW_LABEL) {
-        meter_window.set_ignore_cursor_events(new_state).unwrap();
+        let _ = meter_window.set_ignore_cursor_events(new_state);
         let _ = meter_window.emit("on-clickthrough", new_state);
     }
 
+    refresh_tray_menu(app);
     info!("Clickthrough toggled to: {}", new_state);
 }
 
+fn refresh_tray_menu(app: &tauri::AppHandle) {
+    let updated_menu = create_tray_menu(app);
+    if let Some(tray) = app.tray_by_id("main") {
+        let _ = tray.set_menu(Some(updated_menu));
+    }
+}
+
+#[tauri::command]
+fn get_hotkeys(state: State<Hotkeys>) -> HotkeyConfig {
+    state.0.lock().unwrap().clone()
+}
+
+/// Rebind the global shortcuts; fields left out keep their current binding
+#[tauri::command]
+fn set_hotkeys(
+    app: tauri::AppHandle,
+    clickthrough: Option<String>,
+    always_on_top: Option<String>,
+) -> Result<HotkeyConfig, String> {
+    for accelerator in [&clickthrough, &always_on_top].into_iter().flatten() {
+        if !accelerator.is_empty() {
+            accelerator
+                .parse::<Shortcut>()
+                .map_err(|e| format!("Invalid shortcut \"{}\": {}", accelerator, e))?;
+        }
+    }
+
+    let hotkeys = {
+        let state = app.state::<Hotkeys>();
+        let mut hotkeys = state.0.lock().unwrap();
+        if let Some(clickthrough) = clickthrough {
+            hotkeys.clickthrough = clickthrough;
+        }
+        if let Some(always_on_top) = always_on_top {
+            hotkeys.always_on_top = always_on_top;
+        }
+        hotkeys.clone()
+    };
+
+    register_hotkeys(&app);
+    save_window_settings(&app);
+    Ok(hotkeys)
+}
+
+/// (Re)register the configured shortcuts. Conflicts with other apps are only logged.
+fn register_hotkeys(app: &tauri::AppHandle) {
+    let global_shortcut = app.global_shortcut();
+    if let Err(e) = global_shortcut.unregister_all() {
+        warn!("Failed to unregister global shortcuts: {}", e);
+    }
+
+    let hotkeys = app.state::<Hotkeys>().0.lock().unwrap().clone();
+    let bindings = [
+        ("clickthrough", &hotkeys.clickthrough),
+        ("always on top", &hotkeys.always_on_top),
+    ];
+    for (action, accelerator) in bindings {
+        if accelerator.is_empty() {
+            continue;
+        }
+        match global_shortcut.register(accelerator.as_str()) {
+            Ok(()) => info!("Registered {} shortcut: {}", action, accelerator),
+            Err(e) => warn!("Failed to register {} shortcut {}: {}", action, accelerator, e),
+        }
+    }
+}
+
+fn handle_hotkey(app: &tauri::AppHandle, shortcut: &Shortcut) {
+    let hotkeys = app.state::<Hotkeys>().0.lock().unwrap().clone();
+    let matches = |accelerator: &str| {
+        accelerator
+            .parse::<Shortcut>()
+            .is_ok_and(|bound| &bound == shortcut)
+    };
+
+    if matches(&hotkeys.clickthrough) {
+        toggle_clickthrough_state(app);
+    } else if matches(&hotkeys.always_on_top) {
+        toggle_always_on_top_state(app);
+    }
+}
+
+/// Set the meter window opacity (clamped to 0.2-1.0) and return the applied value.
+/// The window is transparent, so the frontend applies it on `on-opacity`.
+#[tauri::command]
+fn set_opacity(app: tauri::AppHandle, opacity: f64) -> f64 {
+    apply_opacity(&app, opacity)
+}
+
+#[tauri::command]
+fn get_opacity(state: State<Opacity>) -> f64 {
+    *state.0.lock().unwrap()
+}
+
+fn apply_opacity(app: &tauri::AppHandle, opacity: f64) -> f64 {
+    let opacity = if opacity.is_finite() {
+        opacity.clamp(MIN_OPACITY, MAX_OPACITY)
+    } else {
+        MAX_OPACITY
+    };
+    *app.state::<Opacity>().0.lock().unwrap() = opacity;
+
+    if let Some(meter_window) = app.get_webview_window(METER_WINDOW_LABEL) {
+        let _ = meter_window.emit("on-opacity", opacity);
+    }
+
+    save_window_settings(app);
+
+    info!("Opacity set to: {}", opacity);
+    opacity
+}
+
+fn load_window_settings(app: &tauri::AppHandle) -> WindowSettings {
+    let path = app::path::data_dir(app).join(WINDOW_SETTINGS_FILE);
+    let mut settings = std::fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<WindowSettings>(&content).ok())
+        .unwrap_or_else(|| WindowSettings {
+            opacity: default_opacity(),
+            hotkeys: HotkeyConfig::default(),
+        });
+    settings.opacity = settings.opacity.clamp(MIN_OPACITY, MAX_OPACITY);
+    settings
+}
+
+fn save_window_settings(app: &tauri::AppHandle) {
+    let settings = WindowSettings {
+        opacity: *app.state::<Opacity>().0.lock().unwrap(),
+        hotkeys: app.state::<Hotkeys>().0.lock().unwrap().clone(),
+    };
+    let path = app::path::data_dir(app).join(WINDOW_SETTINGS_FILE);
+    match serde_json::to_string_pretty(&settings) {
+        Ok(content) => {
+            let written = path
+                .parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|_| std::fs::write(&path, content));
+            if let Err(e) = written {
+                warn!("Failed to save window settings to {}: {}", path.display(), e);
+            }
+        }
+        Err(e) => warn!("Failed to serialize window settings: {}", e),
+    }
+}
+
 fn setup_tray(app: &tauri::App) {
     // Setup system tray menu for the tray icon configured in tauri.conf.json
     let menu = create_tray_menu(&app.handle());
@@ -200,50 +419,14 @@ fn setup_tray(app: &tauri::App) {
                         let _ = window.set_focus();
                     }
                 }
-                "always_on_top" => {
-                    // Toggle always on top state
-                    let always_on_top_state = app.state::<AlwaysOnTop>();
-                    let new_state = !always_on_top_state.0.load(Ordering::Acquire);
-                    always_on_top_state.0.store(new_state, Ordering::Release);
-
-                    // Update main window
-                    if let Some(window) = app.get_webview_window(METER_WINDOW_LABEL) {
-                        let _ = window.set_always_on_top(new_state);
-                        let _ = window.emit("on-pinned", new_state);
-                    }
-
-                    // Update tray menu
-                    let updated_menu = create_tray_menu(app);
-                    if let Some(tray) = app.tray_by_id("main") {
-                        let _ = tray.set_menu(Some(updated_menu));
-                    }
-
-                    info!("Always on top toggled to: {}", new_state);
-                }
-                "toggle_clickthrough" => {
-                    // Toggle clickthrough state
-                    let clickthrough_state = app.state::<ClickThrough>();
-                    let new_state = !clickthrough_state.0.load(Ordering::Acquire);
-                    clickthrough_state.0.store(new_state, Ordering::Release);
-
-                    // Update main window
-                    if let Some(meter_window) = app.get_webview_window(METER_WINDOW_LABEL) {
-                        meter_window.set_ignore_cursor_events(new_state).unwrap();
-                        let _ = meter_window.emit("on-clickthrough", new_state);
-                    }
-
-                    // Update tray menu
-                    let updated_menu = create_tray_menu(app);
-                    if let Some(tray) = app.tray_by_id("main") {
-                        let _ = tray.set_menu(Some(updated_menu));
+                "always_on_top" => toggle_always_on_top_state(app),
+                "toggle_clickthrough" => toggle_clickthrough_state(app),
+                id if id.starts_with("opacity_") => {
+                    if let Ok(percent) = id["opacity_".len()..].parse::<u32>() {
+                        apply_opacity(app, percent as f64 / 100.0);
                     }
-
-                    info!("Clickthrough toggled to: {}", new_state);
-                }
-                "reset_windows" => {
-                    // Reset window positions/sizes
-                    info!("Windows reset");
                 }
+                "reset_windows" => reset_windows(app),
                 "quit" => {
                     info!("Quit requested from system tray, initiating cleanup...");
                     let app_handle = app.clone();
@@ -259,6 +442,44 @@ fn setup_tray(app: &tauri::App) {
     }
 }
 
+/// Restore the meter and logs windows to their configured size, centered on the
+/// primary monitor's work area, and overwrite the saved window state
+fn reset_windows(app: &tauri::AppHandle) {
+    for label in [METER_WINDOW_LABEL, LOGS_WINDOW_LABEL] {
+        let Some(window) = app.get_webview_window(label) else {
+            continue;
+        };
+
+        let _ = window.set_fullscreen(false);
+        let _ = window.unmaximize();
+        if let Some(config) = app.config().app.windows.iter().find(|config| config.label == label) {
+            let _ = window.set_size(tauri::LogicalSize::new(config.width, config.height));
+        }
+
+        match (window.primary_monitor(), window.outer_size()) {
+            (Ok(Some(monitor)), Ok(size)) => {
+                let area = monitor.work_area();
+                let x = area.position.x + (area.size.width as i32 - size.width as i32).max(0) / 2;
+                let y = area.position.y + (area.size.height as i32 - size.height as i32).max(0) / 2;
+                let _ = window.set_position(tauri::PhysicalPosition::new(x, y));
+            }
+            _ => {
+                let _ = window.center();
+            }
+        }
+
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+
+    // Replace the saved state so the off-screen geometry isn't restored on the next launch
+    if let Err(e) = app.save_window_state(WINDOW_STATE_FLAGS) {
+        warn!("Failed to save window state after reset: {}", e);
+    }
+
+    info!("Windows reset");
+}
+
 fn create_tray_menu(app: &tauri::AppHandle) -> tauri::menu::Menu<tauri::Wry> {
     let always_on_top_state = app.state::<AlwaysOnTop>();
     let always_on_top_text = if always_on_top_state.0.load(Ordering::Acquire) {
@@ -274,11 +495,21 @@ fn create_tray_menu(app: &tauri::AppHandle) -> tauri::menu::Menu<tauri::Wry> {
         "Clickthrough"
     };
 
+    let opacity_menu = SubmenuBuilder::new(app, "Opacity")
+        .text("opacity_100", "100%")
+        .text("opacity_80", "80%")
+        .text("opacity_60", "60%")
+        .text("opacity_40", "40%")
+        .text("opacity_20", "20%")
+        .build()
+        .unwrap();
+
     MenuBuilder::new(app)
         .text("open_meter", "Open Meter")
         .text("open_logs", "Open Logs")
         .text("always_on_top", always_on_top_text)
         .text("toggle_clickthrough", clickthrough_text)
+        .item(&opacity_menu)
         .text("reset_windows", "Reset Windows")
         .separator()
         .text("quit", "Quit")
@@ -3,6 +3,9 @@ import clsx from 'clsx';
 import { FC, useEffect, useLayoutEffect, useState } from 'react';
 import { useNavigation } from 'react-router';
 import { useTranslate } from '@tolgee/react';
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+import { getCurrentWebviewWindow } from '@tauri-apps/api/webviewWindow';
 import Button from '@/components/Button';
 import RouterLoading from '@/components/Loading/Router';
 import component from '@/hoc/component';
@@ -39,6 +42,25 @@ const AppLayout: FC<IProps> = ({
     return () => {};
   }, [isMobile]);
 
+  // 悬浮窗透明度，由托盘菜单或 set_opacity 命令设置
+  useEffect(() => {
+    if (getCurrentWebviewWindow().label !== 'main') return;
+
+    const applyOpacity = (opacity: number) => {
+      document.documentElement.style.opacity = String(opacity);
+    };
+    invoke<number>('get_opacity')
+      .then(applyOpacity)
+      .catch((e) => logger.error('get_opacity', e));
+    const unlisten = listen<number>('on-opacity', (event) =>
+      applyOpacity(event.payload),
+    );
+
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
   return (
     <div
       className={clsx(css.appLayout, 'bg-base-100/30 hover:bg-base-100/80 transition-colors')}